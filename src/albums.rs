@@ -0,0 +1,138 @@
+use std::{collections::HashMap, time::Duration};
+
+use ratatui::widgets::TableState;
+
+use crate::ui::Music;
+
+/// Musics sharing the same album and album artist
+#[derive(Debug, Clone, PartialEq)]
+pub struct Album {
+    pub title: String,
+    pub artist: String,
+    /// year of the earliest tagged music of the album
+    pub year: Option<u32>,
+    /// musics in disc and track order
+    pub musics: Vec<Music>,
+}
+
+impl Album {
+    /// Total duration of the album
+    pub fn length(&self) -> Duration {
+        self.musics.iter().map(|m| m.length).sum()
+    }
+
+    /// Groups _musics_ into albums, sorted by artist, year then title
+    pub fn group(musics: &[Music]) -> Vec<Album> {
+        let mut albums: HashMap<(String, String), Album> = HashMap::new();
+        for music in musics {
+            // tags are not always consistent in their case
+            let key = (music.album_artist.to_lowercase(), music.album.to_lowercase());
+            let album = albums.entry(key).or_insert_with(|| Album {
+                title: music.album.to_owned(),
+                artist: music.album_artist.to_owned(),
+                year: None,
+                musics: vec![],
+            });
+            album.year = match (album.year, music.year) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            album.musics.push(music.clone());
+        }
+        let mut albums = albums.into_values().collect::<Vec<Album>>();
+        for album in albums.iter_mut() {
+            album.musics.sort_by(|a, b| a.track_order(b));
+        }
+        albums.sort_by(|a, b| {
+            a.artist.to_lowercase().cmp(&b.artist.to_lowercase())
+                .then_with(|| a.year.cmp(&b.year))
+                .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+        });
+        albums
+    }
+}
+
+/// Stores the state of the album browser
+#[derive(Debug, Default)]
+pub struct Albums {
+    pub albums: Vec<Album>,
+    /// index of the *selected* album
+    pub selected: usize,
+    pub state: TableState,
+}
+
+impl Albums {
+    /// Regroups the albums out of _musics_, keeping the selection on the same album
+    pub fn refresh(&mut self, musics: &[Music]) {
+        let selected = self.selected_album().map(|a| (a.artist.to_owned(), a.title.to_owned()));
+        self.albums = Album::group(musics);
+        self.selected = selected
+            .and_then(|(artist, title)| self.albums.iter().position(|a| a.artist == artist && a.title == title))
+            .unwrap_or(0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.albums.len()
+    }
+
+    /// The *selected* [Album]
+    pub fn selected_album(&self) -> Option<&Album> {
+        self.albums.get(self.selected)
+    }
+
+    /// Selects the upper album (goes up by 1)
+    pub fn select_up(&mut self) {
+        if self.albums.is_empty() {
+            return
+        }
+        if self.selected == 0 {
+            self.selected = self.albums.len() - 1;
+        }else {
+            self.selected -= 1;
+        }
+    }
+
+    /// Selects the next album (goes down by 1)
+    pub fn select_down(&mut self) {
+        if self.albums.is_empty() {
+            return
+        }
+        if self.selected + 1 >= self.albums.len() {
+            self.selected = 0;
+        }else {
+            self.selected += 1;
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_group() {
+        let albums = Album::group(&[
+            Music { album: String::from("Second"), album_artist: String::from("Artist"), disc: Some(2), track: Some(1), ..Music::test("b") },
+            Music { album: String::from("Second"), album_artist: String::from("Artist"), ..Music::test("c") },
+            Music { album: String::from("Second"), album_artist: String::from("artist"), disc: Some(1), track: Some(2), ..Music::test("a") },
+            Music { album: String::from("First"), album_artist: String::from("Another"), track: Some(1), ..Music::test("x") },
+        ]);
+        assert_eq!(albums.len(), 2);
+        assert_eq!(albums[0].title, "First");
+        let titles = albums[1].musics.iter().map(|m| m.title.as_str()).collect::<Vec<&str>>();
+        assert_eq!(titles, vec!["a", "c", "b"]);
+        assert_eq!(albums[1].length(), Duration::from_secs(180));
+    }
+
+    #[test]
+    fn test_refresh_keeps_selection() {
+        let mut albums = Albums::default();
+        let music = |title: &str, album: &str| Music { album: album.to_string(), ..Music::test(title) };
+        albums.refresh(&[music("a", "A"), music("b", "B")]);
+        albums.select_down();
+        albums.refresh(&[music("0", "0"), music("b", "B")]);
+        assert_eq!(albums.selected_album().unwrap().title, "B");
+    }
+}
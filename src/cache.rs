@@ -0,0 +1,293 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use async_std::channel::{self, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::{ui::Music, utils, Config};
+
+/// bumped whenever the layout of the cached [Music] changes,
+/// so outdated caches get rebuilt instead of failing to load
+const CACHE_VERSION: u32 = 5;
+
+/// Changes to the music library, sent from background tasks to the UI
+#[derive(Debug)]
+pub enum LibraryEvent {
+    /// a new or changed [Music], boxed to keep the events small
+    Insert(Box<Music>),
+    /// a [Music] that does not exist anymore
+    Remove(PathBuf),
+    /// the library is up to date with the disk
+    Done,
+}
+
+/// Library changes gathered from several [LibraryEvent]s, so they can be
+/// applied to a music list in one pass
+#[derive(Debug, Default)]
+pub struct LibraryChanges {
+    pub inserted: HashMap<PathBuf, Music>,
+    pub removed: HashSet<PathBuf>,
+}
+
+impl LibraryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.removed.is_empty()
+    }
+
+    /// Records a [LibraryEvent], later events win over earlier ones
+    pub fn push(&mut self, event: LibraryEvent) {
+        match event {
+            LibraryEvent::Insert(music) => {
+                self.removed.remove(&music.path);
+                self.inserted.insert(music.path.clone(), *music);
+            },
+            LibraryEvent::Remove(path) => {
+                self.inserted.remove(&path);
+                self.removed.insert(path);
+            },
+            LibraryEvent::Done => {},
+        }
+    }
+
+    /// Whether the music at _path_ was removed, either by itself or along
+    /// with one of its parent directories
+    pub fn removes(&self, path: &Path) -> bool {
+        !self.inserted.contains_key(path)
+            && path.ancestors().any(|p| self.removed.contains(p))
+    }
+
+    /// Drops the removed musics and updates the changed ones in place,
+    /// returns the paths of the updated musics
+    pub fn update(&self, musics: &mut Vec<Music>) -> HashSet<PathBuf> {
+        musics.retain(|m| !self.removes(&m.path));
+        let mut seen = HashSet::new();
+        for music in musics.iter_mut() {
+            if let Some(updated) = self.inserted.get(&music.path) {
+                *music = updated.clone();
+                seen.insert(music.path.clone());
+            }
+        }
+        seen
+    }
+
+    /// Applies the changes to _musics_, updated musics keep their place
+    /// and new ones are appended
+    pub fn apply(&self, musics: &mut Vec<Music>) {
+        let seen = self.update(musics);
+        let new = self.inserted.values()
+            .filter(|m| !seen.contains(&m.path))
+            .cloned()
+            .collect::<Vec<Music>>();
+        musics.extend(new);
+    }
+}
+
+/// A cached [Music] along with what is needed to tell whether it is stale
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    /// modification time of the file since the unix epoch
+    pub modified: Duration,
+    /// size of the file in bytes
+    pub size: u64,
+    pub music: Music,
+}
+
+impl CacheEntry {
+    /// Whether the entry still describes the file with the given metadata
+    fn is_fresh(&self, metadata: &Metadata) -> bool {
+        self.size == metadata.len() && self.modified == CacheEntry::modified(metadata)
+    }
+
+    fn modified(metadata: &Metadata) -> Duration {
+        metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// On disk index of the music library, keyed by path
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LibraryCache {
+    version: u32,
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+impl LibraryCache {
+    /// Default location of the cache, `$XDG_CACHE_HOME/mplayer-client/library.json`
+    pub fn default_path() -> Option<PathBuf> {
+        utils::xdg_dir("XDG_CACHE_HOME", ".cache").map(|dir| dir.join("library.json"))
+    }
+
+    /// Reads the cache at _path_, an empty cache is returned if it is
+    /// missing, corrupted or outdated
+    pub fn load(path: &Path) -> Self {
+        let cache = std::fs::read(path).ok()
+            .and_then(|content| serde_json::from_slice::<LibraryCache>(&content).ok());
+        match cache {
+            Some(cache) if cache.version == CACHE_VERSION => cache,
+            _ => LibraryCache::default(),
+        }
+    }
+
+    /// Writes the cache to _path_
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        self.version = CACHE_VERSION;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // writes to a temporary file first so a crash never leaves a half written cache
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Every cached [Music]
+    pub fn musics(&self) -> Vec<Music> {
+        self.entries.values().map(|e| e.music.clone()).collect()
+    }
+
+    /// Inserts or updates the entry of the file at _path_,
+    /// returns the [Music] if the file could be probed
+    pub fn insert(&mut self, path: &Path, metadata: &Metadata) -> Option<Music> {
+        let music = Music::simple_new(path.to_path_buf())?;
+        self.entries.insert(path.to_path_buf(), CacheEntry {
+            modified: CacheEntry::modified(metadata),
+            size: metadata.len(),
+            music: music.clone(),
+        });
+        Some(music)
+    }
+
+    /// Removes the entry of the file at _path_
+    pub fn remove(&mut self, path: &Path) -> Option<CacheEntry> {
+        self.entries.remove(path)
+    }
+
+    /// Brings the cache up to date with the music _files_, sending every
+    /// change to _sender_
+    pub fn refresh(&mut self, files: Vec<PathBuf>, sender: &Sender<LibraryEvent>) {
+        let mut seen = HashSet::with_capacity(files.len());
+        for file in files {
+            let metadata = match std::fs::metadata(&file) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            seen.insert(file.clone());
+            if self.entries.get(&file).is_some_and(|e| e.is_fresh(&metadata)) {
+                continue
+            }
+            match self.insert(&file, &metadata) {
+                Some(music) => {
+                    let _ = sender.send_blocking(LibraryEvent::Insert(Box::new(music)));
+                },
+                // the file changed into something that is not a music
+                None => {
+                    if self.remove(&file).is_some() {
+                        let _ = sender.send_blocking(LibraryEvent::Remove(file));
+                    }
+                },
+            }
+        }
+        let gone = self.entries.keys()
+            .filter(|path| !seen.contains(*path))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        for path in gone {
+            self.remove(&path);
+            let _ = sender.send_blocking(LibraryEvent::Remove(path));
+        }
+    }
+
+    /// Refreshes the cache in a background task, changes are streamed
+    /// through _sender_
+    pub fn spawn_refresh(mut self, config: Config, path: Option<PathBuf>, sender: Sender<LibraryEvent>) {
+        std::thread::spawn(move || {
+            self.refresh(config.music_files(), &sender);
+            if let Some(path) = path {
+                let _ = self.save(&path);
+            }
+            let _ = sender.send_blocking(LibraryEvent::Done);
+        });
+    }
+}
+
+/// Channel carrying [LibraryEvent]s to the UI
+pub fn library_channel() -> (Sender<LibraryEvent>, Receiver<LibraryEvent>) {
+    channel::unbounded()
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("mplayer-client-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("library.json");
+        let music_path = dir.join("music.mp3");
+        std::fs::write(&music_path, b"not really a music").unwrap();
+        let metadata = std::fs::metadata(&music_path).unwrap();
+
+        let mut cache = LibraryCache::default();
+        cache.entries.insert(music_path.clone(), CacheEntry {
+            modified: CacheEntry::modified(&metadata),
+            size: metadata.len(),
+            music: Music::new(
+                String::from("Title"), music_path.clone(), Duration::from_secs(60),
+                String::from("Artist"), String::from("Genre")
+            ),
+        });
+        cache.save(&path).unwrap();
+
+        let cache = LibraryCache::load(&path);
+        assert_eq!(cache.musics().len(), 1);
+        assert!(cache.entries.get(&music_path).unwrap().is_fresh(&metadata));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_refresh_removes_missing_files() {
+        let mut cache = LibraryCache::default();
+        let path = PathBuf::from("/does/not/exist.mp3");
+        cache.entries.insert(path.clone(), CacheEntry {
+            modified: Duration::ZERO,
+            size: 0,
+            music: Music::default(path.clone()),
+        });
+        let (sender, receiver) = library_channel();
+        cache.refresh(vec![path.clone()], &sender);
+        assert!(cache.entries.is_empty());
+        assert!(matches!(receiver.try_recv(), Ok(LibraryEvent::Remove(p)) if p == path));
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut musics = vec![Music::test("a"), Music::test("b"), Music::test("c")];
+        let mut changes = LibraryChanges::default();
+        let mut updated = Music::test("b");
+        updated.artist = String::from("Artist");
+        changes.push(LibraryEvent::Insert(Box::new(updated)));
+        changes.push(LibraryEvent::Insert(Box::new(Music::test("d"))));
+        changes.push(LibraryEvent::Remove(PathBuf::from("/music/a.mp3")));
+        changes.apply(&mut musics);
+        assert_eq!(musics.iter().map(|m| m.title.as_str()).collect::<Vec<&str>>(), vec!["b", "c", "d"]);
+        assert_eq!(musics[0].artist, "Artist");
+    }
+
+    #[test]
+    fn test_apply_removed_directory() {
+        let music = |path: &str| Music::default(PathBuf::from(path));
+        let mut musics = vec![music("/a/1.mp3"), music("/a/b/2.mp3"), music("/ab/3.mp3")];
+        let mut changes = LibraryChanges::default();
+        changes.push(LibraryEvent::Remove(PathBuf::from("/a")));
+        changes.push(LibraryEvent::Insert(Box::new(music("/a/b/2.mp3"))));
+        changes.apply(&mut musics);
+        assert_eq!(musics.iter().map(|m| m.path.to_str().unwrap()).collect::<Vec<&str>>(), vec!["/a/b/2.mp3", "/ab/3.mp3"]);
+    }
+}
@@ -0,0 +1,304 @@
+use std::cmp::Ordering;
+
+use ratatui::layout::{Alignment, Constraint};
+use serde::{Deserialize, Serialize};
+
+use crate::history::PlayCounts;
+use crate::query::Field;
+use crate::ui::{Music, UI};
+
+/// Size of a column or a pane in the config, either a number of cells or one of
+/// `"30%"`, `"fill:2"`, `"min:10"`, `"max:20"` and `"length:6"`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum SizeConfig {
+    Length(u16),
+    Spec(String),
+}
+
+impl SizeConfig {
+    pub fn constraint(&self) -> Option<Constraint> {
+        let spec = match self {
+            SizeConfig::Length(length) => return Some(Constraint::Length(*length)),
+            SizeConfig::Spec(spec) => spec.trim(),
+        };
+        if let Some(percentage) = spec.strip_suffix('%') {
+            return percentage.trim().parse().ok().filter(|p| *p <= 100).map(Constraint::Percentage)
+        }
+        let (kind, value) = spec.split_once(':').unwrap_or(("length", spec));
+        let value = value.trim().parse::<u16>().ok()?;
+        match kind.trim() {
+            "length" => Some(Constraint::Length(value)),
+            "fill" => Some(Constraint::Fill(value)),
+            "min" => Some(Constraint::Min(value)),
+            "max" => Some(Constraint::Max(value)),
+            _ => None,
+        }
+    }
+}
+
+/// A column of the music list as written in the config
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ColumnConfig {
+    /// e.g. `title`, `file_name` or `bitrate`
+    pub field: String,
+    pub width: Option<SizeConfig>,
+    /// `left`, `center` or `right`
+    pub align: Option<String>,
+    /// label of the column, the name of the field if unset
+    pub header: Option<String>,
+}
+
+/// What a column of the music list shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnField {
+    /// position of the music in the displayed list, as used by `12G`
+    Index,
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Composer,
+    Genre,
+    Year,
+    Track,
+    Disc,
+    Duration,
+    Path,
+    FileName,
+    Bitrate,
+    /// times the music was played, from the history
+    Plays,
+}
+
+impl ColumnField {
+    const ALL: [(ColumnField, &'static str); 15] = [
+        (ColumnField::Index, "index"),
+        (ColumnField::Title, "title"),
+        (ColumnField::Artist, "artist"),
+        (ColumnField::Album, "album"),
+        (ColumnField::AlbumArtist, "album_artist"),
+        (ColumnField::Composer, "composer"),
+        (ColumnField::Genre, "genre"),
+        (ColumnField::Year, "year"),
+        (ColumnField::Track, "track"),
+        (ColumnField::Disc, "disc"),
+        (ColumnField::Duration, "duration"),
+        (ColumnField::Path, "path"),
+        (ColumnField::FileName, "file_name"),
+        (ColumnField::Bitrate, "bitrate"),
+        (ColumnField::Plays, "plays"),
+    ];
+
+    fn parse(name: &str) -> Option<ColumnField> {
+        let name = name.to_lowercase().replace('-', "_");
+        ColumnField::ALL.iter().find(|(_, n)| *n == name).map(|(f, _)| *f)
+    }
+
+    fn header(&self) -> &'static str {
+        match self {
+            ColumnField::Index => "#",
+            ColumnField::Title => "Title",
+            ColumnField::AlbumArtist => "album artist",
+            ColumnField::FileName => "file name",
+            field => ColumnField::ALL.iter().find(|(f, _)| f == field).map(|(_, n)| *n).unwrap_or_default(),
+        }
+    }
+
+    fn width(&self) -> Constraint {
+        match self {
+            ColumnField::Title | ColumnField::Path => Constraint::Fill(4),
+            ColumnField::Artist | ColumnField::Album | ColumnField::AlbumArtist
+                | ColumnField::Composer | ColumnField::FileName => Constraint::Fill(2),
+            ColumnField::Index | ColumnField::Year | ColumnField::Track
+                | ColumnField::Disc | ColumnField::Plays => Constraint::Length(5),
+            ColumnField::Bitrate => Constraint::Length(9),
+            ColumnField::Genre | ColumnField::Duration => Constraint::Fill(1),
+        }
+    }
+
+    fn alignment(&self) -> Alignment {
+        match self {
+            ColumnField::Index | ColumnField::Year | ColumnField::Track
+                | ColumnField::Disc | ColumnField::Bitrate | ColumnField::Plays => Alignment::Right,
+            _ => Alignment::Left,
+        }
+    }
+
+    /// Field of the search query the column highlights the matches of
+    pub fn query_field(&self) -> Option<Field> {
+        match self {
+            ColumnField::Title => Some(Field::Title),
+            ColumnField::Artist => Some(Field::Artist),
+            ColumnField::Album => Some(Field::Album),
+            ColumnField::AlbumArtist => Some(Field::AlbumArtist),
+            ColumnField::Composer => Some(Field::Composer),
+            ColumnField::Genre => Some(Field::Genre),
+            ColumnField::Path => Some(Field::Path),
+            _ => None,
+        }
+    }
+
+    /// Text of the column for _music_, displayed at _index_ of the list,
+    /// _plays_ giving the play counts
+    pub fn text(&self, music: &Music, index: usize, plays: &PlayCounts) -> String {
+        let number = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+        match self {
+            ColumnField::Index => (index + 1).to_string(),
+            ColumnField::Title => music.title.to_owned(),
+            ColumnField::Artist => music.artist.to_owned(),
+            ColumnField::Album => music.album.to_owned(),
+            ColumnField::AlbumArtist => music.album_artist.to_owned(),
+            ColumnField::Composer => music.composer.to_owned(),
+            ColumnField::Genre => music.genre.to_owned(),
+            ColumnField::Year => number(music.year),
+            ColumnField::Track => number(music.track),
+            ColumnField::Disc => number(music.disc),
+            ColumnField::Duration => UI::duration_to_string(music.length.as_secs()),
+            ColumnField::Path => music.path.to_string_lossy().to_string(),
+            ColumnField::FileName => music.path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            ColumnField::Bitrate => music.bitrate.map(|b| format!("{} kbps", b)).unwrap_or_default(),
+            ColumnField::Plays => plays.get(&music.path).copied().unwrap_or(0).to_string(),
+        }
+    }
+
+    /// Orders musics by the column, the index having no order of its own
+    pub fn order(&self, a: &Music, b: &Music, plays: &PlayCounts) -> Ordering {
+        let text = |m: &Music| self.text(m, 0, plays).to_lowercase();
+        let count = |m: &Music| plays.get(&m.path).copied().unwrap_or(0);
+        match self {
+            ColumnField::Index => Ordering::Equal,
+            ColumnField::Year => a.year.cmp(&b.year),
+            ColumnField::Track => a.track_order(b),
+            ColumnField::Disc => a.disc.cmp(&b.disc),
+            ColumnField::Duration => a.length.cmp(&b.length),
+            ColumnField::Bitrate => a.bitrate.cmp(&b.bitrate),
+            ColumnField::Plays => count(a).cmp(&count(b)),
+            _ => text(a).cmp(&text(b)),
+        }
+    }
+}
+
+/// A column of the music list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub field: ColumnField,
+    pub width: Constraint,
+    pub alignment: Alignment,
+    pub header: String,
+}
+
+impl From<ColumnField> for Column {
+    fn from(value: ColumnField) -> Self {
+        Column {
+            field: value,
+            width: value.width(),
+            alignment: value.alignment(),
+            header: value.header().to_string(),
+        }
+    }
+}
+
+impl Column {
+    /// Title, artist and duration, after the index if _index_ is true
+    pub fn defaults(index: bool) -> Vec<Column> {
+        let mut columns = vec![ColumnField::Title, ColumnField::Artist, ColumnField::Duration];
+        if index {
+            columns.insert(0, ColumnField::Index);
+        }
+        columns.into_iter().map(Column::from).collect()
+    }
+
+    fn new(config: &ColumnConfig) -> Result<Column, String> {
+        let at = format!("column {:?}", config.field);
+        let field = ColumnField::parse(&config.field).ok_or_else(|| format!("{}: unknown field", at))?;
+        let mut column = Column::from(field);
+        if let Some(width) = &config.width {
+            column.width = width.constraint().ok_or_else(|| format!("{}: invalid width {:?}", at, width))?;
+        }
+        if let Some(align) = &config.align {
+            column.alignment = match align.to_lowercase().as_str() {
+                "left" => Alignment::Left,
+                "center" => Alignment::Center,
+                "right" => Alignment::Right,
+                _ => return Err(format!("{}: invalid alignment {:?}", at, align)),
+            };
+        }
+        if let Some(header) = &config.header {
+            column.header = header.to_owned();
+        }
+        Ok(column)
+    }
+
+    /// Builds the columns of the `columns` config, the defaults if unset
+    pub fn from_config(config: Option<&[ColumnConfig]>, index: bool) -> Result<Vec<Column>, Vec<String>> {
+        let Some(config) = config else {
+            return Ok(Column::defaults(index))
+        };
+        let (columns, errors): (Vec<_>, Vec<_>) = config.iter().map(Column::new).partition(Result::is_ok);
+        if errors.is_empty() {
+            Ok(columns.into_iter().filter_map(Result::ok).collect())
+        } else {
+            Err(errors.into_iter().filter_map(Result::err).collect())
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::{path::PathBuf, time::Duration};
+
+    #[allow(dead_code)]
+    fn column(field: &str, width: Option<SizeConfig>, align: Option<&str>) -> ColumnConfig {
+        ColumnConfig { field: field.to_string(), width, align: align.map(String::from), header: None }
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert_eq!(Column::from_config(None, true).unwrap()[0].field, ColumnField::Index);
+        let columns = Column::from_config(Some(&[
+            column("file-name", Some(SizeConfig::Spec(String::from("30%"))), None),
+            column("bitrate", Some(SizeConfig::Length(8)), Some("center")),
+            ColumnConfig { header: Some(String::from("Kind")), ..column("genre", None, None) },
+            column("album_artist", Some(SizeConfig::Spec(String::from("min:10"))), None),
+        ]), false).unwrap();
+        assert_eq!(columns[0].width, Constraint::Percentage(30));
+        assert_eq!(columns[1].width, Constraint::Length(8));
+        assert_eq!(columns[1].alignment, Alignment::Center);
+        assert_eq!(columns[2].header, "Kind");
+        assert_eq!(columns[3].width, Constraint::Min(10));
+
+        let errors = Column::from_config(Some(&[
+            column("mood", None, None),
+            column("title", Some(SizeConfig::Spec(String::from("wide"))), None),
+            column("title", None, Some("justify")),
+        ]), false).unwrap_err();
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn test_text_and_order() {
+        let mut a = Music::new(
+            String::from("a"), PathBuf::from("/music/b.flac"), Duration::from_secs(61),
+            String::from("Artist"), String::from("House")
+        );
+        a.bitrate = Some(320);
+        let b = Music { bitrate: None, ..Music::new(
+            String::from("b"), PathBuf::from("/music/a.mp3"), Duration::from_secs(60),
+            String::from("Artist"), String::from("House")
+        ) };
+        let plays = PlayCounts::from([(PathBuf::from("/music/a.mp3"), 3)]);
+        assert_eq!(ColumnField::FileName.text(&a, 0, &plays), "b.flac");
+        assert_eq!(ColumnField::Bitrate.text(&a, 0, &plays), "320 kbps");
+        assert_eq!(ColumnField::Index.text(&a, 4, &plays), "5");
+        assert_eq!(ColumnField::FileName.order(&a, &b, &plays), Ordering::Greater);
+        assert_eq!(ColumnField::Duration.order(&a, &b, &plays), Ordering::Greater);
+        assert_eq!(ColumnField::Bitrate.order(&a, &b, &plays), Ordering::Greater);
+        assert_eq!(ColumnField::Plays.text(&b, 0, &plays), "3");
+        assert_eq!(ColumnField::Plays.order(&a, &b, &plays), Ordering::Less);
+    }
+}
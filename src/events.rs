@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use async_std::{channel::{self, Receiver, Sender}, stream::StreamExt, task};
+use crossterm::event::{Event, EventStream};
+
+use crate::cache::LibraryEvent;
+use crate::mpris::MprisCommand;
+use crate::supervisor::ServerStatus;
+
+/// Time between two renders while nothing else happens, keeping the
+/// seeker and the lyrics moving
+pub const TICK: Duration = Duration::from_millis(100);
+
+/// Everything the event loop of the client waits on
+#[derive(Debug)]
+pub enum AppEvent {
+    /// key presses, resizes and the like
+    Terminal(Event),
+    /// time to render again
+    Tick,
+    /// a new player state was fetched from the server
+    State,
+    /// a change of the music library
+    Library(LibraryEvent),
+    /// a request of an MPRIS client
+    Mpris(MprisCommand),
+    /// the server went offline or came back
+    Server(ServerStatus),
+}
+
+/// Channel carrying [AppEvent]s to the event loop
+pub fn app_channel() -> (Sender<AppEvent>, Receiver<AppEvent>) {
+    channel::unbounded()
+}
+
+/// Wait after failing to read the terminal, doubled on every error in a
+/// row up to [TERMINAL_RETRY_MAX]
+const TERMINAL_RETRY: Duration = Duration::from_millis(10);
+const TERMINAL_RETRY_MAX: Duration = Duration::from_secs(1);
+
+/// Sends the events of the terminal to _sender_
+pub fn spawn_terminal(sender: Sender<AppEvent>) {
+    task::spawn(async move {
+        let mut events = EventStream::new();
+        // a broken terminal keeps failing, it mustn't be polled in a loop
+        let mut backoff = Duration::ZERO;
+        while let Some(event) = events.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    if backoff.is_zero() {
+                        crate::utils::report(&format!("terminal: {}", error));
+                    }
+                    backoff = (backoff * 2).clamp(TERMINAL_RETRY, TERMINAL_RETRY_MAX);
+                    task::sleep(backoff).await;
+                    continue
+                },
+            };
+            backoff = Duration::ZERO;
+            if sender.send(AppEvent::Terminal(event)).await.is_err() {
+                return
+            }
+        }
+    });
+}
+
+/// Sends an [AppEvent::Tick] to _sender_ every _interval_
+pub fn spawn_ticks(sender: Sender<AppEvent>, interval: Duration) {
+    task::spawn(async move {
+        loop {
+            task::sleep(interval).await;
+            if sender.send(AppEvent::Tick).await.is_err() {
+                return
+            }
+        }
+    });
+}
+
+/// Sends what _receiver_ receives to _sender_, as the [AppEvent] _wrap_ makes of it
+pub fn forward<T: Send + 'static>(receiver: Receiver<T>, sender: Sender<AppEvent>, wrap: fn(T) -> AppEvent) {
+    task::spawn(async move {
+        while let Ok(value) = receiver.recv().await {
+            if sender.send(wrap(value)).await.is_err() {
+                return
+            }
+        }
+    });
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use async_std::task::block_on;
+
+    #[test]
+    fn test_forward() {
+        let (sender, events) = app_channel();
+        let (library, receiver) = channel::unbounded();
+        forward(receiver, sender.clone(), AppEvent::Library);
+        spawn_ticks(sender, Duration::from_millis(5));
+        block_on(async {
+            library.send(LibraryEvent::Done).await.unwrap();
+            let mut seen = (false, false);
+            while seen != (true, true) {
+                match events.recv().await.unwrap() {
+                    AppEvent::Library(LibraryEvent::Done) => seen.0 = true,
+                    AppEvent::Tick => seen.1 = true,
+                    event => panic!("unexpected {:?}", event),
+                }
+            }
+        });
+    }
+}
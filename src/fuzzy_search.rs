@@ -1,198 +1,199 @@
-// goal:
-// make it possible to take a vector of the Music struct, preform a fuzzy finding 
-// based on the title criteria, then return a new vector of the Music struct
-use std::iter;
-use crate::ui::{Music, UI};
-
-fn trigrams(s: &str) -> Vec<(char, char, char)> {
-    let it_1 = iter::once(' ').chain(iter::once(' ')).chain(s.chars());
-    let it_2 = iter::once(' ').chain(s.chars());
-    let it_3 = s.chars().chain(iter::once(' '));
-
-    let res: Vec<(char, char, char)> = it_1
-        .zip(it_2)
-        .zip(it_3)
-        .map(|((a, b), c): ((char, char), char)| (a, b, c))
-        .collect();
-    res
-}
-
-pub fn fuzzy_compare(a: &str, b: &str) -> f32 {
-    let a_binding = a.to_lowercase();
-    let b_binding = b.to_lowercase();
-    let _a = a_binding.as_str();
-    let _b = b_binding.as_str();
-
-    // gets length of first input string plus 1 (because of the 3 added spaces (' '))
-    let string_len = _a.chars().count() + 1;
-
-    // gets the trigrams for both strings
-    let trigrams_a = trigrams(_a);
-    let trigrams_b = trigrams(_b);
-
-    // accumulator
-    let mut acc: f32 = 0.0f32;
-    // counts the number of trigrams of the first string that are also present in the second one
-    for t_a in &trigrams_a {
-        for t_b in &trigrams_b {
-            if t_a == t_b {
-                acc += 1.0f32;
-                break;
-            }
+// fzf like fuzzy matching: the characters of the pattern have to show up in
+// order in the text, the score favours matches at word boundaries and
+// consecutive characters, and penalizes the gaps in between
+
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+/// after a space, e.g. the `w` of `around the world`
+const BONUS_BOUNDARY_WHITE: i64 = 10;
+/// after a delimiter, e.g. the `b` of `a/b` or `a-b`
+const BONUS_BOUNDARY_DELIMITER: i64 = 9;
+/// after any other non word character, e.g. the `b` of `(b)`
+const BONUS_BOUNDARY: i64 = 8;
+/// camelCase and letter to number transitions
+const BONUS_CAMEL_123: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+/// the bonus of the first character of the pattern counts double
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    White,
+    Delimiter,
+    NonWord,
+    Lower,
+    Upper,
+    Number,
+}
+
+impl CharClass {
+    fn of(c: char) -> CharClass {
+        if c.is_whitespace() {
+            CharClass::White
+        } else if "/,:;|-_.".contains(c) {
+            CharClass::Delimiter
+        } else if c.is_lowercase() {
+            CharClass::Lower
+        } else if c.is_uppercase() {
+            CharClass::Upper
+        } else if c.is_numeric() {
+            CharClass::Number
+        } else if c.is_alphabetic() {
+            // letters without a case (e.g. CJK) behave like lowercase ones
+            CharClass::Lower
+        } else {
+            CharClass::NonWord
         }
     }
-    let res = acc / (string_len as f32);
-    // crops between zero and one
-    if (0.0f32..=1.0f32).contains(&res) {
-        res
-    } else {
-        0.0f32
-    }
-}
-
-pub fn fuzzy_compare_durations(a: &str, b: &str) -> f32 {
-    let a_binding = a.to_lowercase();
-    let b_binding = b.to_lowercase();
-    let _a = a_binding.as_str();
-    let _b = b_binding.as_str();
 
-    // gets length of first input string plus 1 (because of the 3 added spaces (' '))
-    let string_len = _a.chars().count() + 1;
-
-    // gets the trigrams for both strings
-    let trigrams_a = trigrams(_a);
-    let trigrams_b = trigrams(_b);
+    fn is_word(&self) -> bool {
+        matches!(self, CharClass::Lower | CharClass::Upper | CharClass::Number)
+    }
 
-    // accumulator
-    let mut acc: f32 = 0.0f32;
-    // counts the number of trigrams of the first string that are also present in the second one
-    for t_a in &trigrams_a {
-        for t_b in &trigrams_b {
-            if t_a == t_b {
-                acc += 1.0f32;
-                break;
-            }
+    /// Bonus of a character of class _self_ following one of class _prev_
+    fn bonus(&self, prev: CharClass) -> i64 {
+        if !self.is_word() {
+            return 0
+        }
+        match prev {
+            CharClass::White => BONUS_BOUNDARY_WHITE,
+            CharClass::Delimiter => BONUS_BOUNDARY_DELIMITER,
+            CharClass::NonWord => BONUS_BOUNDARY,
+            CharClass::Lower if *self == CharClass::Upper => BONUS_CAMEL_123,
+            CharClass::Lower | CharClass::Upper if *self == CharClass::Number => BONUS_CAMEL_123,
+            _ => 0,
         }
     }
-    let res = acc / (string_len as f32);
-    // crops between zero and one
-    if (0.0f32..=1.0f32).contains(&res) {
-        res
-    } else {
-        0.0f32
-    }
-}
-
-
-#[allow(dead_code)]
-pub fn fuzzy_search<'a, T: AsRef<str>>(s: &'a str, list: &'a [T]) -> Vec<(&'a str, f32)> {
-    list.iter()
-        .map(|value| {
-            let res = fuzzy_compare(s, value.as_ref());
-            (value.as_ref(), res)
-        })
-        .collect()
-}
-
-pub fn fuzzy_search_musics_by_title<'a>(s: &'a str, list: &'a Vec<Music>) -> Vec<(Music, f32)> {
-    list.iter()
-        .map(|value| {
-            let res = fuzzy_compare(s, value.title.as_ref());
-            (Music::unchecked_new(value.path.clone()), res)
-        })
-        .collect()
 }
 
-pub fn fuzzy_search_music_titles_sorted<'a>(s: &'a str, list: &'a Vec<Music>) -> Vec<(Music, f32)> {
-    let mut res = fuzzy_search_musics_by_title(s, list);
-    res.sort_by(|(_, d1), (_, d2)| d2.partial_cmp(d1).unwrap()); // TODO to fix the unwrap call
-    res
+/// A successful fuzzy match
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    /// the higher, the better
+    pub score: i64,
+    /// char indices of the text matched by the pattern
+    pub positions: Vec<usize>,
 }
 
-#[allow(dead_code)]
-pub fn fuzzy_search_sorted<'a, T: AsRef<str>>(s: &'a str, list: &'a [T]) -> Vec<(&'a str, f32)> {
-    let mut res = fuzzy_search(s, list);
-    res.sort_by(|(_, d1), (_, d2)| d2.partial_cmp(d1).unwrap()); // TODO to fix the unwrap call
-    res
+/// Whether _pattern_ should be matched case sensitively, which is the case
+/// only if it contains an uppercase character
+pub fn is_case_sensitive(pattern: &str) -> bool {
+    pattern.chars().any(char::is_uppercase)
 }
 
-#[allow(dead_code)]
-pub fn fuzzy_search_threshold<'a, T: AsRef<str>>(
-    s: &'a str,
-    list: &'a [T],
-    threshold: f32,
-) -> Vec<(&'a str, f32)> {
-    fuzzy_search(s, list)
-        .into_iter()
-        .filter(|&(_, score)| score >= threshold)
-        .collect()
-}
-
-pub fn fuzzy_search_music_titles_best_n<'a>(
-    s: &'a str,
-    list: &'a Vec<Music>,
-    n: usize,
-) -> Vec<(Music, f32)> {
-    fuzzy_search_music_titles_sorted(s, list).into_iter().take(n).collect()
-}
-
-#[allow(dead_code)]
-pub fn fuzzy_search_best_n<'a, T: AsRef<str>>(
-    s: &'a str,
-    list: &'a [T],
-    n: usize,
-) -> Vec<(&'a str, f32)> {
-    fuzzy_search_sorted(s, list).into_iter().take(n).collect()
+fn normalize(c: char, case_sensitive: bool) -> char {
+    if case_sensitive {
+        c
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
 }
 
-pub fn fuzzy_search_music_duration_best_n(
-    s: & str,
-    list: &Vec<Music>,
-    n: usize,
-) -> Vec<(Music, f32)> {
-    fuzzy_search_music_duration_sorted(s, list).into_iter().take(n).collect()
-}
+/// Matches _pattern_ against _text_, [None] if the characters of the pattern
+/// do not all show up, in order, in the text
+///
+/// the shortest window ending at the first full occurrence is scored, which
+/// is what fzf does for long lists, in O(len(text))
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let case_sensitive = is_case_sensitive(pattern);
+    let pattern = pattern.chars().map(|c| normalize(c, case_sensitive)).collect::<Vec<char>>();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch { score: 0, positions: vec![] })
+    }
+    let original = text.chars().collect::<Vec<char>>();
+    let text = original.iter().map(|c| normalize(*c, case_sensitive)).collect::<Vec<char>>();
+
+    // forward: end of the first occurrence of the pattern
+    let mut pattern_index = 0;
+    let mut end = None;
+    for (i, c) in text.iter().enumerate() {
+        if *c == pattern[pattern_index] {
+            pattern_index += 1;
+            if pattern_index == pattern.len() {
+                end = Some(i);
+                break
+            }
+        }
+    }
+    let end = end?;
+    // backward: latest start of an occurrence ending there
+    let mut pattern_index = pattern.len();
+    let mut start = end;
+    for i in (0..=end).rev() {
+        if text[i] == pattern[pattern_index - 1] {
+            pattern_index -= 1;
+            if pattern_index == 0 {
+                start = i;
+                break
+            }
+        }
+    }
 
-fn fuzzy_search_music_duration_sorted(s: &str, list: &Vec<Music>) -> Vec<(Music, f32)>  {
-    let mut res = fuzzy_search_musics_by_duration(s, list);
-    res.sort_by(|(_, d1), (_, d2)| d2.partial_cmp(d1).unwrap()); // TODO to fix the unwrap call
-    res
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut pattern_index = 0;
+    let mut in_gap = false;
+    let mut consecutive = 0;
+    let mut first_bonus = 0;
+    let mut prev_class = if start > 0 { CharClass::of(original[start - 1]) } else { CharClass::White };
+    for i in start..=end {
+        let class = CharClass::of(original[i]);
+        if pattern_index < pattern.len() && text[i] == pattern[pattern_index] {
+            score += SCORE_MATCH;
+            let mut bonus = class.bonus(prev_class);
+            if consecutive == 0 {
+                first_bonus = bonus;
+            } else {
+                // a boundary in the middle of a chunk starts a new one
+                if bonus >= BONUS_BOUNDARY && bonus > first_bonus {
+                    first_bonus = bonus;
+                }
+                bonus = bonus.max(first_bonus).max(BONUS_CONSECUTIVE);
+            }
+            score += if pattern_index == 0 { bonus * BONUS_FIRST_CHAR_MULTIPLIER } else { bonus };
+            positions.push(i);
+            in_gap = false;
+            consecutive += 1;
+            pattern_index += 1;
+        } else {
+            score += if in_gap { SCORE_GAP_EXTENSION } else { SCORE_GAP_START };
+            in_gap = true;
+            consecutive = 0;
+            first_bonus = 0;
+        }
+        prev_class = class;
+    }
+    Some(FuzzyMatch { score, positions })
 }
 
-fn fuzzy_search_musics_by_duration(s: &str, list: &Vec<Music>) -> Vec<(Music, f32)> {
-    list.iter()
-        .map(|value| {
-            let res = fuzzy_compare_durations(
-                s,
-                UI::duration_to_string(value.length.as_secs()).as_str()
-            );
-            (Music::unchecked_new(value.path.clone()), res)
-        })
-        .collect()
-}
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
 
-pub fn fuzzy_search_music_artist_best_n(
-    s: &str,
-    list: &Vec<Music>,
-    n: usize
-) -> Vec<(Music, f32)> {
-    fuzzy_search_artist_sorted(s, list).into_iter().take(n).collect()
-}
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("xyz", "around the world").is_none());
+        let m = fuzzy_match("atw", "Around The World").unwrap();
+        assert_eq!(m.positions, vec![0, 7, 11]);
+        assert_eq!(fuzzy_match("", "anything").unwrap().score, 0);
+    }
 
-fn fuzzy_search_artist_sorted(s: &str, list: &Vec<Music>) -> Vec<(Music, f32)> {
-    let mut res = fuzzy_search_musics_by_artist(s, list);
-    res.sort_by(|(_, d1), (_, d2)| d2.partial_cmp(d1).unwrap()); // TODO to fix the unwrap call
-    res
-}
+    #[test]
+    fn test_smart_case() {
+        assert!(fuzzy_match("world", "Around The World").is_some());
+        assert!(fuzzy_match("World", "around the world").is_none());
+        assert!(fuzzy_match("World", "Around The World").is_some());
+    }
 
-fn fuzzy_search_musics_by_artist(s: &str, list: &Vec<Music>) -> Vec<(Music, f32)> {
-    list.iter()
-        .map(|value| {
-            let res = fuzzy_compare(
-                s,
-                &value.artist,
-            );
-            (Music::unchecked_new(value.path.clone()), res) 
-        })
-        .collect()
+    #[test]
+    fn test_ranking() {
+        let score = |pattern: &str, text: &str| fuzzy_match(pattern, text).unwrap().score;
+        // consecutive characters beat scattered ones
+        assert!(score("abc", "abc def") > score("abc", "a b c"));
+        // word boundaries beat the middle of words
+        assert!(score("abc", "a big cat") > score("abc", "xaxbxcx"));
+        // the start of a word beats the middle of one
+        assert!(score("time", "One More Time") > score("time", "sometimes"));
+    }
 }
@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// A play shorter than this counts only if the music ended
+const MIN_COUNTED: Duration = Duration::from_secs(30);
+/// A music that played up to this close to its end is complete
+const END_MARGIN: Duration = Duration::from_secs(2);
+
+/// Times each music was played, by path
+pub type PlayCounts = HashMap<PathBuf, u32>;
+
+/// How a play ended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+    /// the music played to its end
+    Completed,
+    /// another music started before the end
+    Skipped,
+    /// the player stopped, or the client quit, before the end
+    Stopped,
+}
+
+/// A line of the history
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Play {
+    /// start of the play, in seconds since the unix epoch
+    pub time: i64,
+    pub path: PathBuf,
+    /// length of the music, in seconds
+    pub length: f64,
+    /// time spent playing, in seconds
+    pub played: f64,
+    pub ending: Ending,
+}
+
+impl Play {
+    /// Whether the play adds to the play count of the music
+    pub fn counts(&self) -> bool {
+        self.ending == Ending::Completed || self.played >= MIN_COUNTED.as_secs_f64()
+    }
+
+    pub fn local_time(&self) -> Option<DateTime<Local>> {
+        DateTime::from_timestamp(self.time, 0).map(|time| time.with_timezone(&Local))
+    }
+}
+
+/// The music playing right now, recorded as a [Play] once it stops
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listening {
+    pub path: PathBuf,
+    pub started: DateTime<Local>,
+    pub length: Duration,
+    /// furthest position reached
+    pub played: Duration,
+}
+
+impl Listening {
+    pub fn new(path: PathBuf, length: Duration, played: Duration) -> Self {
+        Listening { path, started: Local::now(), length, played }
+    }
+
+    /// Whether the music played to its end
+    pub fn completed(&self) -> bool {
+        !self.length.is_zero() && self.played + END_MARGIN >= self.length
+    }
+
+    /// The [Play] of the music, _ending_ being overridden if it completed
+    pub fn end(self, ending: Ending) -> Play {
+        let ending = if self.completed() { Ending::Completed } else { ending };
+        let played = if self.length.is_zero() { self.played } else { self.played.min(self.length) };
+        Play {
+            time: self.started.timestamp(),
+            length: self.length.as_secs_f64(),
+            played: played.as_secs_f64(),
+            path: self.path,
+            ending,
+        }
+    }
+}
+
+/// Every recorded play, kept one JSON object per line in
+/// `$XDG_DATA_HOME/mplayer-client/history.jsonl`
+#[derive(Debug, Default)]
+pub struct History {
+    plays: Vec<Play>,
+    /// where new plays are appended, kept in memory only if unset
+    path: Option<PathBuf>,
+    /// whether the history view is shown
+    pub open: bool,
+    /// index of the selected play in the view, from the newest one
+    pub selected: usize,
+}
+
+impl History {
+    /// Default location of the history, `$XDG_DATA_HOME/mplayer-client/history.jsonl`
+    pub fn default_path() -> Option<PathBuf> {
+        utils::xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join("history.jsonl"))
+    }
+
+    /// Reads the history at _path_, lines that can't be read are skipped
+    /// and a missing file is an empty history
+    pub fn load(path: &Path) -> Self {
+        let plays = std::fs::read_to_string(path).unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Play>(line).ok())
+            .collect();
+        History { plays, path: Some(path.to_path_buf()), ..Default::default() }
+    }
+
+    /// Adds _play_ to the history, appending it to the file
+    pub fn record(&mut self, play: Play) -> io::Result<()> {
+        let written = match &self.path {
+            Some(path) => utils::append_json(path, &play),
+            None => Ok(()),
+        };
+        self.plays.push(play);
+        // the view keeps showing the same play
+        if self.selected > 0 {
+            self.selected += 1;
+        }
+        written
+    }
+
+    pub fn play_counts(&self) -> PlayCounts {
+        let mut counts = PlayCounts::new();
+        for play in self.plays.iter().filter(|play| play.counts()) {
+            *counts.entry(play.path.clone()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Plays from the newest one
+    pub fn newest_first(&self) -> impl Iterator<Item = &Play> {
+        self.plays.iter().rev()
+    }
+
+    /// The play selected in the view
+    pub fn selected_play(&self) -> Option<&Play> {
+        self.newest_first().nth(self.selected)
+    }
+
+    pub fn len(&self) -> usize {
+        self.plays.len()
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.selected = 0;
+    }
+
+    pub fn select_down(&mut self) {
+        if self.selected + 1 < self.plays.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::io::Write;
+
+    #[allow(dead_code)]
+    fn listening(path: &str, length: u64, played: u64) -> Listening {
+        Listening::new(PathBuf::from(path), Duration::from_secs(length), Duration::from_secs(played))
+    }
+
+    #[test]
+    fn test_listening() {
+        assert_eq!(listening("a", 200, 199).end(Ending::Skipped).ending, Ending::Completed);
+        let skipped = listening("a", 200, 20).end(Ending::Skipped);
+        assert_eq!(skipped.ending, Ending::Skipped);
+        assert_eq!(skipped.played, 20.0);
+        assert!(!skipped.counts());
+        assert!(listening("a", 200, 45).end(Ending::Stopped).counts());
+        // a music of unknown length is never complete
+        assert_eq!(listening("a", 0, 0).end(Ending::Stopped).ending, Ending::Stopped);
+        assert!(skipped.local_time().is_some());
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = std::env::temp_dir().join(format!("mplayer-client-history-{}", std::process::id()));
+        let path = dir.join("history.jsonl");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut history = History::load(&path);
+        assert_eq!(history.len(), 0);
+        history.record(listening("a", 100, 100).end(Ending::Skipped)).unwrap();
+        history.record(listening("b", 100, 10).end(Ending::Skipped)).unwrap();
+        history.record(listening("a", 100, 50).end(Ending::Stopped)).unwrap();
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"not json\n").unwrap();
+        let history = History::load(&path);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.newest_first().next().unwrap().ending, Ending::Stopped);
+        let counts = history.play_counts();
+        assert_eq!(counts.get(Path::new("a")), Some(&2));
+        assert_eq!(counts.get(Path::new("b")), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,870 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Display};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// `[keybinds]` table of the config: context name -> key sequence -> command name
+pub type KeybindsConfig = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Command name used in the config to remove a default binding
+const UNBIND: &str = "none";
+/// counts are capped so a typo can't freeze the client
+const MAX_COUNT: usize = 9_999;
+
+/// Where a binding applies, a region along with the mode of the music list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// every context but `search`, looked up after the current one
+    Global,
+    List,
+    Search,
+    AfterSearch,
+    Albums,
+    Queue,
+    Playlists,
+    Lyrics,
+    Actions,
+    Seeker,
+    Volume,
+    /// the message history popup, whatever the region
+    Messages,
+    /// the play history popup, whatever the region
+    History,
+}
+
+impl Context {
+    const ALL: [Context; 13] = [
+        Context::Global, Context::List, Context::Search, Context::AfterSearch, Context::Albums,
+        Context::Queue, Context::Playlists, Context::Lyrics, Context::Actions, Context::Seeker,
+        Context::Volume, Context::Messages, Context::History,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::List => "list",
+            Context::Search => "search",
+            Context::AfterSearch => "after_search",
+            Context::Albums => "albums",
+            Context::Queue => "queue",
+            Context::Playlists => "playlists",
+            Context::Lyrics => "lyrics",
+            Context::Actions => "actions",
+            Context::Seeker => "seeker",
+            Context::Volume => "volume",
+            Context::Messages => "messages",
+            Context::History => "history",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Context> {
+        Context::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// Whether _command_ makes sense in the context
+    fn allows(&self, command: Command) -> bool {
+        match command.group() {
+            "app" | "player" | "region" => true,
+            "list" => match self {
+                Context::List => command != Command::ListBack,
+                Context::AfterSearch => matches!(command,
+                    Command::ListDown | Command::ListUp | Command::ListPlay | Command::ListEnqueue
+                    | Command::ListPlayNext | Command::ListBack),
+                Context::Albums => matches!(command,
+                    Command::ListDown | Command::ListUp | Command::ListPlay | Command::ListEnqueue
+                    | Command::ListPlayNext | Command::ListBack),
+                _ => false,
+            },
+            "search" => match self {
+                Context::Search => command != Command::SearchStart,
+                Context::List | Context::AfterSearch => command == Command::SearchStart,
+                _ => false,
+            },
+            "queue" => *self == Context::Queue,
+            "playlists" => *self == Context::Playlists,
+            "lyrics" => *self == Context::Lyrics,
+            "actions" => *self == Context::Actions,
+            "seek" => *self == Context::Seeker,
+            "volume" => *self == Context::Volume,
+            "messages" => *self == Context::Messages,
+            "history" => *self == Context::History,
+            _ => false,
+        }
+    }
+}
+
+/// Everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Quit,
+    /// runs the last command again
+    Repeat,
+    /// shows or hides the message history
+    Messages,
+    /// shows or hides the play history
+    History,
+    TogglePause,
+    ToggleMute,
+    PlayNext,
+    PlayPrevious,
+    RegionList,
+    RegionQueue,
+    RegionPlaylists,
+    RegionSeeker,
+    RegionVolume,
+    RegionActions,
+    /// the lyrics region if displayed, the actions one otherwise
+    RegionLyricsOrActions,
+    /// the lyrics region if displayed, the list one otherwise
+    RegionLyricsOrList,
+    ListDown,
+    ListUp,
+    ListTop,
+    ListBottom,
+    ListPlaying,
+    /// the first visible music
+    ListScreenTop,
+    ListScreenMiddle,
+    ListScreenBottom,
+    /// scrolls the selection to the middle of the list
+    ListCenter,
+    /// marks the selected music with the next character typed
+    ListSetMark,
+    /// selects the music marked with the next character typed
+    ListGotoMark,
+    /// sorts by the column numbered with the next digit typed, reversing
+    /// the order if it is sorted by it already
+    ListSortColumn,
+    ListHalfPageDown,
+    ListHalfPageUp,
+    ListPlay,
+    ListEnqueue,
+    ListPlayNext,
+    ListAddToPlaylist,
+    ListAlbums,
+    /// shuffles the list again, the playing music first
+    ListReshuffle,
+    /// back to the `Select` mode
+    ListBack,
+    SearchStart,
+    SearchAccept,
+    SearchLeave,
+    SearchDeleteChar,
+    QueueDown,
+    QueueUp,
+    QueuePlay,
+    QueueRemove,
+    QueueClear,
+    QueueMoveDown,
+    QueueMoveUp,
+    QueueSave,
+    PlaylistsDown,
+    PlaylistsUp,
+    PlaylistsLoad,
+    PlaylistsRefresh,
+    LyricsDown,
+    LyricsUp,
+    LyricsFollow,
+    ActionsNext,
+    ActionsPrevious,
+    ActionsPerform,
+    ActionsCycleBack,
+    SeekForward,
+    SeekBackward,
+    VolumeUp,
+    VolumeDown,
+    MessagesDown,
+    MessagesUp,
+    MessagesClose,
+    HistoryDown,
+    HistoryUp,
+    /// plays the music of the selected play
+    HistoryPlay,
+    HistoryClose,
+}
+
+/// Names of the commands, as written in the config
+const COMMANDS: [(Command, &str); 71] = [
+    (Command::Quit, "app.quit"),
+    (Command::Repeat, "app.repeat"),
+    (Command::Messages, "app.messages"),
+    (Command::History, "app.history"),
+    (Command::TogglePause, "player.toggle_pause"),
+    (Command::ToggleMute, "player.toggle_mute"),
+    (Command::PlayNext, "player.next"),
+    (Command::PlayPrevious, "player.previous"),
+    (Command::RegionList, "region.list"),
+    (Command::RegionQueue, "region.queue"),
+    (Command::RegionPlaylists, "region.playlists"),
+    (Command::RegionSeeker, "region.seeker"),
+    (Command::RegionVolume, "region.volume"),
+    (Command::RegionActions, "region.actions"),
+    (Command::RegionLyricsOrActions, "region.lyrics_or_actions"),
+    (Command::RegionLyricsOrList, "region.lyrics_or_list"),
+    (Command::ListDown, "list.down"),
+    (Command::ListUp, "list.up"),
+    (Command::ListTop, "list.top"),
+    (Command::ListBottom, "list.bottom"),
+    (Command::ListPlaying, "list.playing"),
+    (Command::ListScreenTop, "list.screen_top"),
+    (Command::ListScreenMiddle, "list.screen_middle"),
+    (Command::ListScreenBottom, "list.screen_bottom"),
+    (Command::ListCenter, "list.center"),
+    (Command::ListSetMark, "list.set_mark"),
+    (Command::ListGotoMark, "list.goto_mark"),
+    (Command::ListSortColumn, "list.sort_column"),
+    (Command::ListHalfPageDown, "list.half_page_down"),
+    (Command::ListHalfPageUp, "list.half_page_up"),
+    (Command::ListPlay, "list.play"),
+    (Command::ListEnqueue, "list.enqueue"),
+    (Command::ListPlayNext, "list.play_next"),
+    (Command::ListAddToPlaylist, "list.add_to_playlist"),
+    (Command::ListAlbums, "list.albums"),
+    (Command::ListReshuffle, "list.reshuffle"),
+    (Command::ListBack, "list.back"),
+    (Command::SearchStart, "search.start"),
+    (Command::SearchAccept, "search.accept"),
+    (Command::SearchLeave, "search.leave"),
+    (Command::SearchDeleteChar, "search.delete_char"),
+    (Command::QueueDown, "queue.down"),
+    (Command::QueueUp, "queue.up"),
+    (Command::QueuePlay, "queue.play"),
+    (Command::QueueRemove, "queue.remove"),
+    (Command::QueueClear, "queue.clear"),
+    (Command::QueueMoveDown, "queue.move_down"),
+    (Command::QueueMoveUp, "queue.move_up"),
+    (Command::QueueSave, "queue.save"),
+    (Command::PlaylistsDown, "playlists.down"),
+    (Command::PlaylistsUp, "playlists.up"),
+    (Command::PlaylistsLoad, "playlists.load"),
+    (Command::PlaylistsRefresh, "playlists.refresh"),
+    (Command::LyricsDown, "lyrics.down"),
+    (Command::LyricsUp, "lyrics.up"),
+    (Command::LyricsFollow, "lyrics.follow"),
+    (Command::ActionsNext, "actions.next"),
+    (Command::ActionsPrevious, "actions.previous"),
+    (Command::ActionsPerform, "actions.perform"),
+    (Command::ActionsCycleBack, "actions.cycle_back"),
+    (Command::SeekForward, "seek.forward"),
+    (Command::SeekBackward, "seek.backward"),
+    (Command::VolumeUp, "volume.up"),
+    (Command::VolumeDown, "volume.down"),
+    (Command::MessagesDown, "messages.down"),
+    (Command::MessagesUp, "messages.up"),
+    (Command::MessagesClose, "messages.close"),
+    (Command::HistoryDown, "history.down"),
+    (Command::HistoryUp, "history.up"),
+    (Command::HistoryPlay, "history.play"),
+    (Command::HistoryClose, "history.close"),
+];
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        COMMANDS.iter().find(|(c, _)| c == self).map(|(_, name)| *name).unwrap_or_default()
+    }
+
+    fn from_name(name: &str) -> Option<Command> {
+        COMMANDS.iter().find(|(_, n)| *n == name).map(|(c, _)| *c)
+    }
+
+    /// What comes before the `.` of the name, e.g. `list` for `list.down`
+    fn group(&self) -> &'static str {
+        self.name().split('.').next().unwrap_or_default()
+    }
+
+    /// Whether the command waits for a character, like the `a` of `ma`
+    pub fn takes_char(&self) -> bool {
+        matches!(self, Command::ListSetMark | Command::ListGotoMark | Command::ListSortColumn)
+    }
+
+    /// Whether a count runs the command that many times, `5j` going down
+    /// by 5, other commands either ignore the count or use it as a position
+    pub fn repeats(&self) -> bool {
+        matches!(self,
+            Command::ListDown | Command::ListUp | Command::ListHalfPageDown | Command::ListHalfPageUp
+            | Command::QueueDown | Command::QueueUp | Command::QueueMoveDown | Command::QueueMoveUp
+            | Command::PlaylistsDown | Command::PlaylistsUp | Command::LyricsDown | Command::LyricsUp
+            | Command::ActionsNext | Command::ActionsPrevious | Command::SeekForward
+            | Command::SeekBackward | Command::VolumeUp | Command::VolumeDown
+            | Command::MessagesDown | Command::MessagesUp | Command::HistoryDown | Command::HistoryUp)
+    }
+}
+
+/// The bindings the client ships with
+const DEFAULTS: &[(Context, &str, Command)] = &[
+    (Context::Global, "q", Command::Quit),
+    (Context::Global, "p", Command::TogglePause),
+    (Context::Global, "m", Command::ToggleMute),
+    (Context::Global, "n", Command::PlayNext),
+    (Context::Global, "N", Command::PlayPrevious),
+    (Context::Global, ".", Command::Repeat),
+    (Context::Global, "!", Command::Messages),
+    (Context::Global, "y", Command::History),
+
+    (Context::List, "j", Command::ListDown),
+    (Context::List, "k", Command::ListUp),
+    (Context::List, "gg", Command::ListTop),
+    (Context::List, "G", Command::ListBottom),
+    (Context::List, "s", Command::ListPlaying),
+    (Context::List, "H", Command::ListScreenTop),
+    (Context::List, "M", Command::ListScreenMiddle),
+    (Context::List, "L", Command::ListScreenBottom),
+    (Context::List, "zz", Command::ListCenter),
+    (Context::List, "m", Command::ListSetMark),
+    (Context::List, "'", Command::ListGotoMark),
+    (Context::List, "o", Command::ListSortColumn),
+    // `m` sets marks in the list
+    (Context::List, "alt+m", Command::ToggleMute),
+    (Context::List, "ctrl+d", Command::ListHalfPageDown),
+    (Context::List, "ctrl+u", Command::ListHalfPageUp),
+    (Context::List, "space", Command::ListPlay),
+    (Context::List, "enter", Command::ListPlay),
+    (Context::List, "a", Command::ListEnqueue),
+    (Context::List, "A", Command::ListPlayNext),
+    (Context::List, "P", Command::ListAddToPlaylist),
+    (Context::List, "b", Command::ListAlbums),
+    (Context::List, "R", Command::ListReshuffle),
+    (Context::List, "/", Command::SearchStart),
+    (Context::List, "alt+k", Command::RegionSeeker),
+    (Context::List, "alt+j", Command::RegionLyricsOrActions),
+    (Context::List, "alt+l", Command::RegionQueue),
+    (Context::List, "alt+h", Command::RegionPlaylists),
+
+    (Context::Search, "enter", Command::SearchAccept),
+    (Context::Search, "esc", Command::SearchLeave),
+    (Context::Search, "backspace", Command::SearchDeleteChar),
+
+    (Context::AfterSearch, "j", Command::ListDown),
+    (Context::AfterSearch, "alt+j", Command::ListDown),
+    (Context::AfterSearch, "k", Command::ListUp),
+    (Context::AfterSearch, "alt+k", Command::ListUp),
+    (Context::AfterSearch, "/", Command::SearchStart),
+    (Context::AfterSearch, "alt+/", Command::SearchStart),
+    (Context::AfterSearch, "space", Command::ListPlay),
+    (Context::AfterSearch, "enter", Command::ListPlay),
+    (Context::AfterSearch, "a", Command::ListEnqueue),
+    (Context::AfterSearch, "A", Command::ListPlayNext),
+    (Context::AfterSearch, "q", Command::ListBack),
+    (Context::AfterSearch, "esc", Command::ListBack),
+
+    (Context::Albums, "j", Command::ListDown),
+    (Context::Albums, "k", Command::ListUp),
+    (Context::Albums, "space", Command::ListPlay),
+    (Context::Albums, "enter", Command::ListPlay),
+    (Context::Albums, "a", Command::ListEnqueue),
+    (Context::Albums, "A", Command::ListPlayNext),
+    (Context::Albums, "b", Command::ListBack),
+    (Context::Albums, "q", Command::ListBack),
+    (Context::Albums, "esc", Command::ListBack),
+    (Context::Albums, "alt+k", Command::RegionSeeker),
+    (Context::Albums, "alt+j", Command::RegionLyricsOrActions),
+    (Context::Albums, "alt+l", Command::RegionQueue),
+    (Context::Albums, "alt+h", Command::RegionPlaylists),
+
+    (Context::Queue, "j", Command::QueueDown),
+    (Context::Queue, "k", Command::QueueUp),
+    (Context::Queue, "space", Command::QueuePlay),
+    (Context::Queue, "enter", Command::QueuePlay),
+    (Context::Queue, "d", Command::QueueRemove),
+    (Context::Queue, "x", Command::QueueRemove),
+    (Context::Queue, "c", Command::QueueClear),
+    (Context::Queue, "J", Command::QueueMoveDown),
+    (Context::Queue, "K", Command::QueueMoveUp),
+    (Context::Queue, "W", Command::QueueSave),
+    (Context::Queue, "alt+h", Command::RegionList),
+    (Context::Queue, "alt+j", Command::RegionLyricsOrActions),
+    (Context::Queue, "alt+k", Command::RegionSeeker),
+
+    (Context::Playlists, "j", Command::PlaylistsDown),
+    (Context::Playlists, "k", Command::PlaylistsUp),
+    (Context::Playlists, "space", Command::PlaylistsLoad),
+    (Context::Playlists, "enter", Command::PlaylistsLoad),
+    (Context::Playlists, "r", Command::PlaylistsRefresh),
+    (Context::Playlists, "alt+l", Command::RegionList),
+    (Context::Playlists, "alt+j", Command::RegionLyricsOrActions),
+    (Context::Playlists, "alt+k", Command::RegionSeeker),
+
+    (Context::Lyrics, "j", Command::LyricsDown),
+    (Context::Lyrics, "k", Command::LyricsUp),
+    (Context::Lyrics, "f", Command::LyricsFollow),
+    (Context::Lyrics, "alt+k", Command::RegionList),
+    (Context::Lyrics, "alt+j", Command::RegionActions),
+
+    (Context::Actions, "l", Command::ActionsNext),
+    (Context::Actions, "h", Command::ActionsPrevious),
+    (Context::Actions, "enter", Command::ActionsPerform),
+    (Context::Actions, "alt+enter", Command::ActionsCycleBack),
+    (Context::Actions, "alt+j", Command::RegionSeeker),
+    (Context::Actions, "alt+k", Command::RegionLyricsOrList),
+
+    (Context::Seeker, "l", Command::SeekForward),
+    (Context::Seeker, "h", Command::SeekBackward),
+    (Context::Seeker, "k", Command::TogglePause),
+    (Context::Seeker, "alt+j", Command::RegionList),
+    (Context::Seeker, "alt+k", Command::RegionActions),
+    (Context::Seeker, "alt+l", Command::RegionVolume),
+    (Context::Seeker, "alt+y", Command::RegionVolume),
+
+    (Context::Volume, "k", Command::VolumeUp),
+    (Context::Volume, "l", Command::VolumeUp),
+    (Context::Volume, "J", Command::VolumeUp),
+    (Context::Volume, "H", Command::VolumeUp),
+    (Context::Volume, "h", Command::VolumeDown),
+    (Context::Volume, "j", Command::VolumeDown),
+    (Context::Volume, "K", Command::VolumeDown),
+    (Context::Volume, "L", Command::VolumeDown),
+    (Context::Volume, "alt+j", Command::RegionList),
+    (Context::Volume, "alt+k", Command::RegionActions),
+    (Context::Volume, "alt+l", Command::RegionSeeker),
+    (Context::Volume, "alt+h", Command::RegionSeeker),
+
+    (Context::Messages, "j", Command::MessagesDown),
+    (Context::Messages, "down", Command::MessagesDown),
+    (Context::Messages, "k", Command::MessagesUp),
+    (Context::Messages, "up", Command::MessagesUp),
+    (Context::Messages, "!", Command::MessagesClose),
+    (Context::Messages, "q", Command::MessagesClose),
+    (Context::Messages, "esc", Command::MessagesClose),
+
+    (Context::History, "j", Command::HistoryDown),
+    (Context::History, "down", Command::HistoryDown),
+    (Context::History, "k", Command::HistoryUp),
+    (Context::History, "up", Command::HistoryUp),
+    (Context::History, "enter", Command::HistoryPlay),
+    (Context::History, "space", Command::HistoryPlay),
+    (Context::History, "y", Command::HistoryClose),
+    (Context::History, "q", Command::HistoryClose),
+    (Context::History, "esc", Command::HistoryClose),
+];
+
+/// Keys with a name, `space` being a [KeyCode::Char] as well
+const NAMED_KEYS: [(&str, KeyCode); 16] = [
+    ("space", KeyCode::Char(' ')),
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("backspace", KeyCode::Backspace),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+/// A key along with the modifiers held while pressing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// shift is carried by the case of characters, terminals do not agree
+    /// on whether to report it for them
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => KeyChord {
+                code: KeyCode::Char(c.to_uppercase().next().unwrap_or(c)),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => KeyChord { code, modifiers },
+        }
+    }
+
+    /// The character typed by the chord, if any
+    pub fn char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => Some(c),
+            _ => None,
+        }
+    }
+
+    fn parse_key(key: &str) -> Option<KeyCode> {
+        let lower = key.to_lowercase();
+        if let Some((_, code)) = NAMED_KEYS.iter().find(|(name, _)| *name == lower) {
+            return Some(*code)
+        }
+        match lower.as_str() {
+            "return" => return Some(KeyCode::Enter),
+            "escape" => return Some(KeyCode::Esc),
+            "del" => return Some(KeyCode::Delete),
+            _ => {}
+        }
+        if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+            return (1..=12).contains(&n).then_some(KeyCode::F(n))
+        }
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(KeyCode::Char(c)),
+            _ => None,
+        }
+    }
+
+    /// Parses a chord with modifiers, e.g. `ctrl+d` or `alt+shift+j`
+    fn parse_chord(chord: &str) -> Result<KeyChord, String> {
+        // `ctrl++` binds the `+` key
+        let (modifiers, key) = match chord.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => chord.rsplit_once('+').unwrap_or(("", chord)),
+        };
+        let mut mods = KeyModifiers::NONE;
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            mods |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier {:?}", modifier)),
+            };
+        }
+        match KeyChord::parse_key(key) {
+            Some(code) => Ok(KeyChord::new(code, mods)),
+            None => Err(format!("unknown key {:?}", key)),
+        }
+    }
+
+    /// Parses a key sequence, chords are separated by spaces, and a word
+    /// which is not the name of a key is a sequence of characters, so
+    /// `gg`, `g g` and `ctrl+w j` are sequences while `G` and `enter` are not
+    pub fn parse_sequence(sequence: &str) -> Result<Vec<KeyChord>, String> {
+        let mut keys = vec![];
+        for word in sequence.split_whitespace() {
+            if word.len() > 1 && word.contains('+') {
+                keys.push(KeyChord::parse_chord(word)?);
+            } else if let Some(code) = KeyChord::parse_key(word) {
+                keys.push(KeyChord::new(code, KeyModifiers::NONE));
+            } else {
+                keys.extend(word.chars().map(|c| KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE)));
+            }
+        }
+        if keys.is_empty() {
+            return Err(String::from("empty key sequence"))
+        }
+        Ok(keys)
+    }
+}
+
+impl From<&KeyEvent> for KeyChord {
+    fn from(value: &KeyEvent) -> Self {
+        KeyChord::new(value.code, value.modifiers)
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl+"), (KeyModifiers::ALT, "alt+"), (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match (NAMED_KEYS.iter().find(|(_, code)| *code == self.code), self.code) {
+            (Some((name, _)), _) => write!(f, "{}", name),
+            (None, KeyCode::Char(c)) => write!(f, "{}", c),
+            (None, KeyCode::F(n)) => write!(f, "f{}", n),
+            (None, code) => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// What a sequence of keys leads to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Command(Command),
+    /// the keys start a longer sequence
+    Pending,
+    Unbound,
+}
+
+/// What the input typed so far leads to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Run {
+        command: Command,
+        count: Option<usize>,
+        /// the character typed after a command which [Command::takes_char]
+        char: Option<char>,
+    },
+    Pending,
+    /// keys that are not bound, given back to be typed in the search
+    Unbound(Vec<KeyChord>),
+}
+
+/// Count, keys and character typed so far for a command, like `12g` on
+/// the way to `12gg`
+#[derive(Debug, Clone, Default)]
+pub struct PendingInput {
+    count: Option<usize>,
+    keys: Vec<KeyChord>,
+    /// bound command waiting for a character
+    awaiting: Option<Command>,
+}
+
+impl PendingInput {
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none() && self.keys.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = PendingInput::default();
+    }
+
+    /// Reads _key_ in _context_, counts are not read in the search
+    pub fn feed(&mut self, keymap: &Keymap, context: Context, key: KeyChord) -> Input {
+        if let Some(command) = self.awaiting {
+            let count = self.count;
+            self.clear();
+            return match key.char() {
+                Some(c) => Input::Run { command, count, char: Some(c) },
+                // e.g. `esc` cancels the command
+                None => Input::Unbound(vec![]),
+            }
+        }
+        let digit = key.char().and_then(|c| c.to_digit(10)).map(|d| d as usize);
+        if let Some(digit) = digit {
+            // a count does not start with 0, and bound digits are left alone
+            let counting = self.keys.is_empty()
+                && context != Context::Search
+                && (digit != 0 || self.count.is_some())
+                && keymap.lookup(context, &[key]) == Lookup::Unbound;
+            if counting {
+                self.count = Some((self.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                return Input::Pending
+            }
+        }
+        self.keys.push(key);
+        match keymap.lookup(context, &self.keys) {
+            Lookup::Command(command) if command.takes_char() => {
+                self.awaiting = Some(command);
+                Input::Pending
+            },
+            Lookup::Command(command) => {
+                let count = self.count;
+                self.clear();
+                Input::Run { command, count, char: None }
+            },
+            Lookup::Pending => Input::Pending,
+            Lookup::Unbound => {
+                let keys = std::mem::take(&mut self.keys);
+                self.clear();
+                Input::Unbound(keys)
+            },
+        }
+    }
+}
+
+impl Display for PendingInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(count) = self.count {
+            write!(f, "{}", count)?;
+        }
+        for key in &self.keys {
+            write!(f, "{}", key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Key sequences bound to commands, per [Context]
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Context, Vec<(Vec<KeyChord>, Command)>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut bindings: HashMap<Context, Vec<(Vec<KeyChord>, Command)>> = HashMap::new();
+        for (context, keys, command) in DEFAULTS {
+            let keys = KeyChord::parse_sequence(keys).expect("default keybinds are valid");
+            bindings.entry(*context).or_default().push((keys, *command));
+        }
+        Keymap { bindings }
+    }
+}
+
+/// Whether one sequence is the start of the other, or both are the same
+fn overlaps(a: &[KeyChord], b: &[KeyChord]) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+fn sequence_to_string(keys: &[KeyChord]) -> String {
+    keys.iter().map(|k| k.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+impl Keymap {
+    /// Builds the keymap out of the defaults and the `[keybinds]` table,
+    /// a configured sequence replaces the defaults it overlaps with.
+    ///
+    /// every invalid context, key, command or conflicting binding is
+    /// returned at once
+    pub fn new(config: Option<&KeybindsConfig>) -> Result<Keymap, Vec<String>> {
+        let mut keymap = Keymap::default();
+        let mut errors = vec![];
+        for (context_name, table) in config.into_iter().flatten() {
+            let Some(context) = Context::from_name(context_name) else {
+                errors.push(format!("[keybinds.{}]: unknown context", context_name));
+                continue
+            };
+            // [None] unbinds the sequence
+            let mut configured: Vec<(Vec<KeyChord>, Option<Command>)> = vec![];
+            for (sequence, command_name) in table {
+                let at = format!("[keybinds.{}] {:?}", context_name, sequence);
+                let keys = match KeyChord::parse_sequence(sequence) {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        errors.push(format!("{}: {}", at, e));
+                        continue
+                    },
+                };
+                let command = if command_name == UNBIND {
+                    None
+                } else {
+                    match Command::from_name(command_name) {
+                        Some(command) if context.allows(command) => Some(command),
+                        Some(_) => {
+                            errors.push(format!("{}: {:?} can't be used in {}", at, command_name, context_name));
+                            continue
+                        },
+                        None => {
+                            errors.push(format!("{}: unknown command {:?}", at, command_name));
+                            continue
+                        },
+                    }
+                };
+                if let Some((other, _)) = configured.iter().find(|(other, _)| overlaps(other, &keys)) {
+                    errors.push(format!("{}: conflicts with {:?}", at, sequence_to_string(other)));
+                    continue
+                }
+                configured.push((keys, command));
+            }
+            let bindings = keymap.bindings.entry(context).or_default();
+            bindings.retain(|(keys, _)| configured.iter().all(|(other, _)| !overlaps(keys, other)));
+            bindings.extend(configured.into_iter().filter_map(|(keys, command)| Some((keys, command?))));
+        }
+        if errors.is_empty() {
+            Ok(keymap)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn lookup_in(&self, context: Context, keys: &[KeyChord]) -> Lookup {
+        let bindings = self.bindings.get(&context).map(Vec::as_slice).unwrap_or_default();
+        if let Some((_, command)) = bindings.iter().find(|(k, _)| k == keys) {
+            Lookup::Command(*command)
+        } else if bindings.iter().any(|(k, _)| k.starts_with(keys)) {
+            Lookup::Pending
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// What the _keys_ typed so far lead to in _context_, falling back to
+    /// the global bindings outside of the search
+    pub fn lookup(&self, context: Context, keys: &[KeyChord]) -> Lookup {
+        match self.lookup_in(context, keys) {
+            Lookup::Unbound if context != Context::Search => self.lookup_in(Context::Global, keys),
+            lookup => lookup,
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn config(context: &str, bindings: &[(&str, &str)]) -> KeybindsConfig {
+        let table = bindings.iter().map(|(k, c)| (k.to_string(), c.to_string())).collect();
+        BTreeMap::from([(context.to_string(), table)])
+    }
+
+    #[test]
+    fn test_parse_sequence() {
+        let chord = |code, modifiers| KeyChord::new(code, modifiers);
+        assert_eq!(KeyChord::parse_sequence("gg").unwrap(), vec![
+            chord(KeyCode::Char('g'), KeyModifiers::NONE), chord(KeyCode::Char('g'), KeyModifiers::NONE),
+        ]);
+        assert_eq!(KeyChord::parse_sequence("shift+g").unwrap(), KeyChord::parse_sequence("G").unwrap());
+        assert_eq!(KeyChord::parse_sequence("ctrl+w j").unwrap(), vec![
+            chord(KeyCode::Char('w'), KeyModifiers::CONTROL), chord(KeyCode::Char('j'), KeyModifiers::NONE),
+        ]);
+        assert_eq!(KeyChord::parse_sequence("Space").unwrap(), vec![chord(KeyCode::Char(' '), KeyModifiers::NONE)]);
+        assert_eq!(KeyChord::parse_sequence("alt+enter").unwrap(), vec![chord(KeyCode::Enter, KeyModifiers::ALT)]);
+        assert_eq!(KeyChord::parse_sequence("ctrl++").unwrap(), vec![chord(KeyCode::Char('+'), KeyModifiers::CONTROL)]);
+        assert!(KeyChord::parse_sequence("hyper+j").is_err());
+        assert!(KeyChord::parse_sequence("ctrl+nothing").is_err());
+        assert_eq!(KeyChord::parse_sequence("ctrl+d").unwrap()[0].to_string(), "ctrl+d");
+    }
+
+    #[test]
+    fn test_defaults() {
+        let keymap = Keymap::new(None).unwrap();
+        let keys = |s| KeyChord::parse_sequence(s).unwrap();
+        assert_eq!(keymap.lookup(Context::List, &keys("g")), Lookup::Pending);
+        assert_eq!(keymap.lookup(Context::List, &keys("gg")), Lookup::Command(Command::ListTop));
+        // global bindings apply everywhere but in the search
+        assert_eq!(keymap.lookup(Context::Queue, &keys("p")), Lookup::Command(Command::TogglePause));
+        assert_eq!(keymap.lookup(Context::Search, &keys("p")), Lookup::Unbound);
+        assert_eq!(keymap.lookup(Context::Albums, &keys("q")), Lookup::Command(Command::ListBack));
+        assert_eq!(keymap.lookup(Context::Messages, &keys("q")), Lookup::Command(Command::MessagesClose));
+        assert_eq!(keymap.lookup(Context::History, &keys("enter")), Lookup::Command(Command::HistoryPlay));
+        // every default is allowed where it is bound, and is not hidden by another one
+        for (context, bindings) in &keymap.bindings {
+            for (i, (keys, command)) in bindings.iter().enumerate() {
+                assert!(context.allows(*command), "{} in {}", command.name(), context.name());
+                assert!(bindings[i + 1..].iter().all(|(other, _)| !overlaps(keys, other)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pending_input() {
+        let keymap = Keymap::default();
+        let mut pending = PendingInput::default();
+        let mut feed = |context, keys: &str| KeyChord::parse_sequence(keys).unwrap().into_iter()
+            .map(|key| pending.feed(&keymap, context, key))
+            .last().unwrap();
+        assert_eq!(feed(Context::List, "5j"), Input::Run { command: Command::ListDown, count: Some(5), char: None });
+        assert_eq!(feed(Context::List, "12g"), Input::Pending);
+        assert_eq!(feed(Context::List, "g"), Input::Run { command: Command::ListTop, count: Some(12), char: None });
+        assert_eq!(feed(Context::List, "ma"), Input::Run { command: Command::ListSetMark, count: None, char: Some('a') });
+        assert_eq!(feed(Context::List, "0"), Input::Unbound(KeyChord::parse_sequence("0").unwrap()));
+        // digits are part of the query in the search
+        assert_eq!(feed(Context::Search, "5"), Input::Unbound(KeyChord::parse_sequence("5").unwrap()));
+        assert_eq!(feed(Context::List, "3z"), Input::Pending);
+        assert_eq!(pending.to_string(), "3z");
+    }
+
+    #[test]
+    fn test_config() {
+        let keys = |s| KeyChord::parse_sequence(s).unwrap();
+        let keymap = Keymap::new(Some(&config("list", &[
+            ("g", "list.bottom"), ("J", "list.down"), ("j", "none"),
+        ]))).unwrap();
+        // `g` replaces the default `gg`
+        assert_eq!(keymap.lookup(Context::List, &keys("g")), Lookup::Command(Command::ListBottom));
+        assert_eq!(keymap.lookup(Context::List, &keys("J")), Lookup::Command(Command::ListDown));
+        assert_eq!(keymap.lookup(Context::List, &keys("j")), Lookup::Unbound);
+        assert_eq!(keymap.lookup(Context::List, &keys("k")), Lookup::Command(Command::ListUp));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let errors = Keymap::new(Some(&config("list", &[
+            ("g", "list.top"), ("gg", "list.bottom"), ("x", "list.topp"), ("y", "queue.clear"),
+        ]))).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("conflicts with \"g\""));
+        assert!(errors[1].contains("unknown command"));
+        assert!(errors[2].contains("can't be used"));
+        assert!(Keymap::new(Some(&config("lists", &[]))).is_err());
+        // both are shift+g
+        assert!(Keymap::new(Some(&config("list", &[("G", "list.top"), ("shift+g", "list.bottom")]))).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use ratatui::layout::{Constraint, Layout, Rect};
+use serde::{Deserialize, Serialize};
+
+use crate::columns::SizeConfig;
+
+/// Names of the built-in layouts
+pub const PRESETS: [&str; 3] = ["default", "compact", "wide"];
+
+/// A region of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    List,
+    Queue,
+    Playlists,
+    Lyrics,
+    Actions,
+    Seeker,
+    Volume,
+    /// a single line with the last message
+    Status,
+}
+
+impl Pane {
+    const ALL: [(Pane, &'static str); 8] = [
+        (Pane::List, "list"),
+        (Pane::Queue, "queue"),
+        (Pane::Playlists, "playlists"),
+        (Pane::Lyrics, "lyrics"),
+        (Pane::Actions, "actions"),
+        (Pane::Seeker, "seeker"),
+        (Pane::Volume, "volume"),
+        (Pane::Status, "status"),
+    ];
+
+    /// Panes left out first when the terminal is too small, the list is
+    /// never left out
+    const DROP_ORDER: [Pane; 7] = [
+        Pane::Lyrics, Pane::Status, Pane::Playlists, Pane::Queue, Pane::Actions, Pane::Volume, Pane::Seeker,
+    ];
+
+    fn parse(name: &str) -> Option<Pane> {
+        Pane::ALL.iter().find(|(_, n)| *n == name.to_lowercase()).map(|(p, _)| *p)
+    }
+
+    /// Smallest width and height the pane is readable in, borders included
+    fn min_size(&self) -> (u16, u16) {
+        match self {
+            // the header and a music at least
+            Pane::List => (20, 4),
+            Pane::Queue | Pane::Playlists => (12, 3),
+            Pane::Lyrics => (12, 3),
+            // the play buttons at least
+            Pane::Actions => (24, 3),
+            Pane::Seeker => (16, 3),
+            Pane::Volume => (10, 3),
+            Pane::Status => (10, 1),
+        }
+    }
+
+    /// Height of a row holding the pane when it is not configured
+    fn height(&self) -> Constraint {
+        match self {
+            Pane::List | Pane::Queue | Pane::Playlists => Constraint::Fill(1),
+            Pane::Lyrics => Constraint::Length(8),
+            Pane::Actions | Pane::Seeker | Pane::Volume => Constraint::Length(3),
+            Pane::Status => Constraint::Length(1),
+        }
+    }
+}
+
+/// A pane of a row as written in the config
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct PaneConfig {
+    pub pane: String,
+    /// `fill:1` if unset
+    pub width: Option<SizeConfig>,
+}
+
+/// A row of panes as written in the config
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct RowConfig {
+    /// fills the screen if the row holds the list, the queue or the
+    /// playlists, fits the other panes otherwise
+    pub height: Option<SizeConfig>,
+    pub panes: Vec<PaneConfig>,
+}
+
+/// The `[layout]` config: a preset, or rows of panes from top to bottom
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct LayoutConfig {
+    pub preset: Option<String>,
+    /// replaces the preset when set
+    pub rows: Option<Vec<RowConfig>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Row {
+    height: Constraint,
+    panes: Vec<(Pane, Constraint)>,
+}
+
+/// Where every pane goes on the screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenLayout {
+    rows: Vec<Row>,
+}
+
+impl Default for ScreenLayout {
+    fn default() -> Self {
+        ScreenLayout::preset("default").unwrap_or(ScreenLayout { rows: vec![] })
+    }
+}
+
+impl ScreenLayout {
+    /// The built-in layout _name_
+    pub fn preset(name: &str) -> Option<ScreenLayout> {
+        let row = |height, panes: &[(Pane, Constraint)]| Row { height, panes: panes.to_vec() };
+        let rows = match name {
+            "default" => vec![
+                row(Constraint::Fill(1), &[
+                    (Pane::Playlists, Constraint::Fill(1)),
+                    (Pane::List, Constraint::Fill(4)),
+                    (Pane::Queue, Constraint::Fill(2)),
+                ]),
+                row(Constraint::Length(8), &[(Pane::Lyrics, Constraint::Fill(1))]),
+                row(Constraint::Length(3), &[(Pane::Actions, Constraint::Fill(1))]),
+                row(Constraint::Length(3), &[
+                    (Pane::Seeker, Constraint::Fill(4)),
+                    (Pane::Volume, Constraint::Fill(1)),
+                ]),
+                row(Constraint::Length(1), &[(Pane::Status, Constraint::Fill(1))]),
+            ],
+            // a single line of controls below the list
+            "compact" => vec![
+                row(Constraint::Fill(1), &[
+                    (Pane::Playlists, Constraint::Fill(1)),
+                    (Pane::List, Constraint::Fill(4)),
+                    (Pane::Queue, Constraint::Fill(2)),
+                ]),
+                row(Constraint::Length(5), &[(Pane::Lyrics, Constraint::Fill(1))]),
+                row(Constraint::Length(3), &[
+                    (Pane::Actions, Constraint::Length(34)),
+                    (Pane::Seeker, Constraint::Fill(1)),
+                    (Pane::Volume, Constraint::Length(14)),
+                ]),
+                row(Constraint::Length(1), &[(Pane::Status, Constraint::Fill(1))]),
+            ],
+            // the lyrics on the side of the list
+            "wide" => vec![
+                row(Constraint::Fill(1), &[
+                    (Pane::Playlists, Constraint::Fill(1)),
+                    (Pane::List, Constraint::Fill(4)),
+                    (Pane::Queue, Constraint::Fill(2)),
+                    (Pane::Lyrics, Constraint::Fill(2)),
+                ]),
+                row(Constraint::Length(3), &[
+                    (Pane::Actions, Constraint::Fill(2)),
+                    (Pane::Seeker, Constraint::Fill(4)),
+                    (Pane::Volume, Constraint::Fill(1)),
+                ]),
+                row(Constraint::Length(1), &[(Pane::Status, Constraint::Fill(1))]),
+            ],
+            _ => return None,
+        };
+        Some(ScreenLayout { rows })
+    }
+
+    /// Builds the layout of the `[layout]` config, every unknown preset,
+    /// pane or size, and repeated or missing list pane is returned at once
+    pub fn new(config: Option<&LayoutConfig>) -> Result<ScreenLayout, Vec<String>> {
+        let Some(config) = config else {
+            return Ok(ScreenLayout::default())
+        };
+        let preset = config.preset.as_deref().unwrap_or("default");
+        let Some(rows) = &config.rows else {
+            return ScreenLayout::preset(preset).ok_or_else(|| vec![format!(
+                "[layout]: unknown preset {:?}, expected one of {}", preset, PRESETS.join(", ")
+            )])
+        };
+        let mut errors = vec![];
+        let size = |size: &Option<SizeConfig>, default: Constraint, errors: &mut Vec<String>| match size {
+            Some(size) => size.constraint().unwrap_or_else(|| {
+                errors.push(format!("[layout]: invalid size {:?}", size));
+                default
+            }),
+            None => default,
+        };
+        let mut layout = ScreenLayout { rows: vec![] };
+        let mut seen: Vec<Pane> = vec![];
+        for row in rows {
+            let mut panes = vec![];
+            for pane in &row.panes {
+                let Some(parsed) = Pane::parse(&pane.pane) else {
+                    errors.push(format!("[layout]: unknown pane {:?}", pane.pane));
+                    continue
+                };
+                if seen.contains(&parsed) {
+                    errors.push(format!("[layout]: pane {:?} shows up more than once", pane.pane));
+                    continue
+                }
+                seen.push(parsed);
+                panes.push((parsed, size(&pane.width, Constraint::Fill(1), &mut errors)));
+            }
+            let height = ScreenLayout::fitting_height(&panes);
+            layout.rows.push(Row { height: size(&row.height, height, &mut errors), panes });
+        }
+        if !seen.contains(&Pane::List) {
+            errors.push(String::from("[layout]: the list pane is missing"));
+        }
+        if errors.is_empty() {
+            Ok(layout)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Height fitting every pane of a row, filling the screen if one of them does
+    fn fitting_height(panes: &[(Pane, Constraint)]) -> Constraint {
+        let heights = panes.iter().map(|(p, _)| p.height()).collect::<Vec<Constraint>>();
+        if heights.iter().any(|h| matches!(h, Constraint::Fill(_))) {
+            return Constraint::Fill(1)
+        }
+        heights.into_iter().max_by_key(|h| match h {
+            Constraint::Length(length) => *length,
+            _ => 0,
+        }).unwrap_or(Constraint::Length(3))
+    }
+
+    /// Splits _area_ between the panes _shown_ keeps, rows without a pane
+    /// being left out
+    fn split(&self, area: Rect, shown: impl Fn(Pane) -> bool) -> Vec<(Pane, Rect)> {
+        let rows = self.rows.iter()
+            .map(|row| (row.height, row.panes.iter().filter(|(p, _)| shown(*p)).collect::<Vec<_>>()))
+            .filter(|(_, panes)| !panes.is_empty())
+            .collect::<Vec<_>>();
+        let row_areas = Layout::vertical(rows.iter().map(|(height, _)| *height)).split(area);
+        rows.iter().zip(row_areas.iter()).flat_map(|((_, panes), row_area)| {
+            let areas = Layout::horizontal(panes.iter().map(|(_, width)| *width)).split(*row_area);
+            panes.iter().map(|(pane, _)| *pane).zip(areas.iter().copied()).collect::<Vec<_>>()
+        }).collect()
+    }
+
+    /// Areas of the panes _shown_ keeps, panes are left out while any of
+    /// them does not fit, empty if even the list alone does not
+    pub fn areas(&self, area: Rect, shown: impl Fn(Pane) -> bool) -> Vec<(Pane, Rect)> {
+        let mut dropped: Vec<Pane> = vec![];
+        loop {
+            let areas = self.split(area, |p| shown(p) && !dropped.contains(&p));
+            let too_small = areas.iter()
+                .filter(|(pane, rect)| {
+                    let (width, height) = pane.min_size();
+                    rect.width < width || rect.height < height
+                })
+                .map(|(pane, _)| *pane)
+                .collect::<Vec<Pane>>();
+            if too_small.is_empty() {
+                return areas
+            }
+            // a pane which does not fit goes first, any pane making room
+            // for the list otherwise
+            let candidates = if too_small == [Pane::List] {
+                areas.iter().map(|(pane, _)| *pane).collect()
+            } else {
+                too_small
+            };
+            match Pane::DROP_ORDER.into_iter().find(|p| candidates.contains(p)) {
+                Some(pane) => dropped.push(pane),
+                None => return vec![],
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn panes(areas: &[(Pane, Rect)]) -> Vec<Pane> {
+        areas.iter().map(|(pane, _)| *pane).collect()
+    }
+
+    #[test]
+    fn test_presets() {
+        for name in PRESETS {
+            let config = LayoutConfig { preset: Some(name.to_string()), rows: None };
+            assert!(ScreenLayout::new(Some(&config)).is_ok(), "{}", name);
+        }
+        let config = LayoutConfig { preset: Some(String::from("tiny")), rows: None };
+        assert!(ScreenLayout::new(Some(&config)).is_err());
+    }
+
+    #[test]
+    fn test_areas() {
+        let layout = ScreenLayout::default();
+        let areas = layout.areas(Rect::new(0, 0, 100, 40), |p| p != Pane::Queue);
+        assert_eq!(panes(&areas), vec![
+            Pane::Playlists, Pane::List, Pane::Lyrics, Pane::Actions, Pane::Seeker, Pane::Volume, Pane::Status,
+        ]);
+        let list = areas.iter().find(|(p, _)| *p == Pane::List).unwrap().1;
+        assert_eq!(list.height, 40 - 8 - 3 - 3 - 1);
+        // the lyrics make room for the list first
+        let areas = layout.areas(Rect::new(0, 0, 100, 16), |_| true);
+        assert!(!panes(&areas).contains(&Pane::Lyrics));
+        assert!(panes(&areas).contains(&Pane::Seeker));
+        // panes too narrow are left out
+        let areas = layout.areas(Rect::new(0, 0, 40, 40), |_| true);
+        assert!(!panes(&areas).contains(&Pane::Volume));
+        assert!(panes(&areas).contains(&Pane::List));
+        // never underflows
+        assert!(layout.areas(Rect::new(0, 0, 5, 2), |_| true).is_empty());
+        assert!(layout.areas(Rect::new(0, 0, 0, 0), |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_config() {
+        let config: LayoutConfig = basic_toml::from_str(r#"
+            [[rows]]
+            panes = [{ pane = "seeker" }, { pane = "volume", width = 12 }]
+            [[rows]]
+            panes = [{ pane = "list", width = "fill:3" }, { pane = "queue" }]
+        "#).unwrap();
+        let layout = ScreenLayout::new(Some(&config)).unwrap();
+        assert_eq!(layout.rows[0].height, Constraint::Length(3));
+        assert_eq!(layout.rows[1].height, Constraint::Fill(1));
+        let areas = layout.areas(Rect::new(0, 0, 100, 30), |_| true);
+        assert_eq!(areas[0], (Pane::Seeker, Rect::new(0, 0, 88, 3)));
+
+        let config: LayoutConfig = basic_toml::from_str(r#"
+            [[rows]]
+            panes = [{ pane = "queue" }, { pane = "queue" }, { pane = "sidebar" }]
+            height = "tall"
+        "#).unwrap();
+        assert_eq!(ScreenLayout::new(Some(&config)).unwrap_err().len(), 4);
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+use crate::{parser::Root, utils, Config};
+
+/// `*` stays inside a directory while `**` goes through them
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A library root along with its compiled patterns
+#[derive(Debug, Clone)]
+struct RootFilter {
+    path: PathBuf,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl RootFilter {
+    fn new(root: &Root) -> Self {
+        RootFilter {
+            path: PathBuf::from(&root.path),
+            include: LibraryFilter::patterns(root.include.as_deref().unwrap_or_default()),
+            exclude: LibraryFilter::patterns(root.exclude.as_deref().unwrap_or_default()),
+        }
+    }
+
+    /// Whether the path, relative to the root, matches an exclude pattern
+    fn excludes(&self, relative: &Path) -> bool {
+        self.exclude.iter().any(|p| p.matches_path_with(relative, MATCH_OPTIONS))
+    }
+
+    /// Whether the file, relative to the root, matches the include patterns
+    fn includes(&self, relative: &Path) -> bool {
+        self.include.is_empty()
+            || self.include.iter().any(|p| p.matches_path_with(relative, MATCH_OPTIONS))
+    }
+}
+
+/// Decides which files are part of the music library, built from the
+/// roots, patterns, extensions and depth of the [Config]
+#[derive(Debug, Clone, Default)]
+pub struct LibraryFilter {
+    roots: Vec<RootFilter>,
+    /// lowercase extensions musics are allowed to have, [None] allows any
+    extensions: Option<Vec<String>>,
+    /// how many directories deep musics are looked for below a root
+    max_depth: Option<usize>,
+}
+
+impl LibraryFilter {
+    pub fn new(config: &Config) -> Self {
+        LibraryFilter {
+            roots: config.roots().iter().map(RootFilter::new).collect(),
+            extensions: config.extensions.as_ref().map(|exts| exts.iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect()),
+            max_depth: config.max_depth,
+        }
+    }
+
+    /// Compiles glob patterns, invalid ones are reported and ignored
+    fn patterns(patterns: &[String]) -> Vec<Pattern> {
+        patterns.iter()
+            .filter_map(|p| match Pattern::new(p) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    utils::report(&format!("Ignoring invalid pattern {:?}: {}", p, e));
+                    None
+                },
+            })
+            .collect()
+    }
+
+    /// Directories the musics are looked for in
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|r| r.path.to_owned()).collect()
+    }
+
+    /// The root _path_ is under along with _path_ relative to it
+    fn root_of<'a>(&self, path: &'a Path) -> Option<(&RootFilter, &'a Path)> {
+        // nested roots are handled by the deepest one
+        self.roots.iter()
+            .filter_map(|r| path.strip_prefix(&r.path).ok().map(|rel| (r, rel)))
+            .min_by_key(|(_, rel)| rel.components().count())
+    }
+
+    /// Whether _path_ is a directory or file of the library that is not
+    /// excluded, whatever its extension
+    pub fn contains(&self, path: &Path) -> bool {
+        match self.root_of(path) {
+            Some((root, relative)) => {
+                let mut prefix = PathBuf::new();
+                // a file inside an excluded directory is excluded as well
+                relative.components().all(|c| {
+                    prefix.push(c);
+                    !root.excludes(&prefix)
+                })
+            },
+            None => false,
+        }
+    }
+
+    /// Whether the file at _path_ should be part of the music library
+    pub fn accepts(&self, path: &Path) -> bool {
+        let Some((root, relative)) = self.root_of(path) else {
+            return false
+        };
+        let depth = relative.components().count().saturating_sub(1);
+        self.max_depth.is_none_or(|max| depth <= max)
+            && self.has_allowed_extension(path)
+            && root.includes(relative)
+            && self.contains(path)
+    }
+
+    fn has_allowed_extension(&self, path: &Path) -> bool {
+        let Some(extensions) = &self.extensions else {
+            return true
+        };
+        let extension = path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        extensions.contains(&extension)
+    }
+
+    /// Collects the accepted files under _dir_, excluded and too deep
+    /// directories are not walked through
+    fn visit_dir(&self, dir: &Path, files: &mut Vec<PathBuf>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                utils::report(&format!("Skipping directory {:?}: {}", dir, e));
+                return
+            },
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !self.contains(&path) {
+                continue
+            }
+            if path.is_dir() {
+                let too_deep = self.root_of(&path)
+                    .zip(self.max_depth)
+                    .is_some_and(|((_, relative), max)| relative.components().count() > max);
+                if !too_deep {
+                    self.visit_dir(&path, files);
+                }
+            } else if self.accepts(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    /// Paths of every accepted file under _dir_
+    pub fn files_in(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        self.visit_dir(dir, &mut files);
+        files
+    }
+
+    /// Paths of every accepted file of every root
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.roots.iter().flat_map(|r| self.files_in(&r.path)).collect()
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn filter() -> LibraryFilter {
+        LibraryFilter::new(&Config {
+            roots: Some(vec![
+                Root {
+                    path: String::from("/music"),
+                    include: None,
+                    exclude: Some(vec![String::from("Podcasts"), String::from("**/*.tmp.*")]),
+                },
+                Root {
+                    path: String::from("/disk/samples"),
+                    include: Some(vec![String::from("Loops/**/*.wav")]),
+                    exclude: None,
+                },
+            ]),
+            extensions: Some(vec![String::from("mp3"), String::from(".WAV")]),
+            max_depth: Some(2),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_accepts() {
+        let filter = filter();
+        assert!(filter.accepts(Path::new("/music/a.mp3")));
+        assert!(filter.accepts(Path::new("/music/Artist/Album/b.MP3")));
+        assert!(!filter.accepts(Path::new("/music/a/b/c/d.mp3")));
+        assert!(!filter.accepts(Path::new("/music/cover.jpg")));
+        assert!(!filter.accepts(Path::new("/music/Podcasts/episode.mp3")));
+        assert!(!filter.accepts(Path::new("/music/Artist/song.tmp.mp3")));
+        assert!(!filter.accepts(Path::new("/elsewhere/a.mp3")));
+        assert!(filter.accepts(Path::new("/disk/samples/Loops/kick.wav")));
+        assert!(!filter.accepts(Path::new("/disk/samples/OneShots/kick.wav")));
+    }
+
+    #[test]
+    fn test_contains() {
+        let filter = filter();
+        assert!(filter.contains(Path::new("/music/Artist")));
+        assert!(!filter.contains(Path::new("/music/Podcasts")));
+        assert!(!filter.contains(Path::new("/music/Podcasts/show")));
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("mplayer-client-library-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Podcasts")).unwrap();
+        std::fs::create_dir_all(dir.join("a/b")).unwrap();
+        for file in ["1.mp3", "2.txt", "Podcasts/3.mp3", "a/4.mp3", "a/b/5.mp3"] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        let filter = LibraryFilter::new(&Config {
+            roots: Some(vec![Root {
+                path: dir.to_string_lossy().to_string(),
+                include: None,
+                exclude: Some(vec![String::from("Podcasts")]),
+            }]),
+            extensions: Some(vec![String::from("mp3")]),
+            max_depth: Some(1),
+            ..Default::default()
+        });
+        let mut files = filter.files();
+        files.sort();
+        assert_eq!(files, vec![dir.join("1.mp3"), dir.join("a/4.mp3")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use lofty::{
+    config::ParseOptions,
+    file::{AudioFile, TaggedFileExt},
+    id3::v2::{Frame, FrameId, SyncTextContentType, SynchronizedTextFrame, TimestampFormat},
+    mpeg::MpegFile,
+    tag::ItemKey,
+};
+
+/// One line of lyrics
+#[derive(Debug, Clone, PartialEq)]
+pub struct LyricLine {
+    /// time stamp at which the line starts, [None] for unsynced lyrics
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+/// Lyrics of a music, either synchronized (every line has a time stamp)
+/// or plain text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lyrics {
+    pub lines: Vec<LyricLine>,
+    /// whether the lines carry time stamps
+    pub synced: bool,
+}
+
+impl Lyrics {
+    /// Loads the lyrics of the music at _path_, looking in order for:
+    /// - a sidecar `.lrc` file next to the music
+    /// - an embedded ID3v2 `SYLT` frame
+    /// - an embedded `USLT`/`LYRICS` tag
+    pub fn load(path: &Path) -> Option<Lyrics> {
+        if let Ok(content) = std::fs::read_to_string(path.with_extension("lrc")) {
+            let lyrics = Lyrics::parse_lrc(&content);
+            if !lyrics.lines.is_empty() {
+                return Some(lyrics)
+            }
+        }
+        if let Some(lyrics) = Lyrics::read_sylt(path) {
+            return Some(lyrics)
+        }
+        let tagged = lofty::probe::Probe::open(path).ok()?.read().ok()?;
+        let text = tagged.tags().iter()
+            .find_map(|tag| tag.get_string(&ItemKey::Lyrics).map(str::to_string))?;
+        let lyrics = Lyrics::parse_lrc(&text);
+        if lyrics.lines.is_empty() {
+            return None
+        }
+        Some(lyrics)
+    }
+
+    /// Reads the synchronized lyrics of an ID3v2 `SYLT` frame
+    fn read_sylt(path: &Path) -> Option<Lyrics> {
+        let is_mpeg = path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("mp3"));
+        if !is_mpeg {
+            return None
+        }
+        let mut file = File::open(path).ok()?;
+        let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+        let frame = mpeg.id3v2()?.get(&FrameId::Valid("SYLT".into()))?;
+        let Frame::Binary(binary) = frame else {
+            return None
+        };
+        let sylt = SynchronizedTextFrame::parse(&binary.data, binary.flags()).ok()?;
+        if sylt.timestamp_format != TimestampFormat::MS
+            || sylt.content_type != SyncTextContentType::Lyrics
+        {
+            return None
+        }
+        let lines = sylt.content.into_iter()
+            .map(|(ms, text)| LyricLine {
+                time: Some(Duration::from_millis(ms as u64)),
+                text: text.trim().to_string(),
+            })
+            .collect::<Vec<LyricLine>>();
+        if lines.is_empty() {
+            return None
+        }
+        Some(Lyrics { lines, synced: true })
+    }
+
+    /// Parses the content of a `.lrc` file, falls back to plain lyrics
+    /// if no line carries a time stamp
+    pub fn parse_lrc(content: &str) -> Lyrics {
+        let mut offset: i64 = 0;
+        let mut synced_lines = vec![];
+        let mut plain_lines = vec![];
+        for line in content.lines() {
+            let mut rest = line.trim();
+            let mut stamps = vec![];
+            while let Some(tag) = rest.strip_prefix('[') {
+                let Some(end) = tag.find(']') else {
+                    break
+                };
+                let (inner, after) = (&tag[..end], &tag[end + 1..]);
+                if let Some(stamp) = Lyrics::parse_time_stamp(inner) {
+                    stamps.push(stamp);
+                } else if let Some(value) = inner.strip_prefix("offset:") {
+                    offset = value.trim().parse().unwrap_or(0);
+                } else if !stamps.is_empty() {
+                    break
+                }
+                rest = after;
+            }
+            let text = Lyrics::strip_word_time_stamps(rest.trim());
+            if stamps.is_empty() {
+                // metadata tags like [ar:...] are not lyrics
+                if !line.trim().starts_with('[') {
+                    plain_lines.push(text);
+                }
+                continue
+            }
+            for stamp in stamps {
+                synced_lines.push(LyricLine { time: Some(stamp), text: text.clone() });
+            }
+        }
+        if synced_lines.is_empty() {
+            // trailing and leading blank lines are noise in plain lyrics
+            while plain_lines.last().is_some_and(|l| l.is_empty()) {
+                plain_lines.pop();
+            }
+            let start = plain_lines.iter().position(|l| !l.is_empty()).unwrap_or(plain_lines.len());
+            return Lyrics {
+                lines: plain_lines[start..].iter()
+                    .map(|text| LyricLine { time: None, text: text.to_owned() })
+                    .collect(),
+                synced: false,
+            }
+        }
+        // a positive offset shifts the lyrics up (earlier)
+        for line in synced_lines.iter_mut() {
+            let millis = line.time.unwrap_or_default().as_millis() as i64 - offset;
+            line.time = Some(Duration::from_millis(millis.max(0) as u64));
+        }
+        synced_lines.sort_by_key(|l| l.time);
+        Lyrics { lines: synced_lines, synced: true }
+    }
+
+    /// Parses a `mm:ss`, `mm:ss.xx` or `mm:ss:xx` time stamp
+    fn parse_time_stamp(stamp: &str) -> Option<Duration> {
+        let (minutes, seconds) = stamp.split_once(':')?;
+        let minutes = minutes.trim().parse::<u64>().ok()?;
+        // some files use a colon before the hundredths
+        let seconds = seconds.trim().replacen(':', ".", 1);
+        let seconds = seconds.parse::<f64>().ok()?;
+        if !(0.0..60.0).contains(&seconds) {
+            return None
+        }
+        Some(Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds))
+    }
+
+    /// Removes the `<mm:ss.xx>` word time stamps of enhanced lrc files
+    fn strip_word_time_stamps(text: &str) -> String {
+        let mut res = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('<') {
+            match rest[start..].find('>') {
+                Some(end) if Lyrics::parse_time_stamp(&rest[start + 1..start + end]).is_some() => {
+                    res.push_str(&rest[..start]);
+                    rest = &rest[start + end + 1..];
+                },
+                _ => {
+                    res.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        res.push_str(rest);
+        res.trim().to_string()
+    }
+
+    /// Index of the line being sung at _time_, [None] for unsynced lyrics
+    /// or before the first line
+    pub fn current_line(&self, time: Duration) -> Option<usize> {
+        if !self.synced {
+            return None
+        }
+        let next = self.lines.partition_point(|l| l.time.is_some_and(|t| t <= time));
+        next.checked_sub(1)
+    }
+}
+
+/// Stores the state of the lyrics region
+#[derive(Debug, Default)]
+pub struct LyricsView {
+    /// lyrics of the currently playing music
+    pub lyrics: Option<Lyrics>,
+    /// path of the music the lyrics were loaded for
+    pub path: PathBuf,
+    /// first displayed line when not following the playing line
+    pub scroll: usize,
+    /// whether to keep the currently sung line in the middle of the region
+    pub follow: bool,
+}
+
+impl LyricsView {
+    /// Loads the lyrics of the music at _path_ if they are not already loaded
+    pub fn load(&mut self, path: &Path) {
+        if self.path == path {
+            return
+        }
+        self.path = path.to_path_buf();
+        self.lyrics = if path.as_os_str().is_empty() { None } else { Lyrics::load(path) };
+        self.scroll = 0;
+        self.follow = true;
+    }
+
+    pub fn scroll_down(&mut self) {
+        let len = self.lyrics.as_ref().map(|l| l.lines.len()).unwrap_or(0);
+        if self.scroll + 1 < len {
+            self.scroll += 1;
+        }
+        self.follow = false;
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+        self.follow = false;
+    }
+
+    /// Goes back to following the currently sung line
+    pub fn follow(&mut self) {
+        self.follow = true;
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_parse_lrc() {
+        let content = "[ar:Someone]\n[offset:500]\n[00:12.00][00:45.50]Chorus\n[00:01.00]<00:01.00>Hello <00:01.50>world\n";
+        let lyrics = Lyrics::parse_lrc(content);
+        assert!(lyrics.synced);
+        assert_eq!(lyrics.lines.len(), 3);
+        assert_eq!(lyrics.lines[0].text, "Hello world");
+        assert_eq!(lyrics.lines[0].time, Some(Duration::from_millis(500)));
+        assert_eq!(lyrics.lines[1].time, Some(Duration::from_millis(11500)));
+        assert_eq!(lyrics.lines[2].text, "Chorus");
+    }
+
+    #[test]
+    fn test_current_line() {
+        let lyrics = Lyrics::parse_lrc("[00:01.00]a\n[00:05.00]b\n[00:09.00]c");
+        assert_eq!(lyrics.current_line(Duration::from_millis(500)), None);
+        assert_eq!(lyrics.current_line(Duration::from_secs(5)), Some(1));
+        assert_eq!(lyrics.current_line(Duration::from_secs(100)), Some(2));
+    }
+
+    #[test]
+    fn test_unsynced_fallback() {
+        let lyrics = Lyrics::parse_lrc("\nfirst line\n\nsecond line\n\n");
+        assert!(!lyrics.synced);
+        assert_eq!(lyrics.lines.len(), 3);
+        assert_eq!(lyrics.current_line(Duration::from_secs(1)), None);
+    }
+}
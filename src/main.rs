@@ -1,4 +1,4 @@
-use std::{env, io::{self, stdout}, path::PathBuf};
+use std::{env, io::stdout, path::PathBuf};
 
 use utils::RunStatus;
 use zbus::{proxy, Connection, Result};
@@ -7,13 +7,36 @@ mod utils;
 mod ui;
 mod parser;
 mod fuzzy_search;
+mod queue;
+mod playlist;
+mod lyrics;
+mod cache;
+mod watcher;
+mod library;
+mod query;
+mod albums;
+mod keybinds;
+mod theme;
+mod columns;
+mod layout;
+mod mpris;
+mod events;
+mod supervisor;
+mod messages;
+mod shuffle;
+mod history;
+mod scrobble;
+mod session;
 use crossterm::{
-    event::{self, Event},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 use ratatui::{self, Terminal, backend::CrosstermBackend};
 
+use cache::LibraryCache;
+use history::History;
+use scrobble::ScrobbleQueue;
+use session::Session;
 use ui::Music;
 use parser::*;
 #[allow(unused_imports)]
@@ -33,6 +56,22 @@ pub struct Metadata {
     cover: Picture,
 }
 
+/// [Music] as sent by the server, which only knows about these fields
+#[derive(serde::Deserialize, serde::Serialize, zbus::zvariant::Type, Debug, Default, Clone)]
+pub struct PlayingMusic {
+    title: String,
+    length: std::time::Duration,
+    path: PathBuf,
+    artist: String,
+    genre: String,
+}
+
+impl From<PlayingMusic> for Music {
+    fn from(value: PlayingMusic) -> Self {
+        Music::new(value.title, value.path, value.length, value.artist, value.genre)
+    }
+}
+
 #[proxy(
     interface = "org.zbus.mplayerServer",
     default_service = "org.zbus.mplayer",
@@ -69,7 +108,7 @@ pub trait Server {
     fn volume(&self, amount:f64) -> Result<RunStatus>;
     fn metadata(&self) -> Result<Metadata>;
     /// Gets the currently playing [Music]
-    fn playing(&self) -> Result<Music>;
+    fn playing(&self) -> Result<PlayingMusic>;
     fn toggle_mute(&self) -> Result<RunStatus>;
 }
 
@@ -86,83 +125,157 @@ pub fn init_panic_hook() {
 #[async_std::main]
 async fn main() -> Result<()> {
 
-    let connection = Connection::session().await.unwrap_or_else(|_|{
-        panic!("Could not connect to the bus address, aborting...");
-    });
-    let proxy = ServerProxy::new(&connection).await?;
-    proxy.status().await.unwrap_or_else(|_|{
-        panic!("Mplayer server is not Up, aborting..");
-    });
+    let args: Vec<String> = env::args().collect();
+    // commands that work without the server
+    if args.get(1).is_some_and(|arg| arg == "scrobbles") {
+        if let Err(error) = scrobble::dump(&args[2..]) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return Ok(())
+    }
 
-    init_panic_hook();
-    enable_raw_mode()?;
-    stdout().execute(EnterAlternateScreen)?;
-    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let connection = Connection::session().await.inspect_err(|error| {
+        eprintln!("Could not connect to the session bus: {}", error);
+    })?;
+    // the server may be down or restart at any time, the supervisor
+    // reconnects to it
+    let proxy = ServerProxy::new(&connection).await?;
 
-    let args: Vec<String> = env::args().collect();
-    let wrapper = if args.len() > 1 {
-        Config::parse_config(&args[1])
+    // the config path is the only argument that is not a flag
+    let restore = !args.iter().any(|arg| arg == "--no-restore");
+    let wrapper = if let Some(path) = args.iter().skip(1).find(|arg| !arg.starts_with("--")) {
+        Config::parse_config(path)
     }else {
         let mut home = env::var("HOME").unwrap().to_string();
         home.push_str("/.config/mplayer-client/config.toml");
         Config::parse_config(&home)
     };
 
-    let mut ui = ui::UI::default(proxy);
+    // bad keybinds are reported before the terminal is taken over
+    let keymap = keybinds::Keymap::new(wrapper.keybinds.as_ref()).unwrap_or_else(|errors| {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        panic!("Invalid keybinds in the config, aborting...");
+    });
+    let style = theme::load(wrapper.theme.as_ref()).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        panic!("Invalid theme in the config, aborting...");
+    });
+    let layout = layout::ScreenLayout::new(wrapper.layout.as_ref()).unwrap_or_else(|errors| {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        panic!("Invalid layout in the config, aborting...");
+    });
     let config = wrapper.config.unwrap_or_default();
+    // the library is read in the background, where a panic would leave
+    // the terminal behind while the UI keeps drawing
+    config.check_roots().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        panic!("Invalid music directories in the config, aborting...");
+    });
+    let columns = columns::Column::from_config(
+        config.columns.as_deref(), config.index_column.unwrap_or(false)
+    ).unwrap_or_else(|errors| {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        panic!("Invalid columns in the config, aborting...");
+    });
+
+    // the client works without it, so failing to serve it is not fatal
+    let mpris = if config.mpris.unwrap_or(false) {
+        mpris::Mpris::serve(&connection).await.map_err(|error| {
+            eprintln!("Could not expose the player over MPRIS: {}", error);
+        }).ok()
+    } else {
+        None
+    };
+
+    init_panic_hook();
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+
+    let mut ui = ui::UI::default(proxy);
+    ui.keymap(keymap);
+    ui.style = style;
+    ui.columns(columns);
+    ui.layout(layout);
+
+    // terminal events, ticks, player states, library changes and MPRIS
+    // requests all go through a single channel
+    let (events, app_events) = events::app_channel();
+    events::spawn_terminal(events.clone());
+    events::spawn_ticks(events.clone(), events::TICK);
+
+    // rendering works off the batch of a background task, so a slow server
+    // does not hold the input back
+    let interval = std::time::Duration::from_millis(config.poll_interval.unwrap_or(1000));
+    let (fetched, fetches) = async_std::channel::unbounded();
+    if let Err(error) = ui.watch_server(interval, fetched).await {
+        utils::report(&format!("state: polling on every render, {}", error));
+    }
+    supervisor::spawn_supervisor(ServerProxy::new(&connection).await?, fetches, events.clone());
+    if let Some((mpris, receiver)) = mpris {
+        ui.mpris(mpris.spawn());
+        events::forward(receiver, events.clone(), events::AppEvent::Mpris);
+    }
 
-    let mut musics = config.extract_music();
-    musics.sort(config.sorting);
+    // the cached library is displayed right away while the
+    // music directory is checked for changes in the background
+    let cache_path = LibraryCache::default_path();
+    let cache = cache_path.as_deref().map(LibraryCache::load).unwrap_or_default();
+    // sorted in the order of the config once given to the UI
+    let musics = ui::Musics::new(cache.musics());
+
+    let (library_sender, library_receiver) = cache::library_channel();
+    cache.spawn_refresh(config.clone(), cache_path, library_sender.clone());
+    // kept alive for the whole session, changes stop being watched once dropped
+    let _watcher = if config.watch.unwrap_or(true) {
+        watcher::spawn_watcher(config.clone(), library_sender).ok()
+    } else {
+        None
+    };
 
     ui.update_from_config(&config);
+    // read before sorting, the play counts taking part in some orders
+    ui.history(History::default_path().map(|path| History::load(&path)).unwrap_or_default());
+    if let Some(path) = ScrobbleQueue::default_path() {
+        ui.scrobbles(ScrobbleQueue::new(path));
+    }
     ui.musics(musics);
+    // the session is still saved with `--no-restore`, for the next start
+    if let Some(path) = Session::default_path() {
+        if let Some(session) = Session::load(&path).filter(|_| restore) {
+            ui.restore_session(session);
+        }
+        ui.session_path(path);
+    }
+    events::forward(library_receiver, events, events::AppEvent::Library);
     ui.restore_state();
 
-    let mut should_quit = false;
-    while !should_quit {
+    terminal.draw(|frame| {
+        ui.render(frame);
+    })?;
+    while let Ok(event) = app_events.recv().await {
+        // everything already there is handled before rendering once
+        let mut should_quit = ui.handle_event(event);
+        while let Ok(event) = app_events.try_recv() {
+            should_quit |= ui.handle_event(event);
+        }
+        if should_quit {
+            ui.close();
+            break
+        }
         terminal.draw(|frame| {
             ui.render(frame);
         })?;
-        should_quit = handle_events(&mut ui)?;
     }
 
     disable_raw_mode()?;
     stdout().execute(LeaveAlternateScreen)?;
     Ok(())
 }
-
-fn handle_events<'a>(ui: &mut ui::UI<'a>) -> io::Result<bool>{
-    if event::poll(std::time::Duration::from_millis(50))? {
-        if let Event::Key(key) = event::read()? {
-            ui::Region::handle_global(ui, &key);
-            // keybind depend on reagion
-            match ui.region {
-                // list region
-                ui::Region::List =>{
-                    if ui::Region::handle_list(ui, &key).is_ok_and(|x| x == true) {
-                        return Ok(true)
-                    }
-                }
-                // seeker region
-                ui::Region::Seeker => {
-                    if ui::Region::handle_seeker(ui, &key).is_ok_and(|x| x == true) {
-                        return Ok(true)
-                    }
-                }
-                // volume region
-                ui::Region::Volume => {
-                    if ui::Region::handle_volume(ui, &key).is_ok_and(|x| x == true) {
-                        return Ok(true)
-                    }
-                }
-                // actions region
-                ui::Region::Action => {
-                    if ui::Region::handle_action(ui, &key).is_ok_and(|x| x == true) {
-                        return Ok(true)
-                    }
-                }
-            }
-        }
-    }
-    return Ok(false);
-}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Local};
+
+use crate::states::{Outcome, ServerCommand};
+
+/// Messages kept in the history, the oldest ones are dropped first
+const MAX_MESSAGES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+/// A line of the status bar and of the message history
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub text: String,
+}
+
+impl Message {
+    pub fn info(text: impl Into<String>) -> Self {
+        Message { time: Local::now(), level: Level::Info, text: text.into() }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Message { time: Local::now(), level: Level::Error, text: text.into() }
+    }
+
+    /// What the server made of a command, e.g. `play song.mp3: wrong path`
+    pub fn outcome(outcome: &Outcome) -> Self {
+        let action = outcome.command.describe();
+        match &outcome.result {
+            Ok(status) if status.is_ok() => Message::info(action),
+            Ok(status) => Message::error(format!("{}: {}", action, status.message())),
+            Err(error) => Message::error(format!("{}: {}", action, error)),
+        }
+    }
+
+    /// Time of the message, as `HH:MM:SS`
+    pub fn timestamp(&self) -> String {
+        self.time.format("%H:%M:%S").to_string()
+    }
+}
+
+impl ServerCommand {
+    /// Short description of the command for the status bar
+    pub fn describe(&self) -> String {
+        match self {
+            ServerCommand::Play(path) => format!(
+                "play {}",
+                path.file_name().map(|name| name.to_string_lossy()).unwrap_or_else(|| path.to_string_lossy())
+            ),
+            ServerCommand::End => "stop".to_string(),
+            ServerCommand::Seek(amount) => format!("seek {:+}s", amount),
+            ServerCommand::Resume => "resume".to_string(),
+            ServerCommand::Pause => "pause".to_string(),
+            ServerCommand::Volume(level) => format!("volume {:.0}%", level),
+            ServerCommand::ToggleMute => "toggle mute".to_string(),
+        }
+    }
+}
+
+/// History of the messages, shown in a scrollable popup, the newest first
+#[derive(Debug, Default)]
+pub struct Messages {
+    entries: VecDeque<Message>,
+    /// whether the history popup is shown
+    pub open: bool,
+    /// number of messages scrolled past, from the newest one
+    pub scroll: usize,
+}
+
+impl Messages {
+    pub fn push(&mut self, message: Message) {
+        if self.entries.len() == MAX_MESSAGES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(message);
+        // the lines seen stay in place
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.entries.len() - 1);
+        }
+    }
+
+    /// The message of the status bar
+    pub fn last(&self) -> Option<&Message> {
+        self.entries.back()
+    }
+
+    /// Messages from the newest one
+    pub fn newest_first(&self) -> impl Iterator<Item = &Message> {
+        self.entries.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.scroll = 0;
+    }
+
+    pub fn scroll_down(&mut self) {
+        if self.scroll + 1 < self.entries.len() {
+            self.scroll += 1;
+        }
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::utils::{RunStatus, StatusOption};
+
+    #[test]
+    fn test_outcome() {
+        let play = ServerCommand::Play("/music/song.mp3".into());
+        let ok = Message::outcome(&Outcome { command: play.clone(), result: Ok(RunStatus::new(String::new(), StatusOption::Ok)) });
+        assert_eq!((ok.level, ok.text.as_str()), (Level::Info, "play song.mp3"));
+        let wrong = Message::outcome(&Outcome {
+            command: play.clone(), result: Ok(RunStatus::new(String::new(), StatusOption::WrongPath)),
+        });
+        assert_eq!((wrong.level, wrong.text.as_str()), (Level::Error, "play song.mp3: wrong path"));
+        let told = Message::outcome(&Outcome {
+            command: ServerCommand::Seek(-5.0), result: Ok(RunStatus::new("no stream\n".into(), StatusOption::CoudntSeek)),
+        });
+        assert_eq!(told.text, "seek -5s: no stream");
+        assert_eq!(ServerCommand::Volume(57.0).describe(), "volume 57%");
+        let failed = Message::outcome(&Outcome { command: play, result: Err("no reply".into()) });
+        assert_eq!((failed.level, failed.text.as_str()), (Level::Error, "play song.mp3: no reply"));
+        assert_eq!(ok.timestamp().len(), 8);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut messages = Messages::default();
+        for i in 0..MAX_MESSAGES + 10 {
+            messages.push(Message::info(i.to_string()));
+        }
+        assert_eq!(messages.len(), MAX_MESSAGES);
+        assert_eq!(messages.last().unwrap().text, (MAX_MESSAGES + 9).to_string());
+        assert_eq!(messages.newest_first().last().unwrap().text, "10");
+        messages.scroll_down();
+        messages.push(Message::error("late"));
+        // the line looked at stays in place
+        assert_eq!(messages.newest_first().nth(messages.scroll).unwrap().text, (MAX_MESSAGES + 8).to_string());
+        messages.toggle();
+        assert!(messages.open);
+        assert_eq!(messages.scroll, 0);
+    }
+}
@@ -0,0 +1,483 @@
+use std::{collections::HashMap, hash::{DefaultHasher, Hash, Hasher}, os::unix::ffi::OsStrExt, path::Path, time::{Duration, Instant}};
+
+use async_std::{channel::{self, Receiver, Sender}, task};
+use zbus::{fdo, interface, zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value}, Connection, SignalContext};
+
+use crate::states::Status;
+use crate::ui::{Music, Repeat};
+use crate::utils;
+
+/// Name the client is reachable at by MPRIS clients like playerctl
+pub const BUS_NAME: &str = "org.mpris.MediaPlayer2.mplayer_client";
+pub const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+/// Track id of the metadata when nothing is playing
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// `file://` URI of _path_, every byte but the unreserved ones and `/`
+/// being percent-encoded
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// Request of an MPRIS client, run by the UI as if its key was pressed
+#[derive(Debug, Clone, PartialEq)]
+pub enum MprisCommand {
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// seeks by the given microseconds, backward if negative
+    Seek(i64),
+    /// seeks to the given microseconds of the playing music
+    SetPosition(i64),
+    /// sets the volume, between 0 and 1
+    Volume(f64),
+}
+
+/// What the MPRIS clients are told about the player
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlayerSnapshot {
+    pub status: Status,
+    /// playing music, [None] if the player has nothing to play
+    pub music: Option<Music>,
+    pub position: Duration,
+    /// between 0 and 1
+    pub volume: f64,
+    pub repeat: Repeat,
+    pub shuffle: bool,
+}
+
+impl PlayerSnapshot {
+    fn playback_status(&self) -> &'static str {
+        match self.status {
+            Status::Playing => "Playing",
+            Status::Pausing => "Paused",
+            Status::Stopping => "Stopped",
+        }
+    }
+
+    fn loop_status(&self) -> &'static str {
+        match self.repeat {
+            Repeat::ThisMusic => "Track",
+            Repeat::AllMusics => "Playlist",
+            Repeat::Dont => "None",
+        }
+    }
+
+    /// Track id of the playing music, derived from its path
+    fn track_id(&self) -> OwnedObjectPath {
+        let path = match &self.music {
+            Some(music) => {
+                let mut hasher = DefaultHasher::new();
+                music.path.hash(&mut hasher);
+                format!("/org/mplayer_client/track/{:x}", hasher.finish())
+            },
+            None => String::from(NO_TRACK),
+        };
+        ObjectPath::try_from(path).map(OwnedObjectPath::from)
+            .unwrap_or_else(|_| OwnedObjectPath::from(ObjectPath::from_static_str_unchecked(NO_TRACK)))
+    }
+
+    /// `xesam:` and `mpris:` fields of the playing music
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut fields: Vec<(&str, Value)> = vec![("mpris:trackid", Value::from(self.track_id()))];
+        if let Some(music) = &self.music {
+            fields.extend([
+                ("mpris:length", Value::from(music.length.as_micros() as i64)),
+                ("xesam:title", Value::from(music.title.as_str())),
+                ("xesam:artist", Value::from(vec![music.artist.as_str()])),
+                ("xesam:albumArtist", Value::from(vec![music.album_artist.as_str()])),
+                ("xesam:album", Value::from(music.album.as_str())),
+                ("xesam:genre", Value::from(vec![music.genre.as_str()])),
+                ("xesam:url", Value::from(file_uri(&music.path))),
+            ]);
+            if let Some(track) = music.track {
+                fields.push(("xesam:trackNumber", Value::from(track as i32)));
+            }
+            if let Some(disc) = music.disc {
+                fields.push(("xesam:discNumber", Value::from(disc as i32)));
+            }
+        }
+        fields.into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value.try_to_owned().ok()?)))
+            .collect()
+    }
+}
+
+/// The `org.mpris.MediaPlayer2` interface
+struct Root;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "mplayer-client"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec![String::from("file")]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface, sending what it is asked
+/// to the UI and answering from the last [PlayerSnapshot]
+struct Player {
+    commands: Sender<MprisCommand>,
+    snapshot: PlayerSnapshot,
+}
+
+impl Player {
+    fn send(&self, command: MprisCommand) -> fdo::Result<()> {
+        self.commands.try_send(command).map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.send(MprisCommand::Next)
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.send(MprisCommand::Previous)
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.send(MprisCommand::Pause)
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        self.send(MprisCommand::PlayPause)
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.send(MprisCommand::Stop)
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        self.send(MprisCommand::Play)
+    }
+
+    fn seek(&self, offset: i64) -> fdo::Result<()> {
+        self.send(MprisCommand::Seek(offset))
+    }
+
+    /// Ignored unless _track_id_ is the playing music, as the spec asks
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        if track_id.as_str() != self.snapshot.track_id().as_str() || position < 0 {
+            return Ok(())
+        }
+        self.send(MprisCommand::SetPosition(position))
+    }
+
+    fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(String::from("musics are only played from the library")))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(context: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        self.snapshot.playback_status()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> &str {
+        self.snapshot.loop_status()
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.snapshot.shuffle
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        self.snapshot.metadata()
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.snapshot.volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, volume: f64) -> fdo::Result<()> {
+        self.send(MprisCommand::Volume(volume.clamp(0.0, 1.0)))
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.snapshot.position.as_micros() as i64
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.snapshot.music.is_some()
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// The MPRIS service of the client
+pub struct Mpris {
+    connection: Connection,
+    /// when the last snapshot was published, to tell seeking from playing
+    published: Instant,
+}
+
+impl Mpris {
+    /// Serves the MPRIS interfaces on _connection_, under [BUS_NAME] or an
+    /// instance of it if another client already took it, the commands of
+    /// the MPRIS clients are received from the returned channel
+    pub async fn serve(connection: &Connection) -> zbus::Result<(Mpris, Receiver<MprisCommand>)> {
+        let (sender, receiver) = channel::unbounded();
+        let server = connection.object_server();
+        server.at(OBJECT_PATH, Root).await?;
+        server.at(OBJECT_PATH, Player { commands: sender, snapshot: PlayerSnapshot::default() }).await?;
+        if connection.request_name(BUS_NAME).await.is_err() {
+            connection.request_name(format!("{}.instance{}", BUS_NAME, std::process::id())).await?;
+        }
+        let mpris = Mpris { connection: connection.to_owned(), published: Instant::now() };
+        Ok((mpris, receiver))
+    }
+
+    /// Publishes the snapshots sent to the returned channel in the
+    /// background, so a slow bus does not hold the UI back
+    pub fn spawn(mut self) -> Sender<PlayerSnapshot> {
+        let (sender, receiver) = channel::unbounded::<PlayerSnapshot>();
+        task::spawn(async move {
+            while let Ok(mut snapshot) = receiver.recv().await {
+                // only the latest snapshot matters
+                while let Ok(newer) = receiver.try_recv() {
+                    snapshot = newer;
+                }
+                if let Err(error) = self.update(snapshot).await {
+                    utils::report(&format!("mpris: {}", error));
+                }
+            }
+        });
+        sender
+    }
+
+    /// Publishes _snapshot_, signaling the properties that changed and
+    /// positions that jumped
+    pub async fn update(&mut self, snapshot: PlayerSnapshot) -> zbus::Result<()> {
+        let elapsed = self.published.elapsed();
+        self.published = Instant::now();
+        let iface = self.connection.object_server().interface::<_, Player>(OBJECT_PATH).await?;
+        let mut player = iface.get_mut().await;
+        let old = std::mem::replace(&mut player.snapshot, snapshot);
+        let new = &player.snapshot;
+        if old == *new {
+            return Ok(())
+        }
+        let context = iface.signal_context();
+        if old.status != new.status {
+            player.playback_status_changed(context).await?;
+        }
+        if old.music != new.music {
+            player.metadata_changed(context).await?;
+            player.can_seek_changed(context).await?;
+        }
+        if old.volume != new.volume {
+            player.volume_changed(context).await?;
+        }
+        if old.repeat != new.repeat {
+            player.loop_status_changed(context).await?;
+        }
+        if old.shuffle != new.shuffle {
+            player.shuffle_changed(context).await?;
+        }
+        // the position is expected to move by the time spent playing
+        let expected = match old.status {
+            Status::Playing => old.position + elapsed,
+            _ => old.position,
+        };
+        let drift = new.position.abs_diff(expected);
+        if old.music == new.music && new.music.is_some() && drift > Duration::from_secs(2) {
+            Player::seeked(context, new.position.as_micros() as i64).await?;
+        }
+        Ok(())
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::{io::{BufRead, BufReader}, path::PathBuf, process::{Child, Command, Stdio}};
+    #[allow(unused_imports)]
+    use async_std::task::block_on;
+
+    /// `dbus-daemon --session` of its own, killed once dropped
+    #[allow(dead_code)]
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        /// [None] if dbus-daemon can't be run
+        #[allow(dead_code)]
+        fn spawn() -> Option<PrivateBus> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address=1"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn().ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+            Some(PrivateBus { daemon, address: address.trim().to_string() })
+        }
+
+        #[allow(dead_code)]
+        async fn connect(&self) -> Connection {
+            zbus::connection::Builder::address(self.address.as_str()).unwrap().build().await.unwrap()
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[test]
+    fn test_metadata() {
+        let snapshot = PlayerSnapshot::default();
+        assert_eq!(snapshot.track_id().as_str(), NO_TRACK);
+        assert_eq!(snapshot.metadata().len(), 1);
+        let music = Music { length: Duration::from_secs(90), ..Music::test("Song") };
+        let snapshot = PlayerSnapshot { music: Some(music), ..Default::default() };
+        let metadata = snapshot.metadata();
+        assert_eq!(metadata["xesam:title"], OwnedValue::from(zbus::zvariant::Str::from("Song")));
+        assert_eq!(metadata["mpris:length"], OwnedValue::from(90_000_000i64));
+        assert_ne!(snapshot.track_id().as_str(), NO_TRACK);
+        assert_eq!(file_uri(Path::new("/music/a b#1é.flac")), "file:///music/a%20b%231%C3%A9.flac");
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon to own the MPRIS name"]
+    fn test_service() {
+        let bus = PrivateBus::spawn().expect("dbus-daemon could not be run");
+        block_on(async {
+            let server = bus.connect().await;
+            let (mut mpris, commands) = Mpris::serve(&server).await.unwrap();
+            let client = bus.connect().await;
+            let player: zbus::Proxy = zbus::proxy::Builder::new(&client)
+                .destination(BUS_NAME).unwrap()
+                .path(OBJECT_PATH).unwrap()
+                .interface("org.mpris.MediaPlayer2.Player").unwrap()
+                .cache_properties(zbus::proxy::CacheProperties::No)
+                .build().await.unwrap();
+
+            player.call_method("PlayPause", &()).await.unwrap();
+            assert_eq!(commands.recv().await, Ok(MprisCommand::PlayPause));
+            player.call_method("Seek", &(-5_000_000i64)).await.unwrap();
+            assert_eq!(commands.recv().await, Ok(MprisCommand::Seek(-5_000_000)));
+            player.set_property("Volume", 1.5).await.unwrap();
+            assert_eq!(commands.recv().await, Ok(MprisCommand::Volume(1.0)));
+
+            let music = Music { length: Duration::from_secs(90), ..Music::test("Song") };
+            mpris.update(PlayerSnapshot {
+                status: Status::Playing,
+                music: Some(music),
+                position: Duration::from_secs(12),
+                volume: 0.5,
+                ..Default::default()
+            }).await.unwrap();
+            let status: String = player.get_property("PlaybackStatus").await.unwrap();
+            assert_eq!(status, "Playing");
+            let volume: f64 = player.get_property("Volume").await.unwrap();
+            assert_eq!(volume, 0.5);
+            let position: i64 = player.get_property("Position").await.unwrap();
+            assert_eq!(position, 12_000_000);
+            let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").await.unwrap();
+            let title: String = metadata["xesam:title"].try_clone().unwrap().try_into().unwrap();
+            assert_eq!(title, "Song");
+
+            // only the playing music can be seeked into
+            player.call_method("SetPosition", &(ObjectPath::try_from(NO_TRACK).unwrap(), 0i64)).await.unwrap();
+            let track_id = metadata["mpris:trackid"].try_clone().unwrap();
+            let track_id: OwnedObjectPath = track_id.try_into().unwrap();
+            player.call_method("SetPosition", &(track_id, 30_000_000i64)).await.unwrap();
+            assert_eq!(commands.recv().await, Ok(MprisCommand::SetPosition(30_000_000)));
+        });
+    }
+}
@@ -1,77 +1,245 @@
 use crate::ui::{Music, Musics};
-use basic_toml;
 use crate::ui::Repeat;
+use crate::columns::ColumnConfig;
+use crate::keybinds::KeybindsConfig;
+use crate::layout::LayoutConfig;
+use crate::library::LibraryFilter;
+use crate::theme::ThemeConfig;
+use crate::utils;
+use ratatui::style::{Color, Modifier, Style};
 use serde::{self, Deserialize, Serialize};
+use std::{path::PathBuf, str::FromStr};
 
-#[derive(Deserialize, Serialize, Debug, Copy, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, Default, PartialEq)]
 pub enum Sorting{
     #[default]
     ByTitleAscending,
     ByTitleDescending,
     ByDurationAscending,
     ByDurationDescending,
+    /// by album, then disc and track number
+    ByAlbum,
+    /// most played first, going by the play history
+    MostPlayed,
+    /// last added to the music directory first
+    RecentlyAdded,
     Shuffle,
+    /// random albums, each one in track order
+    AlbumShuffle,
+    /// no artist twice in a row
+    ArtistShuffle,
+    /// rarely played and highly rated musics first
+    WeightedShuffle,
+}
+
+impl Sorting {
+    /// Whether the order is drawn at random
+    pub fn is_shuffle(&self) -> bool {
+        matches!(self, Sorting::Shuffle | Sorting::AlbumShuffle | Sorting::ArtistShuffle | Sorting::WeightedShuffle)
+    }
+}
+
+/// A style written in the config, colors are either names (`yellow`,
+/// `light-red`), indexes (`208`) or hex codes (`#ffaa00`)
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct StyleConfig {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    /// e.g. `bold`, `italic`, `underlined`, `reversed`
+    pub modifiers: Option<Vec<String>>,
+}
+
+impl StyleConfig {
+    /// Builds the ratatui [Style], invalid colors and modifiers are
+    /// reported and ignored
+    pub fn style(&self) -> Style {
+        let (style, errors) = self.parse();
+        for error in errors {
+            utils::report(&format!("Ignoring {}", error));
+        }
+        style
+    }
+
+    /// Builds the ratatui [Style], failing on the first invalid color or modifier
+    pub fn try_style(&self) -> Result<Style, String> {
+        match self.parse() {
+            (style, errors) if errors.is_empty() => Ok(style),
+            (_, errors) => Err(errors.join(", ")),
+        }
+    }
+
+    /// The style out of the valid parts, along with what is invalid
+    fn parse(&self) -> (Style, Vec<String>) {
+        let mut style = Style::default();
+        let mut errors = vec![];
+        let mut color = |c: &String| match Color::from_str(c) {
+            Ok(color) => Some(color),
+            Err(_) => {
+                errors.push(format!("invalid color {:?}", c));
+                None
+            },
+        };
+        if let Some(fg) = self.fg.as_ref().and_then(&mut color) {
+            style = style.fg(fg);
+        }
+        if let Some(bg) = self.bg.as_ref().and_then(&mut color) {
+            style = style.bg(bg);
+        }
+        for modifier in self.modifiers.iter().flatten() {
+            match StyleConfig::modifier(modifier) {
+                Some(modifier) => style = style.add_modifier(modifier),
+                None => errors.push(format!("invalid modifier {:?}", modifier)),
+            }
+        }
+        (style, errors)
+    }
+
+    fn modifier(name: &str) -> Option<Modifier> {
+        match name.to_lowercase().replace('-', "_").as_str() {
+            "bold" => Some(Modifier::BOLD),
+            "dim" => Some(Modifier::DIM),
+            "italic" => Some(Modifier::ITALIC),
+            "underline" | "underlined" => Some(Modifier::UNDERLINED),
+            "slow_blink" => Some(Modifier::SLOW_BLINK),
+            "rapid_blink" => Some(Modifier::RAPID_BLINK),
+            "reversed" => Some(Modifier::REVERSED),
+            "hidden" => Some(Modifier::HIDDEN),
+            "crossed_out" => Some(Modifier::CROSSED_OUT),
+            _ => None,
+        }
+    }
+}
+
+impl From<Style> for StyleConfig {
+    fn from(value: Style) -> Self {
+        let modifiers = value.add_modifier.iter_names()
+            .map(|(name, _)| name.to_lowercase())
+            .collect::<Vec<String>>();
+        StyleConfig {
+            fg: value.fg.map(|c| c.to_string()),
+            bg: value.bg.map(|c| c.to_string()),
+            modifiers: (!modifiers.is_empty()).then_some(modifiers),
+        }
+    }
+}
+
+/// (De)serializes a [Style] as a [StyleConfig], for `#[serde(with)]`
+pub mod style_config {
+    use ratatui::style::Style;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::StyleConfig;
+
+    pub fn serialize<S: Serializer>(style: &Style, serializer: S) -> Result<S::Ok, S::Error> {
+        StyleConfig::from(*style).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Style, D::Error> {
+        StyleConfig::deserialize(deserializer)?.try_style().map_err(D::Error::custom)
+    }
+}
+
+/// A directory of the music library
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Root {
+    pub path: String,
+    /// glob patterns relative to the root, when set only the matching files are kept
+    pub include: Option<Vec<String>>,
+    /// glob patterns relative to the root of the files and directories to leave out
+    pub exclude: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Config {
+    /// single music directory, kept alongside _roots_ for older configs
     pub path: Option<String>,
+    /// music directories along with their include/exclude patterns
+    pub roots: Option<Vec<Root>>,
+    /// extensions the musics are allowed to have, any extension is allowed if unset
+    pub extensions: Option<Vec<String>>,
+    /// how many directories deep musics are looked for below a root
+    pub max_depth: Option<usize>,
     pub sorting: Option<Sorting>,
-    pub repeat: Option<Repeat>
+    pub repeat: Option<Repeat>,
+    /// directories containing .m3u/.m3u8 playlists
+    pub playlists: Option<Vec<String>>,
+    /// whether to display the lyrics region
+    pub lyrics: Option<bool>,
+    /// whether to watch the music directory for changes
+    pub watch: Option<bool>,
+    /// style of the characters matched by the search
+    pub highlight: Option<StyleConfig>,
+    /// maximum number of search results, unlimited if unset
+    pub search_limit: Option<usize>,
+    /// whether to expose the player to desktop media keys and tools like
+    /// playerctl over MPRIS
+    pub mpris: Option<bool>,
+    /// milliseconds between two polls of the player state, signals of the
+    /// server being picked up in between
+    pub poll_interval: Option<u64>,
+    /// seed of the `Shuffle` order, the same seed giving the same orders
+    pub shuffle_seed: Option<u64>,
+    /// whether to display the index of the musics in the list, when _columns_ is unset
+    pub index_column: Option<bool>,
+    /// columns of the music list, title, artist and duration if unset
+    pub columns: Option<Vec<ColumnConfig>>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Wrapper {
-    pub config: Option<Config>
+    pub config: Option<Config>,
+    /// keys bound to commands, per context
+    pub keybinds: Option<KeybindsConfig>,
+    /// built-in theme along with the fields overriding it
+    pub theme: Option<ThemeConfig>,
+    /// panes of the screen, as a preset or rows of panes
+    pub layout: Option<LayoutConfig>,
 }
 
 impl Config {
-    fn visit_dirs(dir: &std::path::Path) -> Musics {
-        let mut musics = vec![];
-        if dir.is_dir() {
-            for entry in std::fs::read_dir(dir).unwrap() {
-                let entry = entry.unwrap();
-                let path = entry.path();
-                if path.is_dir() {
-                    Config::visit_dirs(&path).que.iter().for_each(|v| {
-                        match Music::simple_new(v.path.to_owned()) {
-                            Some(music) => {
-                                musics.push(music)
-                            },
-                            None => {} 
-                        }
-                    });
-                } else {
-                    match Music::simple_new(path) {
-                        Some(music) => {
-                            musics.push(music)
-                        },
-                        None => {} 
-                    }
-                }
-            }
-        }
-        Musics::new(musics)
-    }
-
     pub fn parse_config(_path: &str) -> Wrapper {
-        let conf_content = std::fs::read_to_string(_path).expect(
-            &format!("Couldn't read config path '{}', aborting...", _path)
+        let conf_content = std::fs::read_to_string(_path).unwrap_or_else(|_|
+            panic!("Couldn't read config path '{}', aborting...", _path)
         );
-        let skeleton: Wrapper = basic_toml::from_str(&conf_content).expect(
-            &format!("Couldn't parse config path '{}', aborting...", _path)
+        let skeleton: Wrapper = basic_toml::from_str(&conf_content).unwrap_or_else(|_|
+            panic!("Couldn't parse config path '{}', aborting...", _path)
         );
         skeleton
     }
 
+    /// Checks that there is a music directory to read the library from,
+    /// before the library is read in the background
+    pub fn check_roots(&self) -> Result<(), String> {
+        if self.path.is_none() && self.roots.as_ref().is_none_or(|roots| roots.is_empty()) {
+            return Err(String::from("No music directory, set `path` or add a `[[config.roots]]`"))
+        }
+        Ok(())
+    }
+
+    /// Every music directory, the _path_ one included, see [Config::check_roots]
+    pub fn roots(&self) -> Vec<Root> {
+        let mut roots = self.roots.clone().unwrap_or_default();
+        if let Some(path) = &self.path {
+            roots.insert(0, Root { path: path.to_owned(), ..Default::default() });
+        }
+        if roots.is_empty() {
+            panic!("playist path is None!")
+        }
+        roots
+    }
+
+    /// Paths of every file of the music library
+    pub fn music_files(&self) -> Vec<PathBuf> {
+        LibraryFilter::new(self).files()
+    }
+
+    #[allow(dead_code)]
+    /// Probes every music of the music directory synchronously,
+    /// the [crate::cache::LibraryCache] should be preferred
     pub fn extract_music(&self) -> Musics {
-        let musics = match self.path.to_owned() {
-            Some(p) => {
-                Config::visit_dirs(std::path::Path::new(&p))
-            },
-            None => panic!("playist path is None!"),
-        };
-        return musics
+        Musics::new(
+            self.music_files().into_iter().filter_map(Music::simple_new).collect()
+        )
     }
 }
 
@@ -83,6 +251,13 @@ mod test {
         Config::parse_config("./config.toml");
     }
 
+    #[test]
+    fn test_check_roots() {
+        assert!(Config::default().check_roots().is_err());
+        assert!(Config { roots: Some(vec![]), ..Default::default() }.check_roots().is_err());
+        assert!(Config { path: Some(String::from("/music")), ..Default::default() }.check_roots().is_ok());
+    }
+
     #[test]
     fn test_playlist_load() {
         let res = Config::parse_config("./config.toml");
@@ -96,6 +271,53 @@ mod test {
         musics.sort(res.config.clone().unwrap().sorting);
     }
 
+    #[test]
+    fn test_roots() {
+        let config = Config::parse_config("./config.toml").config.unwrap();
+        let roots = config.roots();
+        assert_eq!(roots.len(), 3);
+        assert_eq!(roots[0].path, "/home/yassine/Music");
+        assert_eq!(roots[1].exclude.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_keybinds() {
+        let keybinds = Config::parse_config("./config.toml").keybinds;
+        assert!(crate::keybinds::Keymap::new(keybinds.as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_columns() {
+        let config = Config::parse_config("./config.toml").config.unwrap();
+        let columns = crate::columns::Column::from_config(config.columns.as_deref(), false).unwrap();
+        assert_eq!(columns.len(), 5);
+    }
+
+    #[test]
+    fn test_theme() {
+        let theme = Config::parse_config("./config.toml").theme;
+        assert!(crate::theme::load(theme.as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = Config::parse_config("./config.toml").layout;
+        assert!(crate::layout::ScreenLayout::new(layout.as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_style_config() {
+        let style = StyleConfig {
+            fg: Some(String::from("#ffaa00")),
+            bg: Some(String::from("light-red")),
+            modifiers: Some(vec![String::from("bold"), String::from("Underlined")]),
+        }.style();
+        assert_eq!(style, Style::default()
+            .fg(Color::Rgb(0xff, 0xaa, 0x00))
+            .bg(Color::LightRed)
+            .add_modifier(Modifier::BOLD | Modifier::UNDERLINED));
+    }
+
     #[test]
     #[should_panic]
     fn test_failing() {
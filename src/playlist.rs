@@ -0,0 +1,355 @@
+use std::{
+    ffi::OsStr,
+    fs::OpenOptions,
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ratatui::widgets::ListState;
+
+use crate::ui::{Music, Musics};
+
+/// One entry of a .m3u/.m3u8 playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    /// absolute path to the music file
+    pub path: PathBuf,
+    /// title from the `#EXTINF` line
+    pub title: Option<String>,
+    /// duration from the `#EXTINF` line
+    pub duration: Option<Duration>,
+}
+
+/// A .m3u/.m3u8 playlist read from disk
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>,
+}
+
+impl Playlist {
+    /// Reads the playlist at _path_
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or(Path::new("/"));
+        Ok(Playlist {
+            entries: Playlist::parse(&content, base),
+        })
+    }
+
+    /// Parses the content of a playlist, relative paths are resolved against _base_
+    pub fn parse(content: &str, base: &Path) -> Vec<PlaylistEntry> {
+        let mut entries = vec![];
+        let mut extinf: Option<(Option<Duration>, Option<String>)> = None;
+        for line in content.lines() {
+            // the utf-8 BOM some editors put at the start of .m3u8 files
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if line.is_empty() {
+                continue
+            }
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                extinf = Some(Playlist::parse_extinf(info));
+            } else if line.starts_with('#') {
+                continue
+            } else {
+                let (duration, title) = extinf.take().unwrap_or((None, None));
+                entries.push(PlaylistEntry {
+                    path: Playlist::resolve(line, base),
+                    title,
+                    duration,
+                });
+            }
+        }
+        entries
+    }
+
+    /// Parses the `<duration> [attributes],<title>` part of an `#EXTINF` line
+    fn parse_extinf(info: &str) -> (Option<Duration>, Option<String>) {
+        let (head, title) = match info.split_once(',') {
+            Some((head, title)) => (head, title.trim()),
+            None => (info, ""),
+        };
+        let duration = head.split_whitespace().next()
+            .and_then(|d| d.parse::<f64>().ok())
+            .filter(|d| *d >= 0.0)
+            .map(Duration::from_secs_f64);
+        let title = if title.is_empty() { None } else { Some(title.to_string()) };
+        (duration, title)
+    }
+
+    /// Resolves a playlist location, a path or a `file://` URI, to an
+    /// absolute path
+    fn resolve(location: &str, base: &Path) -> PathBuf {
+        let path = match location.strip_prefix("file://") {
+            Some(uri) => {
+                let uri = uri.strip_prefix("localhost").unwrap_or(uri);
+                PathBuf::from(OsStr::from_bytes(&Playlist::percent_decode(uri)))
+            },
+            None => PathBuf::from(location),
+        };
+        if path.is_absolute() {
+            path
+        } else {
+            base.join(path)
+        }
+    }
+
+    /// Bytes of _text_ with its `%XX` escapes decoded, malformed ones are
+    /// kept as they are
+    fn percent_decode(text: &str) -> Vec<u8> {
+        let bytes = text.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let escape = (bytes[i] == b'%')
+                .then(|| bytes.get(i + 1..i + 3))
+                .flatten()
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match escape {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 3;
+                },
+                None => {
+                    decoded.push(bytes[i]);
+                    i += 1;
+                },
+            }
+        }
+        decoded
+    }
+
+    /// File name of the playlist without its extension
+    pub fn name(path: &Path) -> String {
+        path.file_stem()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Builds the [Musics] of the playlist, entries that point to
+    /// unreadable files are skipped
+    pub fn musics(&self) -> Musics {
+        let mut musics = vec![];
+        for entry in self.entries.iter() {
+            if !entry.path.is_file() {
+                continue
+            }
+            if let Some(mut music) = Music::simple_new(entry.path.to_owned()) {
+                if let Some(title) = &entry.title {
+                    if music.title == "Unknown" {
+                        music.title = title.to_owned();
+                    }
+                }
+                if let Some(duration) = entry.duration {
+                    if music.length.is_zero() {
+                        music.length = duration;
+                    }
+                }
+                musics.push(music);
+            }
+        }
+        Musics::new(musics)
+    }
+
+    /// Formats the `#EXTINF` line of a [Music]
+    fn extinf(music: &Music) -> String {
+        if music.artist.is_empty() || music.artist == "Unknown" {
+            format!("#EXTINF:{},{}", music.length.as_secs(), music.title)
+        } else {
+            format!("#EXTINF:{},{} - {}", music.length.as_secs(), music.artist, music.title)
+        }
+    }
+
+    /// Appends a [Music] to the playlist at _path_, the playlist is created
+    /// if it does not exist
+    pub fn append(path: &Path, music: &Music) -> io::Result<()> {
+        let is_new = !path.exists() || std::fs::metadata(path)?.len() == 0;
+        let mut f = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)?;
+        if is_new {
+            writeln!(f, "#EXTM3U")?;
+        }
+        writeln!(f, "{}", Playlist::extinf(music))?;
+        writeln!(f, "{}", music.path.display())?;
+        Ok(())
+    }
+
+    /// Writes the musics to a new playlist at _path_, overwriting it if it exists
+    pub fn write(path: &Path, musics: &[Music]) -> io::Result<()> {
+        let mut f = std::fs::File::create(path)?;
+        writeln!(f, "#EXTM3U")?;
+        for music in musics {
+            writeln!(f, "{}", Playlist::extinf(music))?;
+            writeln!(f, "{}", music.path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Stores the state of the playlist browser
+#[derive(Debug, Default)]
+pub struct Playlists {
+    /// directories to look for playlists in
+    pub dirs: Vec<PathBuf>,
+    /// paths to every found playlist
+    pub paths: Vec<PathBuf>,
+    /// index of the *selected* entry, 0 being the library
+    pub selected: usize,
+    /// index of the currently loaded entry, 0 being the library
+    pub loaded: usize,
+    pub state: ListState,
+}
+
+impl Playlists {
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        let mut playlists = Playlists {
+            dirs,
+            state: ListState::default().with_selected(Some(0)),
+            ..Default::default()
+        };
+        playlists.refresh();
+        playlists
+    }
+
+    fn is_playlist(path: &Path) -> bool {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) => ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"),
+            None => false,
+        }
+    }
+
+    /// Re-reads the playlist directories, the selected and loaded entries
+    /// following their playlist to its new index
+    pub fn refresh(&mut self) {
+        let selected = self.selected_path().cloned();
+        let loaded = self.loaded.checked_sub(1).and_then(|i| self.paths.get(i)).cloned();
+        let mut paths = vec![];
+        for dir in self.dirs.iter() {
+            if let Ok(entries) = std::fs::read_dir(dir) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.is_file() && Playlists::is_playlist(&path) {
+                        paths.push(path);
+                    }
+                }
+            }
+        }
+        paths.sort();
+        self.paths = paths;
+        let index = |path: &PathBuf| self.paths.iter().position(|p| p == path).map(|i| i + 1);
+        // the selection stays in place if the playlist is gone
+        self.selected = selected.as_ref().and_then(index).unwrap_or(self.selected.min(self.paths.len()));
+        // a loaded playlist that is gone falls back to the library
+        self.loaded = loaded.as_ref().and_then(index).unwrap_or(0);
+    }
+
+    /// Number of entries in the browser, the library included
+    pub fn len(&self) -> usize {
+        self.paths.len() + 1
+    }
+
+    /// Path of the *selected* playlist, [None] if the library is selected
+    pub fn selected_path(&self) -> Option<&PathBuf> {
+        self.selected.checked_sub(1).and_then(|i| self.paths.get(i))
+    }
+
+    /// Selects the upper entry (goes up by 1)
+    pub fn select_up(&mut self) {
+        if self.selected == 0 {
+            self.selected = self.len() - 1;
+        }else {
+            self.selected -= 1;
+        }
+    }
+
+    /// Selects the next entry (goes down by 1)
+    pub fn select_down(&mut self) {
+        if self.selected + 1 >= self.len() {
+            self.selected = 0;
+        }else {
+            self.selected += 1;
+        }
+    }
+
+    /// Path for a new playlist named after the queue, inside the
+    /// first playlist directory
+    pub fn new_playlist_path(&self) -> Option<PathBuf> {
+        let dir = self.dirs.first()?;
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Some(dir.join(format!("queue-{}.m3u8", secs)))
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_parse_extinf() {
+        let content = "#EXTM3U\n#EXTINF:215,Artist - Title\nmusic/a.mp3\n#EXTINF:-1,Stream\n/abs/b.flac\nc.ogg\n";
+        let entries = Playlist::parse(content, Path::new("/playlists"));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].path, PathBuf::from("/playlists/music/a.mp3"));
+        assert_eq!(entries[0].title.as_deref(), Some("Artist - Title"));
+        assert_eq!(entries[0].duration, Some(Duration::from_secs(215)));
+        assert_eq!(entries[1].path, PathBuf::from("/abs/b.flac"));
+        assert_eq!(entries[1].duration, None);
+        assert_eq!(entries[2].title, None);
+    }
+
+    #[test]
+    fn test_resolve_uri() {
+        let base = Path::new("/playlists");
+        assert_eq!(Playlist::resolve("file:///music/a%20b%231%C3%A9.flac", base), PathBuf::from("/music/a b#1é.flac"));
+        assert_eq!(Playlist::resolve("file://localhost/music/a%20b.mp3", base), PathBuf::from("/music/a b.mp3"));
+        assert_eq!(Playlist::resolve("file:///music/100%.mp3", base), PathBuf::from("/music/100%.mp3"));
+        // plain paths are taken as they are
+        assert_eq!(Playlist::resolve("music/a%20b.mp3", base), PathBuf::from("/playlists/music/a%20b.mp3"));
+    }
+
+    #[test]
+    fn test_refresh() {
+        let dir = std::env::temp_dir().join(format!("mplayer-client-playlists-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.m3u"), "").unwrap();
+        std::fs::write(dir.join("c.m3u"), "").unwrap();
+        let mut playlists = Playlists::new(vec![dir.clone()]);
+        playlists.loaded = 1;
+        playlists.selected = 2;
+        // sorts before both of them
+        std::fs::write(dir.join("a.m3u"), "").unwrap();
+        playlists.refresh();
+        assert_eq!(playlists.loaded, 2);
+        assert_eq!(playlists.selected_path(), Some(&dir.join("c.m3u")));
+        std::fs::remove_file(dir.join("b.m3u")).unwrap();
+        playlists.refresh();
+        assert_eq!(playlists.loaded, 0);
+        assert_eq!(playlists.selected_path(), Some(&dir.join("c.m3u")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_and_append() {
+        let dir = std::env::temp_dir().join(format!("mplayer-client-playlist-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.m3u8");
+        let music = Music::new(
+            String::from("Title"), PathBuf::from("/music/a.mp3"),
+            Duration::from_secs(60), String::from("Artist"), String::from("Unknown")
+        );
+        Playlist::write(&path, std::slice::from_ref(&music)).unwrap();
+        Playlist::append(&path, &music).unwrap();
+        let playlist = Playlist::load(&path).unwrap();
+        assert_eq!(playlist.entries.len(), 2);
+        assert_eq!(playlist.entries[1].path, PathBuf::from("/music/a.mp3"));
+        assert_eq!(playlist.entries[1].title.as_deref(), Some("Artist - Title"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
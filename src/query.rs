@@ -0,0 +1,559 @@
+// query language of the search bar, e.g.
+// `daft -artist:"daft punk" (genre:house OR genre:disco) duration:>4:00 year:1990..1999`
+use std::{fmt, ops::Range};
+
+use crate::{fuzzy_search::{self, FuzzyMatch}, ui::Music};
+
+/// A property of a [Music] a query term can look into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Composer,
+    Genre,
+    Year,
+    Path,
+    Duration,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        match name.to_lowercase().as_str() {
+            "title" => Some(Field::Title),
+            "artist" => Some(Field::Artist),
+            "album" => Some(Field::Album),
+            "albumartist" | "album_artist" => Some(Field::AlbumArtist),
+            "composer" => Some(Field::Composer),
+            "genre" => Some(Field::Genre),
+            "year" => Some(Field::Year),
+            "path" => Some(Field::Path),
+            "duration" => Some(Field::Duration),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Field::Year | Field::Duration)
+    }
+
+    /// Text of the field, [None] for numeric or unknown fields
+    fn text(&self, music: &Music) -> Option<String> {
+        match self {
+            Field::Title => Some(music.title.to_owned()),
+            Field::Artist => Some(music.artist.to_owned()),
+            Field::Genre => Some(music.genre.to_owned()),
+            Field::Path => Some(music.path.to_string_lossy().to_string()),
+            Field::Album => Some(music.album.to_owned()),
+            Field::AlbumArtist => Some(music.album_artist.to_owned()),
+            Field::Composer => Some(music.composer.to_owned()),
+            Field::Year | Field::Duration => None,
+        }
+    }
+
+    /// Value of a numeric field, durations are in seconds
+    fn number(&self, music: &Music) -> Option<u64> {
+        match self {
+            Field::Duration => Some(music.length.as_secs()),
+            Field::Year => music.year.map(u64::from),
+            _ => None,
+        }
+    }
+}
+
+/// How a term compares to the value of a field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    /// case insensitive substring, the value is lowercased
+    Contains(String),
+    /// fzf like subsequence, smart case
+    Fuzzy(String),
+    Equal(u64),
+    Less(u64),
+    LessOrEqual(u64),
+    Greater(u64),
+    GreaterOrEqual(u64),
+    /// inclusive range, either bound can be left open
+    Range(Option<u64>, Option<u64>),
+}
+
+impl Predicate {
+    fn matches_number(&self, value: u64) -> bool {
+        match *self {
+            Predicate::Contains(_) | Predicate::Fuzzy(_) => false,
+            Predicate::Equal(v) => value == v,
+            Predicate::Less(v) => value < v,
+            Predicate::LessOrEqual(v) => value <= v,
+            Predicate::Greater(v) => value > v,
+            Predicate::GreaterOrEqual(v) => value >= v,
+            Predicate::Range(from, to) => {
+                from.is_none_or(|from| value >= from) && to.is_none_or(|to| value <= to)
+            },
+        }
+    }
+}
+
+/// A parsed search query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// the empty query, matches every music
+    All,
+    /// a term without a field looks into the title, the artist and the genre,
+    /// fuzzily unless it is quoted
+    Term { field: Option<Field>, predicate: Predicate },
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+/// Why a query could not be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    /// char index in the query where the error was found
+    pub position: usize,
+}
+
+impl QueryError {
+    fn new(message: impl Into<String>, position: usize) -> Self {
+        QueryError { message: message.into(), position }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (column {})", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    /// `field:value`, `value` or a `"quoted value"`
+    Term { field: Option<String>, value: String, quoted: bool },
+}
+
+/// Splits a query into [Token]s along with their position
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars = query.chars().collect::<Vec<char>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue
+            },
+            '(' => {
+                tokens.push((Token::LParen, start));
+                i += 1;
+            },
+            ')' => {
+                tokens.push((Token::RParen, start));
+                i += 1;
+            },
+            '-' => {
+                tokens.push((Token::Not, start));
+                i += 1;
+            },
+            '"' => {
+                let value = read_quoted(&chars, &mut i)?;
+                tokens.push((Token::Term { field: None, value, quoted: true }, start));
+            },
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !"()\"".contains(chars[i]) {
+                    i += 1;
+                }
+                let word = chars[start..i].iter().collect::<String>();
+                let token = match word.as_str() {
+                    "AND" | "&&" => Token::And,
+                    "OR" | "||" | "|" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match word.split_once(':') {
+                        // `field:"some phrase"`
+                        Some((field, "")) if chars.get(i) == Some(&'"') => Token::Term {
+                            field: Some(field.to_string()),
+                            value: read_quoted(&chars, &mut i)?,
+                            quoted: true,
+                        },
+                        Some((field, value)) => Token::Term {
+                            field: Some(field.to_string()),
+                            value: value.to_string(),
+                            quoted: false,
+                        },
+                        None => Token::Term { field: None, value: word, quoted: false },
+                    },
+                };
+                tokens.push((token, start));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a `"quoted value"` starting at _i_, leaving _i_ after the closing quote
+fn read_quoted(chars: &[char], i: &mut usize) -> Result<String, QueryError> {
+    let start = *i;
+    *i += 1;
+    let mut value = String::new();
+    while *i < chars.len() && chars[*i] != '"' {
+        value.push(chars[*i]);
+        *i += 1;
+    }
+    if *i >= chars.len() {
+        return Err(QueryError::new("unterminated quote", start))
+    }
+    *i += 1;
+    Ok(value)
+}
+
+/// Parses a duration written as `h:mm:ss`, `m:ss` or seconds
+fn parse_duration(value: &str) -> Option<u64> {
+    let mut secs: u64 = 0;
+    for part in value.split(':') {
+        secs = secs.checked_mul(60)?.checked_add(part.parse::<u64>().ok()?)?;
+    }
+    Some(secs)
+}
+
+/// Recursive descent parser, from the lowest to the highest precedence:
+/// `OR`, `AND` (or juxtaposition), `NOT`/`-`
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    /// position reported when the query ends unexpectedly
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(t, _)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map(|(_, p)| *p).unwrap_or(self.end)
+    }
+
+    fn or(&mut self) -> Result<Query, QueryError> {
+        let mut queries = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.index += 1;
+            queries.push(self.and()?);
+        }
+        Ok(if queries.len() == 1 { queries.remove(0) } else { Query::Or(queries) })
+    }
+
+    fn and(&mut self) -> Result<Query, QueryError> {
+        let mut queries = vec![self.not()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.index += 1;
+                    queries.push(self.not()?);
+                },
+                Some(Token::Or) | Some(Token::RParen) | None => break,
+                Some(_) => queries.push(self.not()?),
+            }
+        }
+        Ok(if queries.len() == 1 { queries.remove(0) } else { Query::And(queries) })
+    }
+
+    fn not(&mut self) -> Result<Query, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.index += 1;
+            return Ok(Query::Not(Box::new(self.not()?)))
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Query, QueryError> {
+        let position = self.position();
+        let Some((token, _)) = self.tokens.get(self.index).cloned() else {
+            return Err(QueryError::new("expected a term", position))
+        };
+        self.index += 1;
+        match token {
+            Token::LParen => {
+                let query = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(QueryError::new("missing closing parenthesis", self.position()))
+                }
+                self.index += 1;
+                Ok(query)
+            },
+            Token::Term { field, value, quoted } => Parser::term(field, value, quoted, position),
+            Token::RParen => Err(QueryError::new("unexpected closing parenthesis", position)),
+            Token::And | Token::Or => Err(QueryError::new("expected a term before the operator", position)),
+            Token::Not => unreachable!("handled by Parser::not"),
+        }
+    }
+
+    fn term(field: Option<String>, value: String, quoted: bool, position: usize) -> Result<Query, QueryError> {
+        let Some(name) = field else {
+            let predicate = if quoted { Predicate::Contains(value.to_lowercase()) } else { Predicate::Fuzzy(value) };
+            return Ok(Query::Term { field: None, predicate })
+        };
+        let Some(field) = Field::parse(&name) else {
+            return Err(QueryError::new(
+                format!("unknown field `{}`, quote the term to search for it", name), position
+            ))
+        };
+        if value.is_empty() && !quoted {
+            return Err(QueryError::new(format!("missing value after `{}:`", name), position))
+        }
+        if !field.is_numeric() {
+            return Ok(Query::Term { field: Some(field), predicate: Predicate::Contains(value.to_lowercase()) })
+        }
+        let parse = |v: &str| match field {
+            Field::Duration => parse_duration(v),
+            _ => v.parse::<u64>().ok(),
+        };
+        let invalid = |v: &str| {
+            let expected = if field == Field::Duration { "m:ss or seconds" } else { "a number" };
+            QueryError::new(format!("invalid {} `{}`, expected {}", name, v, expected), position)
+        };
+        let predicate = if let Some((from, to)) = value.split_once("..") {
+            let bound = |v: &str| if v.is_empty() { Ok(None) } else { parse(v).map(Some).ok_or_else(|| invalid(v)) };
+            Predicate::Range(bound(from)?, bound(to)?)
+        } else {
+            let (constructor, rest): (fn(u64) -> Predicate, &str) = if let Some(rest) = value.strip_prefix(">=") {
+                (Predicate::GreaterOrEqual, rest)
+            } else if let Some(rest) = value.strip_prefix("<=") {
+                (Predicate::LessOrEqual, rest)
+            } else if let Some(rest) = value.strip_prefix('>') {
+                (Predicate::Greater, rest)
+            } else if let Some(rest) = value.strip_prefix('<') {
+                (Predicate::Less, rest)
+            } else {
+                (Predicate::Equal, value.strip_prefix('=').unwrap_or(&value))
+            };
+            if rest.is_empty() {
+                return Err(QueryError::new(format!("missing value after `{}:{}`", name, value), position))
+            }
+            constructor(parse(rest).ok_or_else(|| invalid(rest))?)
+        };
+        Ok(Query::Term { field: Some(field), predicate })
+    }
+}
+
+impl Query {
+    /// Parses a query typed in the search bar
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(query)?;
+        if tokens.is_empty() {
+            return Ok(Query::All)
+        }
+        let mut parser = Parser { tokens, index: 0, end: query.chars().count() };
+        let res = parser.or()?;
+        if parser.index < parser.tokens.len() {
+            return Err(QueryError::new("unexpected closing parenthesis", parser.position()))
+        }
+        Ok(res)
+    }
+
+    /// Fields a term without a field looks into
+    const DEFAULT_FIELDS: [Field; 3] = [Field::Title, Field::Artist, Field::Genre];
+
+    /// Best fuzzy match of _pattern_ across the default fields of the [Music]
+    fn fuzzy_match(pattern: &str, music: &Music) -> Option<(Field, FuzzyMatch)> {
+        // reversed so the first field wins ties
+        Query::DEFAULT_FIELDS.iter().rev()
+            .filter_map(|f| Some((*f, fuzzy_search::fuzzy_match(pattern, &f.text(music)?)?)))
+            .max_by_key(|(_, m)| m.score)
+    }
+
+    /// Whether a term that is not fuzzy matches the [Music]
+    fn term_matches(field: Option<Field>, predicate: &Predicate, music: &Music) -> bool {
+        match (field, predicate) {
+            (None, Predicate::Contains(value)) => Query::DEFAULT_FIELDS.iter()
+                .filter_map(|f| f.text(music))
+                .any(|text| text.to_lowercase().contains(value.as_str())),
+            (Some(field), Predicate::Contains(value)) => {
+                field.text(music).is_some_and(|text| text.to_lowercase().contains(value.as_str()))
+            },
+            (Some(field), predicate) => {
+                field.number(music).is_some_and(|n| predicate.matches_number(n))
+            },
+            (None, _) => false,
+        }
+    }
+
+    /// Score of the [Music] against the query, the higher the better,
+    /// [None] if it does not match
+    pub fn score(&self, music: &Music) -> Option<i64> {
+        match self {
+            Query::All => Some(0),
+            Query::Term { field: None, predicate: Predicate::Fuzzy(pattern) } => {
+                Query::fuzzy_match(pattern, music).map(|(_, m)| m.score)
+            },
+            Query::Term { field, predicate } => {
+                Query::term_matches(*field, predicate, music).then_some(0)
+            },
+            Query::Not(query) => query.score(music).is_none().then_some(0),
+            Query::And(queries) => queries.iter().map(|q| q.score(music)).sum(),
+            Query::Or(queries) => queries.iter().filter_map(|q| q.score(music)).max(),
+        }
+    }
+
+    /// Char ranges of the _field_ of the [Music] matched by the terms of the
+    /// query, sorted and merged
+    pub fn spans(&self, music: &Music, field: Field) -> Vec<Range<usize>> {
+        let Some(text) = field.text(music) else {
+            return vec![]
+        };
+        // lowercased char by char so the ranges line up with the original text
+        let chars = text.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect::<Vec<char>>();
+        let mut ranges = vec![];
+        self.collect_spans(music, field, &chars, &mut ranges);
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = vec![];
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// Collects the ranges matched by the terms that look into _field_,
+    /// negated terms are left out since they never show up in the results
+    fn collect_spans(&self, music: &Music, field: Field, chars: &[char], ranges: &mut Vec<Range<usize>>) {
+        let value = match self {
+            Query::Term { field: Some(f), predicate: Predicate::Contains(value) } if *f == field => value,
+            Query::Term { field: None, predicate: Predicate::Contains(value) }
+                if Query::DEFAULT_FIELDS.contains(&field) => value,
+            // a fuzzy term is only highlighted in the field it matched best
+            Query::Term { field: None, predicate: Predicate::Fuzzy(pattern) } => {
+                if let Some((f, m)) = Query::fuzzy_match(pattern, music) {
+                    if f == field {
+                        ranges.extend(m.positions.into_iter().map(|p| p..p + 1));
+                    }
+                }
+                return
+            },
+            Query::And(queries) | Query::Or(queries) => {
+                for query in queries {
+                    query.collect_spans(music, field, chars, ranges);
+                }
+                return
+            },
+            _ => return,
+        };
+        let value = value.chars().collect::<Vec<char>>();
+        if value.is_empty() || value.len() > chars.len() {
+            return
+        }
+        for start in 0..=chars.len() - value.len() {
+            if chars[start..start + value.len()] == value[..] {
+                ranges.push(start..start + value.len());
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::{path::PathBuf, time::Duration};
+
+    #[allow(dead_code)]
+    fn album_music(title: &str, album: &str, year: u32) -> Music {
+        Music {
+            artist: String::from("Daft Punk"),
+            genre: String::from("House"),
+            length: Duration::from_secs(320),
+            album: album.to_string(),
+            year: Some(year),
+            ..Music::test(title)
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse("-artist:\"daft punk\" (genre:house OR disco) duration:>4:00").unwrap();
+        assert_eq!(query, Query::And(vec![
+            Query::Not(Box::new(Query::Term {
+                field: Some(Field::Artist), predicate: Predicate::Contains(String::from("daft punk"))
+            })),
+            Query::Or(vec![
+                Query::Term { field: Some(Field::Genre), predicate: Predicate::Contains(String::from("house")) },
+                Query::Term { field: None, predicate: Predicate::Fuzzy(String::from("disco")) },
+            ]),
+            Query::Term { field: Some(Field::Duration), predicate: Predicate::Greater(240) },
+        ]));
+        assert_eq!(
+            Query::parse("year:1990..1999").unwrap(),
+            Query::Term { field: Some(Field::Year), predicate: Predicate::Range(Some(1990), Some(1999)) }
+        );
+        assert_eq!(Query::parse("  ").unwrap(), Query::All);
+        assert_eq!(
+            Query::parse("\"a b\"").unwrap(),
+            Query::Term { field: None, predicate: Predicate::Contains(String::from("a b")) }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Query::parse("foo:bar").unwrap_err().position, 0);
+        assert_eq!(Query::parse("a \"b").unwrap_err().message, "unterminated quote");
+        assert_eq!(Query::parse("(a OR b").unwrap_err().position, 7);
+        assert!(Query::parse("duration:>").is_err());
+        assert!(Query::parse("duration:4m").is_err());
+        // too long to be counted in seconds
+        assert!(Query::parse("duration:>999999999999999999:0").is_err());
+        assert!(Query::parse("a OR").is_err());
+        assert!(Query::parse("a)").is_err());
+        assert!(Query::parse("\"re:zero\"").is_ok());
+    }
+
+    #[test]
+    fn test_matches() {
+        let musics = [
+            album_music("One More Time", "Discovery", 2001),
+            Music { length: Duration::from_secs(429), ..album_music("Around the World", "Homework", 1997) },
+            Music {
+                artist: String::from("Outkast"), genre: String::from("Hip Hop"), length: Duration::from_secs(235),
+                ..Music::test("Hey Ya")
+            },
+        ];
+        let titles = |query: &str| {
+            let query = Query::parse(query).unwrap();
+            musics.iter().filter(|m| query.score(m).is_some()).map(|m| m.title.as_str()).collect::<Vec<&str>>()
+        };
+        assert_eq!(titles("daft duration:<6:00"), vec!["One More Time"]);
+        assert_eq!(titles("-artist:daft"), vec!["Hey Ya"]);
+        assert_eq!(titles("\"hey ya\" OR title:world"), vec!["Around the World", "Hey Ya"]);
+        assert_eq!(titles("duration:200..330 AND genre:house"), vec!["One More Time"]);
+        assert_eq!(titles("path:one"), vec!["One More Time"]);
+        assert_eq!(titles("omt"), vec!["One More Time"]);
+        assert_eq!(titles("year:1999..2001 album:disc"), vec!["One More Time"]);
+        assert_eq!(titles("dp wrld"), vec!["Around the World"]);
+        assert!(titles("Omt").is_empty());
+    }
+
+    #[test]
+    fn test_score() {
+        let query = Query::parse("wor").unwrap();
+        let world = Music { artist: String::from("Daft Punk"), ..Music::test("Around the World") };
+        let worm = Music { artist: String::from("Someone"), ..Music::test("Glowworm") };
+        assert!(query.score(&world) > query.score(&worm));
+        assert_eq!(Query::parse("-wor").unwrap().score(&world), None);
+    }
+
+    #[test]
+    fn test_spans() {
+        let music = Music { artist: String::from("Daft Punk"), ..Music::test("Around the World") };
+        let query = Query::parse("o -world title:\"the w\" artist:punk").unwrap();
+        assert_eq!(query.spans(&music, Field::Title), vec![2..3, 7..12]);
+        assert_eq!(query.spans(&music, Field::Artist), vec![5..9]);
+        assert!(query.spans(&music, Field::Genre).is_empty());
+    }
+}
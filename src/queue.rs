@@ -0,0 +1,163 @@
+use ratatui::widgets::TableState;
+
+use crate::ui::Music;
+
+/// User editable play queue, lives independently from the displayed
+/// (and possibly filtered) music list
+#[derive(Default, Debug)]
+pub struct PlayQueue {
+    /// musics waiting to be played, the first one is played next
+    pub musics: Vec<Music>,
+    /// index of the currently *selected* music in the queue
+    pub selected: usize,
+    pub state: TableState,
+}
+
+impl PlayQueue {
+    #[allow(dead_code)]
+    pub fn new(musics: Vec<Music>) -> Self {
+        PlayQueue {
+            musics,
+            selected: 0,
+            state: TableState::default().with_selected(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.musics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.musics.is_empty()
+    }
+
+    /// Appends the [Music] to the end of the queue
+    pub fn enqueue(&mut self, music: Music) {
+        self.musics.push(music);
+    }
+
+    /// Inserts the [Music] at the front of the queue so it plays next
+    pub fn play_next(&mut self, music: Music) {
+        self.musics.insert(0, music);
+        // keeps the selection on the same music
+        if self.musics.len() > 1 {
+            self.selected += 1;
+        }
+    }
+
+    /// Takes the next [Music] to play out of the queue
+    pub fn pop(&mut self) -> Option<Music> {
+        if self.musics.is_empty() {
+            return None
+        }
+        let music = self.musics.remove(0);
+        self.selected = self.selected.saturating_sub(1);
+        Some(music)
+    }
+
+    /// Removes the [Music] at _index_ from the queue
+    pub fn remove(&mut self, index: usize) -> Option<Music> {
+        if index >= self.musics.len() {
+            return None
+        }
+        let music = self.musics.remove(index);
+        if self.selected >= self.musics.len() {
+            self.selected = self.musics.len().saturating_sub(1);
+        }
+        Some(music)
+    }
+
+    /// Removes the *selected* [Music] from the queue
+    pub fn remove_selected(&mut self) -> Option<Music> {
+        self.remove(self.selected)
+    }
+
+    /// Moves the *selected* [Music] one place closer to the front of the queue
+    pub fn move_up(&mut self) {
+        if self.selected > 0 && self.selected < self.musics.len() {
+            self.musics.swap(self.selected, self.selected - 1);
+            self.selected -= 1;
+        }
+    }
+
+    /// Moves the *selected* [Music] one place closer to the end of the queue
+    pub fn move_down(&mut self) {
+        if self.selected + 1 < self.musics.len() {
+            self.musics.swap(self.selected, self.selected + 1);
+            self.selected += 1;
+        }
+    }
+
+    /// Removes every [Music] from the queue
+    pub fn clear(&mut self) {
+        self.musics.clear();
+        self.selected = 0;
+    }
+
+    /// Selects the upper element in the queue (goes up by 1)
+    pub fn select_up(&mut self) {
+        if self.musics.is_empty() {
+            return
+        }
+        if self.selected == 0 {
+            self.selected = self.musics.len() - 1;
+        }else {
+            self.selected -= 1;
+        }
+    }
+
+    /// Selects the next element in the queue (goes down by 1)
+    pub fn select_down(&mut self) {
+        if self.musics.is_empty() {
+            return
+        }
+        if self.selected + 1 >= self.musics.len() {
+            self.selected = 0;
+        }else {
+            self.selected += 1;
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::{path::PathBuf, time::Duration};
+
+    #[test]
+    fn test_enqueue_and_pop() {
+        let mut queue = PlayQueue::default();
+        queue.enqueue(Music::test("a"));
+        queue.enqueue(Music::test("b"));
+        queue.play_next(Music::test("c"));
+        assert_eq!(queue.pop().unwrap().title, "c");
+        assert_eq!(queue.pop().unwrap().title, "a");
+        assert_eq!(queue.pop().unwrap().title, "b");
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut queue = PlayQueue::new(vec![Music::test("a"), Music::test("b"), Music::test("c")]);
+        queue.selected = 2;
+        queue.move_up();
+        assert_eq!(queue.selected, 1);
+        assert_eq!(queue.musics[1].title, "c");
+        queue.move_down();
+        queue.move_down();
+        assert_eq!(queue.selected, 2);
+        assert_eq!(queue.musics[2].title, "c");
+    }
+
+    #[test]
+    fn test_remove_and_clear() {
+        let mut queue = PlayQueue::new(vec![Music::test("a"), Music::test("b")]);
+        queue.selected = 1;
+        assert_eq!(queue.remove_selected().unwrap().title, "b");
+        assert_eq!(queue.selected, 0);
+        assert!(queue.remove(4).is_none());
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+}
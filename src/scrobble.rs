@@ -0,0 +1,278 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::history::Play;
+use crate::ui::Music;
+use crate::utils;
+
+/// A play is a listen past this share of the music
+const MIN_SHARE: f64 = 0.5;
+/// or past this time, whatever the length of the music
+const MIN_PLAYED: Duration = Duration::from_secs(240);
+/// Name of the client in the exported files
+const CLIENT: &str = "mplayer-client";
+
+/// A completed listen waiting to be submitted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scrobble {
+    /// start of the listen, in seconds since the unix epoch
+    pub time: i64,
+    pub artist: String,
+    pub album: String,
+    pub title: String,
+    pub track: Option<u32>,
+    /// length of the music, in seconds
+    pub length: f64,
+}
+
+impl Scrobble {
+    /// The scrobble of _play_ of _music_, if it played past half of the
+    /// music or for 4 minutes, and the artist and title of the music are known
+    pub fn new(play: &Play, music: &Music) -> Option<Self> {
+        let listened = play.played >= MIN_PLAYED.as_secs_f64()
+            || (play.length > 0.0 && play.played >= play.length * MIN_SHARE);
+        // services reject listens without an artist or a title
+        let unknown = |text: &str| text.is_empty() || text == "Unknown";
+        if !listened || unknown(&music.artist) || unknown(&music.title) {
+            return None
+        }
+        // the other tags that are missing are left out of the exports
+        let known = |text: &str| if unknown(text) { String::new() } else { text.to_string() };
+        Some(Scrobble {
+            time: play.time,
+            artist: music.artist.clone(),
+            album: known(&music.album),
+            title: music.title.clone(),
+            track: music.track,
+            length: play.length,
+        })
+    }
+}
+
+/// What the scrobble queue is dumped as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// the tab separated `.scrobbler.log` of Rockbox and other portable players
+    #[default]
+    ScrobblerLog,
+    /// the JSON payload of an `import` to ListenBrainz
+    ListenBrainz,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scrobbler-log" => Ok(Format::ScrobblerLog),
+            "listenbrainz" => Ok(Format::ListenBrainz),
+            _ => Err(format!("Unknown scrobble format {}, expected scrobbler-log or listenbrainz", s)),
+        }
+    }
+}
+
+impl Format {
+    /// _scrobbles_ written in the format
+    pub fn write(&self, scrobbles: &[Scrobble]) -> String {
+        match self {
+            Format::ScrobblerLog => scrobbler_log(scrobbles),
+            Format::ListenBrainz => listenbrainz(scrobbles),
+        }
+    }
+}
+
+/// The `.scrobbler.log` of _scrobbles_, in the AudioScrobbler 1.1 format
+/// with UTC times
+fn scrobbler_log(scrobbles: &[Scrobble]) -> String {
+    // the fields are tab separated, one scrobble per line
+    let field = |text: &str| text.replace(['\t', '\n', '\r'], " ");
+    let mut log = format!("#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/{} {}\n", CLIENT, env!("CARGO_PKG_VERSION"));
+    for scrobble in scrobbles {
+        log.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\tL\t{}\t\n",
+            field(&scrobble.artist),
+            field(&scrobble.album),
+            field(&scrobble.title),
+            scrobble.track.map(|track| track.to_string()).unwrap_or_default(),
+            scrobble.length.round() as u64,
+            scrobble.time,
+        ));
+    }
+    log
+}
+
+/// The ListenBrainz `import` payload of _scrobbles_
+fn listenbrainz(scrobbles: &[Scrobble]) -> String {
+    let payload = scrobbles.iter().map(|scrobble| {
+        let mut info = json!({
+            "media_player": CLIENT,
+            "submission_client": CLIENT,
+            "submission_client_version": env!("CARGO_PKG_VERSION"),
+            "duration_ms": (scrobble.length * 1000.0).round() as u64,
+        });
+        if let Some(track) = scrobble.track {
+            info["tracknumber"] = json!(track);
+        }
+        let mut metadata = json!({
+            "artist_name": scrobble.artist,
+            "track_name": scrobble.title,
+            "additional_info": info,
+        });
+        if !scrobble.album.is_empty() {
+            metadata["release_name"] = json!(scrobble.album);
+        }
+        json!({ "listened_at": scrobble.time, "track_metadata": metadata })
+    }).collect::<Vec<_>>();
+    let listens = json!({ "listen_type": "import", "payload": payload });
+    serde_json::to_string_pretty(&listens).unwrap_or_default() + "\n"
+}
+
+/// Listens kept one JSON object per line in
+/// `$XDG_DATA_HOME/mplayer-client/scrobbles.jsonl` until they are dumped,
+/// nothing is ever sent over the network
+#[derive(Debug, Default)]
+pub struct ScrobbleQueue {
+    /// where listens are appended, they are dropped if unset
+    path: Option<PathBuf>,
+}
+
+impl ScrobbleQueue {
+    /// Default location of the queue, `$XDG_DATA_HOME/mplayer-client/scrobbles.jsonl`
+    pub fn default_path() -> Option<PathBuf> {
+        utils::xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join("scrobbles.jsonl"))
+    }
+
+    pub fn new(path: PathBuf) -> Self {
+        ScrobbleQueue { path: Some(path) }
+    }
+
+    /// Adds _scrobble_ to the end of the queue
+    pub fn push(&self, scrobble: &Scrobble) -> io::Result<()> {
+        match &self.path {
+            Some(path) => utils::append_json(path, scrobble),
+            None => Ok(()),
+        }
+    }
+
+    /// Listens of the queue at _path_, lines that can't be read are skipped
+    pub fn load(path: &Path) -> Vec<Scrobble> {
+        std::fs::read_to_string(path).unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()
+    }
+}
+
+/// Runs `mplayer-client scrobbles [scrobbler-log|listenbrainz] [--clear]`
+/// with _args_ the arguments after `scrobbles`, printing the queue in the
+/// format and emptying it with `--clear`
+pub fn dump(args: &[String]) -> Result<(), String> {
+    let mut format = Format::default();
+    let mut clear = false;
+    for arg in args {
+        match arg.as_str() {
+            "--clear" => clear = true,
+            arg => format = arg.parse()?,
+        }
+    }
+    let path = ScrobbleQueue::default_path().ok_or("Neither XDG_DATA_HOME nor HOME is set")?;
+    if !clear {
+        print!("{}", format.write(&ScrobbleQueue::load(&path)));
+        return Ok(())
+    }
+    if !path.exists() {
+        print!("{}", format.write(&[]));
+        return Ok(())
+    }
+    // the queue is moved aside first, so listens the client queues while
+    // dumping start a new queue instead of being cleared unprinted
+    let dumped = path.with_extension("jsonl.dump");
+    std::fs::rename(&path, &dumped)
+        .map_err(|error| format!("Could not clear {}: {}", path.display(), error))?;
+    let scrobbles = ScrobbleQueue::load(&dumped);
+    let mut stdout = io::stdout();
+    if let Err(error) = stdout.write_all(format.write(&scrobbles).as_bytes()).and_then(|_| stdout.flush()) {
+        // put the listens back in the queue rather than losing them
+        let queue = ScrobbleQueue::new(path.clone());
+        if scrobbles.iter().try_for_each(|scrobble| queue.push(scrobble)).is_ok() {
+            let _ = std::fs::remove_file(&dumped);
+        }
+        return Err(format!("Could not print the scrobbles: {}", error))
+    }
+    std::fs::remove_file(&dumped)
+        .map_err(|error| format!("Could not clear {}: {}", dumped.display(), error))?;
+    Ok(())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn scrobble() -> Scrobble {
+        Scrobble {
+            time: 1700000000,
+            artist: String::from("Artist"),
+            album: String::new(),
+            title: String::from("A\ttitle"),
+            track: Some(3),
+            length: 200.4,
+        }
+    }
+
+    #[test]
+    fn test_listened() {
+        let music = Music {
+            title: String::from("Title"), artist: String::from("Artist"), album: String::from("Unknown"),
+            ..Default::default()
+        };
+        let play = |length: f64, played: f64| Play {
+            time: 0, path: PathBuf::new(), length, played, ending: crate::history::Ending::Skipped,
+        };
+        assert_eq!(Scrobble::new(&play(200.0, 99.0), &music), None);
+        let listened = Scrobble::new(&play(200.0, 100.0), &music).unwrap();
+        assert_eq!(listened.album, "");
+        assert_eq!(listened.artist, "Artist");
+        assert!(Scrobble::new(&play(900.0, 240.0), &music).is_some());
+        // a music of unknown length needs the 4 minutes
+        assert_eq!(Scrobble::new(&play(0.0, 100.0), &music), None);
+    }
+
+    #[test]
+    fn test_unknown_tags() {
+        let play = Play {
+            time: 0, path: PathBuf::new(), length: 200.0, played: 200.0, ending: crate::history::Ending::Completed,
+        };
+        let music = Music { title: String::from("Title"), artist: String::from("Artist"), ..Default::default() };
+        assert!(Scrobble::new(&play, &music).is_some());
+        let untitled = Music { title: String::from("Unknown"), ..music.clone() };
+        assert_eq!(Scrobble::new(&play, &untitled), None);
+        let anonymous = Music { artist: String::from("Unknown"), ..music.clone() };
+        assert_eq!(Scrobble::new(&play, &anonymous), None);
+        let untagged = Music { artist: String::new(), ..music };
+        assert_eq!(Scrobble::new(&play, &untagged), None);
+    }
+
+    #[test]
+    fn test_formats() {
+        let log = Format::ScrobblerLog.write(&[scrobble()]);
+        assert!(log.starts_with("#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/mplayer-client"));
+        assert_eq!(log.lines().last(), Some("Artist\t\tA title\t3\t200\tL\t1700000000\t"));
+        let listens: serde_json::Value = serde_json::from_str(&Format::ListenBrainz.write(&[scrobble()])).unwrap();
+        assert_eq!(listens["listen_type"], "import");
+        let listen = &listens["payload"][0];
+        assert_eq!(listen["listened_at"], 1700000000);
+        assert_eq!(listen["track_metadata"]["track_name"], "A\ttitle");
+        assert_eq!(listen["track_metadata"]["additional_info"]["duration_ms"], 200400);
+        assert!(listen["track_metadata"].get("release_name").is_none());
+        assert_eq!("listenbrainz".parse(), Ok(Format::ListenBrainz));
+        assert!("csv".parse::<Format>().is_err());
+    }
+}
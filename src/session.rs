@@ -0,0 +1,86 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ui::{Region, Repeat};
+use crate::utils;
+use crate::Sorting;
+
+/// How often the session is saved while the client runs, on top of
+/// being saved on exit
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What the UI looked like, restored on the next start
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct Session {
+    pub order: Sorting,
+    pub repeat: Repeat,
+    pub region: Region,
+    /// music selected in the music list, by path to survive library changes
+    pub selected: Option<PathBuf>,
+    /// first music shown in the music list
+    pub offset: usize,
+    /// search the music list was filtered with
+    pub query: Option<String>,
+    pub queue: Vec<PathBuf>,
+    /// index of the selected music in the queue
+    pub queue_selected: usize,
+}
+
+impl Session {
+    /// Default location of the session, `$XDG_STATE_HOME/mplayer-client/session.json`
+    pub fn default_path() -> Option<PathBuf> {
+        utils::xdg_dir("XDG_STATE_HOME", ".local/state").map(|dir| dir.join("session.json"))
+    }
+
+    /// Reads the session at _path_, [None] if there is none or it can't be read
+    pub fn load(path: &Path) -> Option<Self> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // a crash while writing never leaves half a session behind
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let dir = std::env::temp_dir().join(format!("mplayer-client-session-{}", std::process::id()));
+        let path = dir.join("session.json");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(Session::load(&path), None);
+        let session = Session {
+            order: Sorting::ByAlbum,
+            repeat: Repeat::AllMusics,
+            region: Region::Queue,
+            selected: Some(PathBuf::from("/music/a.mp3")),
+            offset: 12,
+            query: Some(String::from("artist:abba")),
+            queue: vec![PathBuf::from("/music/b.mp3"), PathBuf::from("/music/c.mp3")],
+            queue_selected: 1,
+        };
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path), Some(session));
+        // fields missing from an older session take their defaults
+        std::fs::write(&path, r#"{"offset": 3}"#).unwrap();
+        assert_eq!(Session::load(&path), Some(Session { offset: 3, ..Default::default() }));
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(Session::load(&path), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
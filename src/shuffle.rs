@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::ui::Music;
+use crate::Sorting;
+
+/// Rating given to the musics without one in the `WeightedShuffle` order,
+/// out of 5
+const DEFAULT_RATING: f64 = 2.5;
+
+/// How likely _music_ is to come early in the `WeightedShuffle` order,
+/// rarely played and highly rated musics coming first
+pub fn weight(music: &Music, plays: u32) -> f64 {
+    let rating = music.rating.map(f64::from).unwrap_or(DEFAULT_RATING);
+    (1.0 + rating) / (1.0 + plays as f64)
+}
+
+/// Shuffles the music list, the same seed giving the same orders
+pub struct Shuffler {
+    rng: StdRng,
+    /// music played last before the list was shuffled again on wrapping,
+    /// where going back from the first music leads
+    pub wrapped_from: Option<PathBuf>,
+}
+
+impl Default for Shuffler {
+    fn default() -> Self {
+        Shuffler::new(None)
+    }
+}
+
+impl Shuffler {
+    /// A shuffler drawing from _seed_, from the entropy of the system if unset
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Shuffler { rng, wrapped_from: None }
+    }
+
+    /// Fisher–Yates shuffle, every order being as likely
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.rng.gen_range(0..=i);
+            items.swap(i, j);
+        }
+    }
+
+    /// Puts _musics_ in the shuffled _order_, the musics of an album
+    /// staying together in the `AlbumShuffle` one, _weight_ telling how
+    /// early a music should come in the `WeightedShuffle` one, other orders
+    /// are left untouched
+    pub fn arrange(&mut self, order: Sorting, musics: &mut [Music], weight: impl Fn(&Music) -> f64) {
+        match order {
+            Sorting::Shuffle => self.shuffle(musics),
+            Sorting::AlbumShuffle => self.shuffle_albums(musics),
+            Sorting::ArtistShuffle => self.spread_artists(musics, None),
+            Sorting::WeightedShuffle => self.weighted_shuffle(musics, weight),
+            _ => {},
+        }
+    }
+
+    /// Shuffles _musics_ in _order_ with the one at _playing_ first, or
+    /// its album in the `AlbumShuffle` order, so the rest plays in the new order
+    pub fn shuffle_musics(
+        &mut self, order: Sorting, musics: &mut Vec<Music>, playing: Option<&Path>, weight: impl Fn(&Music) -> f64,
+    ) {
+        self.wrapped_from = None;
+        let Some(index) = playing.and_then(|path| musics.iter().position(|m| m.path == path)) else {
+            return self.arrange(order, musics, weight)
+        };
+        match order {
+            Sorting::AlbumShuffle => {
+                let album = musics[index].album_key();
+                self.arrange(order, musics, weight);
+                // stable, the tracks keep their order
+                musics.sort_by_key(|m| m.album_key() != album);
+            },
+            Sorting::ArtistShuffle => {
+                let first = musics.remove(index);
+                self.spread_artists(musics, Some(&first.artist));
+                musics.insert(0, first);
+            },
+            _ => {
+                let first = musics.remove(index);
+                self.arrange(order, musics, weight);
+                musics.insert(0, first);
+            },
+        }
+    }
+
+    /// Shuffles _musics_ in _order_ once they all played, _last_ not coming
+    /// first so it doesn't play twice in a row, nor its album or its artist
+    /// in the `AlbumShuffle` and `ArtistShuffle` orders
+    pub fn reshuffle(&mut self, order: Sorting, musics: &mut [Music], last: &Path, weight: impl Fn(&Music) -> f64) {
+        let last_music = musics.iter().find(|m| m.path == last).cloned();
+        match (order, last_music) {
+            (Sorting::AlbumShuffle, Some(last_music)) => {
+                self.shuffle_albums(musics);
+                // the album that just played goes last instead
+                if musics.first().map(|m| m.album_key()) == Some(last_music.album_key()) {
+                    musics.sort_by_key(|m| m.album_key() == last_music.album_key());
+                }
+            },
+            (Sorting::ArtistShuffle, Some(last_music)) => self.spread_artists(musics, Some(&last_music.artist)),
+            _ => {
+                self.arrange(order, musics, weight);
+                // swapping it with any other music keeps every order as likely
+                if musics.len() > 1 && musics[0].path == last {
+                    let index = self.rng.gen_range(1..musics.len());
+                    musics.swap(0, index);
+                }
+            },
+        }
+        self.wrapped_from = Some(last.to_path_buf());
+    }
+
+    /// Shuffles the albums of _musics_, each one played in track order
+    fn shuffle_albums(&mut self, musics: &mut [Music]) {
+        let mut albums = vec![];
+        for music in musics.iter() {
+            let key = music.album_key();
+            if !albums.contains(&key) {
+                albums.push(key);
+            }
+        }
+        self.shuffle(&mut albums);
+        let ranks = albums.into_iter().enumerate()
+            .map(|(rank, key)| (key, rank))
+            .collect::<HashMap<(String, String), usize>>();
+        musics.sort_by(|a, b| ranks[&a.album_key()].cmp(&ranks[&b.album_key()]).then_with(|| a.track_order(b)));
+    }
+
+    /// Shuffles _musics_ so that no artist plays twice in a row, nor right
+    /// after _previous_, as long as there are enough other artists
+    fn spread_artists(&mut self, musics: &mut [Music], previous: Option<&str>) {
+        let mut artists: HashMap<String, Vec<Music>> = HashMap::new();
+        for music in musics.iter() {
+            artists.entry(music.artist.clone()).or_default().push(music.clone());
+        }
+        // sorted so a seed gives the same order whatever the hashing
+        let mut artists = artists.into_iter().collect::<Vec<(String, Vec<Music>)>>();
+        artists.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, tracks) in artists.iter_mut() {
+            self.shuffle(tracks);
+        }
+        let mut last = previous.map(str::to_string);
+        for slot in musics.iter_mut() {
+            let remaining = artists.iter().map(|(_, tracks)| tracks.len()).sum::<usize>();
+            let mut candidates = artists.iter().enumerate()
+                .filter(|(_, (artist, tracks))| !tracks.is_empty() && Some(artist) != last.as_ref())
+                .map(|(index, _)| index)
+                .collect::<Vec<usize>>();
+            if candidates.is_empty() {
+                // only the last artist is left
+                candidates = artists.iter().enumerate()
+                    .filter(|(_, (_, tracks))| !tracks.is_empty())
+                    .map(|(index, _)| index)
+                    .collect();
+            }
+            let largest = candidates.iter().copied().max_by_key(|index| artists[*index].1.len()).unwrap_or(0);
+            // an artist with half of what is left has to go now, or it
+            // ends up playing back to back
+            let pick = if artists[largest].1.len() * 2 >= remaining {
+                largest
+            } else {
+                // the artists with more tracks left are more likely to go
+                let mut draw = self.rng.gen_range(0..candidates.iter().map(|i| artists[*i].1.len()).sum::<usize>());
+                candidates.into_iter().find(|index| {
+                    let count = artists[*index].1.len();
+                    if draw < count {
+                        return true
+                    }
+                    draw -= count;
+                    false
+                }).unwrap_or(largest)
+            };
+            let (artist, tracks) = &mut artists[pick];
+            if let Some(music) = tracks.pop() {
+                *slot = music;
+            }
+            last = Some(artist.clone());
+        }
+    }
+
+    /// Efraimidis–Spirakis weighted shuffle, a music of twice the _weight_
+    /// of another being twice as likely to come before it
+    fn weighted_shuffle(&mut self, musics: &mut [Music], weight: impl Fn(&Music) -> f64) {
+        let mut keyed = musics.iter().map(|music| {
+            let weight = weight(music).max(f64::MIN_POSITIVE);
+            (self.rng.gen::<f64>().powf(1.0 / weight), music.clone())
+        }).collect::<Vec<(f64, Music)>>();
+        keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (slot, (_, music)) in musics.iter_mut().zip(keyed) {
+            *slot = music;
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn musics(count: usize) -> Vec<Music> {
+        (0..count).map(|i| Music { path: PathBuf::from(i.to_string()), ..Music::test(&i.to_string()) }).collect()
+    }
+
+    #[allow(dead_code)]
+    fn tagged(tags: &[(&str, &str, u32)]) -> Vec<Music> {
+        tags.iter().enumerate().map(|(i, (artist, album, track))| Music {
+            path: PathBuf::from(i.to_string()),
+            artist: artist.to_string(),
+            album_artist: artist.to_string(),
+            album: album.to_string(),
+            track: Some(*track),
+            ..Music::test(&i.to_string())
+        }).collect()
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let shuffled = |seed| {
+            let mut items = (0..50).collect::<Vec<usize>>();
+            Shuffler::new(Some(seed)).shuffle(&mut items);
+            items
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+        let mut sorted = shuffled(7);
+        sorted.sort();
+        assert_eq!(sorted, (0..50).collect::<Vec<usize>>());
+        // every order of 3 items comes up about as often
+        let mut shuffler = Shuffler::new(Some(1));
+        let mut counts = HashMap::new();
+        for _ in 0..6000 {
+            let mut items = [0, 1, 2];
+            shuffler.shuffle(&mut items);
+            *counts.entry(items).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 6);
+        assert!(counts.values().all(|count| (850..1150).contains(count)), "{:?}", counts);
+        shuffler.shuffle::<usize>(&mut []);
+    }
+
+    #[test]
+    fn test_reshuffle() {
+        let mut shuffler = Shuffler::new(Some(3));
+        let mut list = musics(10);
+        shuffler.shuffle_musics(Sorting::Shuffle, &mut list, Some(Path::new("4")), |_| 1.0);
+        assert_eq!(list[0].path, PathBuf::from("4"));
+        for _ in 0..200 {
+            let last = list.last().unwrap().path.clone();
+            shuffler.reshuffle(Sorting::Shuffle, &mut list, &last, |_| 1.0);
+            assert_ne!(list[0].path, last);
+            assert_eq!(shuffler.wrapped_from, Some(last));
+        }
+        let mut single = musics(1);
+        shuffler.reshuffle(Sorting::Shuffle, &mut single, Path::new("0"), |_| 1.0);
+        assert_eq!(single.len(), 1);
+    }
+
+    #[test]
+    fn test_album_shuffle() {
+        let mut shuffler = Shuffler::new(Some(5));
+        let mut list = tagged(&[
+            ("a", "x", 2), ("b", "y", 1), ("a", "x", 1), ("c", "z", 3), ("c", "z", 1), ("b", "y", 2), ("c", "z", 2),
+        ]);
+        for _ in 0..20 {
+            shuffler.arrange(Sorting::AlbumShuffle, &mut list, |_| 1.0);
+            // every album in a single run, in track order
+            let albums = list.iter().map(|m| m.album.as_str()).collect::<Vec<&str>>();
+            let mut runs = albums.clone();
+            runs.dedup();
+            assert_eq!(runs.len(), 3);
+            assert!(list.windows(2).all(|w| w[0].album != w[1].album || w[0].track < w[1].track));
+            let last = list.last().unwrap().path.clone();
+            let last_album = list.last().unwrap().album.clone();
+            shuffler.reshuffle(Sorting::AlbumShuffle, &mut list, &last, |_| 1.0);
+            assert_ne!(list[0].album, last_album);
+        }
+        shuffler.shuffle_musics(Sorting::AlbumShuffle, &mut list, Some(Path::new("3")), |_| 1.0);
+        assert_eq!(list.iter().take(3).map(|m| m.album.as_str()).collect::<Vec<&str>>(), vec!["z"; 3]);
+    }
+
+    #[test]
+    fn test_artist_spread() {
+        let mut shuffler = Shuffler::new(Some(9));
+        let mut list = tagged(&[
+            ("a", "", 0), ("a", "", 0), ("a", "", 0), ("a", "", 0), ("b", "", 0),
+            ("b", "", 0), ("c", "", 0), ("c", "", 0), ("d", "", 0),
+        ]);
+        for _ in 0..200 {
+            shuffler.arrange(Sorting::ArtistShuffle, &mut list, |_| 1.0);
+            assert_eq!(list.len(), 9);
+            assert!(list.windows(2).all(|w| w[0].artist != w[1].artist), "{:?}", list.iter().map(|m| &m.artist).collect::<Vec<_>>());
+            let last = list.last().unwrap().clone();
+            shuffler.reshuffle(Sorting::ArtistShuffle, &mut list, &last.path, |_| 1.0);
+            if last.artist != "a" {
+                assert_ne!(list[0].artist, last.artist);
+            }
+        }
+        let mut paths = list.iter().map(|m| m.path.clone()).collect::<Vec<PathBuf>>();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 9);
+        // back to back once no other artist is left
+        let mut alone = tagged(&[("a", "", 0), ("a", "", 0), ("b", "", 0)]);
+        shuffler.spread_artists(&mut alone, Some("b"));
+        assert_eq!(alone.len(), 3);
+    }
+
+    #[test]
+    fn test_weighted_shuffle() {
+        let mut shuffler = Shuffler::new(Some(2));
+        let mut list = musics(2);
+        let mut first = 0;
+        for _ in 0..3000 {
+            // the music "0" weighs 3 times more
+            shuffler.arrange(Sorting::WeightedShuffle, &mut list, |m| if m.path == Path::new("0") { 3.0 } else { 1.0 });
+            if list[0].path == Path::new("0") {
+                first += 1;
+            }
+        }
+        // 3 times out of 4
+        assert!((2100..2400).contains(&first), "{}", first);
+        let unrated = Music { ..Default::default() };
+        let rated = Music { rating: Some(5), ..Default::default() };
+        assert!(weight(&rated, 0) > weight(&unrated, 0));
+        assert!(weight(&unrated, 0) > weight(&unrated, 3));
+    }
+}
@@ -1,12 +1,16 @@
 #![allow(dead_code)]
-use std::{path::PathBuf, time::Duration};
+use std::{path::{Path, PathBuf}, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
 
-use async_std::task::block_on;
+use async_std::{channel::{self, Receiver, Sender}, future, stream::StreamExt, task::{self, block_on}};
+use zbus::{message, MatchRule, MessageStream};
 
 use crate::{
-    ui::Music, Metadata, ServerProxy
+    ui::Music, utils::{self, RunStatus}, Metadata, ServerProxy
 };
 
+/// Longest time between two polls of a server that does not answer
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Debug, PartialEq, Eq, Default, Clone)]
 pub enum Status {
     Playing,
@@ -23,7 +27,7 @@ pub struct MusicListState {
 
 /// Batch of all possible possible values that could be gotten
 /// from the bus server
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Batch {
     /// played duration 
     played_duration: Duration,
@@ -39,6 +43,155 @@ pub struct Batch {
     music_path: String,
     /// currently playing music metadata
     metadata: Metadata,
+    /// when the values were asked to the server, [None] if they never were
+    fetched: Option<Instant>,
+    /// number of commands the server had run when the values were asked
+    commands_run: usize,
+}
+
+impl Batch {
+    /// Played duration, counting the time spent playing since the batch
+    /// was fetched
+    fn played_duration(&self) -> Duration {
+        match (&self.status, self.fetched) {
+            (Status::Playing, Some(fetched)) => {
+                (self.played_duration + fetched.elapsed()).min(self.music_duration)
+            },
+            _ => self.played_duration,
+        }
+    }
+
+    /// Asks the server for everything but the metadata, failing if the
+    /// server can't be reached
+    pub async fn fetch(proxy: &ServerProxy<'_>, commands_run: usize) -> zbus::Result<Batch> {
+        let fetched = Some(Instant::now());
+        let status = proxy.status().await?;
+        let (played_duration, music_duration) = State::handle_timer(&proxy.timer().await?);
+        let playing_music = proxy.playing().await.map(Music::from).unwrap_or_default();
+        let mut l = status.split_terminator("\n");
+        let state = l.next().unwrap_or("Stopp");
+        let path = l.next().unwrap_or("");
+        let volume = l.next().unwrap_or("0.5");
+        Ok(Batch {
+            played_duration,
+            music_duration,
+            playing_music,
+            status: State::handle_state(state),
+            volume: State::handle_volume(volume),
+            music_path: State::handle_path(path),
+            metadata: Metadata::default(),
+            fetched,
+            commands_run,
+        })
+    }
+}
+
+/// Request to the server, sent by the [State] to a background task so
+/// the UI does not wait for it
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerCommand {
+    Play(PathBuf),
+    End,
+    Seek(f64),
+    Resume,
+    Pause,
+    /// volume level to set, out of 100
+    Volume(f64),
+    ToggleMute,
+}
+
+/// What the server made of a [ServerCommand], the error of the call if
+/// it could not be made
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub command: ServerCommand,
+    pub result: Result<RunStatus, String>,
+}
+
+impl ServerCommand {
+    async fn run(&self, proxy: &ServerProxy<'_>) -> zbus::Result<RunStatus> {
+        match self {
+            ServerCommand::Play(path) => proxy.play(path).await,
+            ServerCommand::End => proxy.end().await,
+            ServerCommand::Seek(amount) => proxy.seek(*amount).await,
+            ServerCommand::Resume => proxy.resume().await,
+            ServerCommand::Pause => proxy.pause().await,
+            ServerCommand::Volume(amount) => proxy.volume(*amount).await,
+            ServerCommand::ToggleMute => proxy.toggle_mute().await,
+        }
+    }
+}
+
+/// Keeps _shared_ up to date with the server behind _proxy_, fetching a
+/// [Batch] whenever _refresh_ receives something and every _interval_
+/// otherwise, _fetched_ receiving whether each fetch worked
+async fn watch_server(
+    proxy: ServerProxy<'static>, shared: Arc<Mutex<Batch>>, commands_run: Arc<AtomicUsize>,
+    refresh: Receiver<()>, fetched: Sender<bool>, interval: Duration,
+) {
+    let mut delay = interval;
+    loop {
+        match Batch::fetch(&proxy, commands_run.load(Ordering::SeqCst)).await {
+            Ok(batch) => {
+                if let Ok(mut shared) = shared.lock() {
+                    *shared = batch;
+                }
+                let _ = fetched.try_send(true);
+                delay = interval;
+            },
+            // the last known state stays until the server is back, which
+            // is asked less and less often
+            Err(_) => {
+                let _ = fetched.try_send(false);
+                delay = (delay * 2).min(MAX_POLL_INTERVAL.max(interval));
+            },
+        }
+        if let Ok(Err(_)) = future::timeout(delay, refresh.recv()).await {
+            // the state is gone
+            return
+        }
+    }
+}
+
+/// Runs the commands received from _commands_ in order, sending their
+/// [Outcome] to _outcomes_ and asking for a refresh through _refresh_
+/// once each of them is done
+async fn run_commands(
+    proxy: ServerProxy<'static>, commands: Receiver<ServerCommand>,
+    commands_run: Arc<AtomicUsize>, refresh: Sender<()>, outcomes: Sender<Outcome>,
+) {
+    while let Ok(command) = commands.recv().await {
+        let result = command.run(&proxy).await;
+        if let Err(error) = &result {
+            utils::report(&format!("server: {:?} failed, {}", command, error));
+        }
+        let _ = outcomes.try_send(Outcome { command, result: result.map_err(|error| error.to_string()) });
+        commands_run.fetch_add(1, Ordering::SeqCst);
+        let _ = refresh.try_send(());
+    }
+}
+
+/// Asks for a refresh through _refresh_ whenever the server behind _proxy_
+/// emits a signal, `PropertiesChanged` or one of its own
+async fn forward_signals(proxy: ServerProxy<'static>, refresh: Sender<()>) {
+    let inner = proxy.inner();
+    let rule = MatchRule::builder()
+        .msg_type(message::Type::Signal)
+        .path(inner.path().to_owned())
+        .map(|rule| rule.build());
+    let Ok(rule) = rule else {
+        return
+    };
+    // servers without signals are only polled
+    let Ok(mut signals) = MessageStream::for_match_rule(rule, inner.connection(), None).await else {
+        return
+    };
+    while signals.next().await.is_some() {
+        if refresh.is_closed() {
+            return
+        }
+        let _ = refresh.try_send(());
+    }
 }
 
 /// Handle state management with the bus server
@@ -47,35 +200,36 @@ pub struct State<'a> {
     pub proxy: ServerProxy<'a>,
     /// batch of all possible derived values from the server proxy
     pub batch: Batch,
+    /// batch kept up to date by a background task, see [State::watch]
+    shared: Option<Arc<Mutex<Batch>>>,
+    /// asks the background task for a new batch
+    refresh: Option<Sender<()>>,
+    /// commands run by a background task, see [State::watch]
+    commands: Option<Sender<ServerCommand>>,
+    /// number of commands sent to the background task
+    sent: usize,
+    /// outcomes of the commands run in the background
+    outcomes: Option<Receiver<Outcome>>,
+    /// outcomes of the commands run right away, when there is no
+    /// background task
+    ran: Vec<Outcome>,
 }
 
 impl<'a> State<'a> {
-    pub fn get_playing_index(&self, musics: &Vec<Music>) -> usize{
-        let target = block_on(self.proxy.playing());
-        match target {
-            Ok(target) => {
-                for (index, music) in musics.iter().enumerate() {
-                    if music == &target {
-                        return index
-                    }
-                }
-                return 0
-            },
-            Err(_) => {
-                return 0
-            },
-        }
+    pub fn get_playing_index(&self, musics: &[Music]) -> usize{
+        let target = &self.batch.playing_music;
+        musics.iter().position(|music| music.path == target.path).unwrap_or(0)
     }
 
     fn handle_state(input: &str) -> Status {
         // stopping or stopped
         if input.contains("Stopped") {
-            return Status::Stopping
+            Status::Stopping
         // Pausing or paused
         } else if input.contains("Pausing") {
-            return Status::Pausing
+            Status::Pausing
         }else {
-            return Status::Playing
+            Status::Playing
         }
     }
 
@@ -87,122 +241,128 @@ impl<'a> State<'a> {
     fn handle_volume(input: &str) -> f64{
         let lines = input.splitn(2, ":").collect::<Vec<&str>>();
         let volume_s = lines.get(1).unwrap_or(&"0.5").trim();
-        let volume = volume_s.parse::<f64>().unwrap();
-        volume
-    }
-
-    /// Handes the bus `status` call
-    /// Reutrn tuple of (status, path, duration) where:
-    /// - status: status of the server player
-    /// - path: path to the currently playing song 
-    /// - duration: played duration
-    fn hande_status_call(&self) -> (Status, String, f64) {
-        // call to be handled
-        let status = block_on(self.proxy.status())
-            .unwrap();
-        let mut l = status.split_terminator("\n");
-        let state = l.next().unwrap_or("Stopped");
-        let path = l.next().unwrap_or("");
-        let volume = l.next().unwrap_or("0.5");
-        (State::handle_state(state), State::handle_path(path), State::handle_volume(volume))
-    }
-
-    /// gets the syncronized played duration
-    fn handle_timer_call(&self) -> (Duration, Duration) {
-        let timer = block_on(self.proxy.timer()).unwrap_or(String::from("0.0/0.0"));
-        let s = timer.splitn(2,'/').collect::<Vec<&str>>();
-        let len = s.get(0).unwrap().parse::<f64>().unwrap();
-        let secs = s.get(1).unwrap().parse::<f64>().unwrap();
-        (Duration::from_secs_f64(secs), Duration::from_secs_f64(len))
-    }
-
-    /// gets the syncronized played duration
-    fn handle_playing_music_call(&self) -> Music {
-        block_on(self.proxy.playing()).unwrap_or_default()
+        volume_s.parse::<f64>().unwrap_or(0.5)
     }
 
-    /// gets metadata of the metadata of the currently playing music
-    fn handle_metadata_call(&self) -> Metadata {
-        block_on(self.proxy.metadata()).unwrap_or_default()
+    /// Played and full duration out of the `timer` reply, formatted as
+    /// `full length/played duration` in seconds, zero where unreadable
+    fn handle_timer(input: &str) -> (Duration, Duration) {
+        let mut parts = input.splitn(2, '/')
+            .map(|part| part.trim().parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0).unwrap_or(0.0))
+            .map(Duration::from_secs_f64);
+        let length = parts.next().unwrap_or_default();
+        let played = parts.next().unwrap_or_default();
+        (played, length)
     }
 
-    pub fn finished_playing(&self) -> bool {
-        let timer = block_on(self.proxy.timer()).unwrap_or(String::from("0.0/0.0"));
-        let s = timer.splitn(2,'/').collect::<Vec<&str>>();
-        let len = s.get(0).unwrap().parse::<f64>().unwrap();
-        let played = s.get(1).unwrap().parse::<f64>().unwrap();
-        if len != 0.0 && played == 0.0 {return false} else {return true}
-    }
-
-
-    pub fn batch_calls(&mut self) {
-        (self.batch.played_duration, self.batch.music_duration) = self.handle_timer_call();
-        self.batch.playing_music = self.handle_playing_music_call();
-        (self.batch.status, self.batch.music_path , self.batch.volume) = self.hande_status_call();
-        self.batch.metadata = self.handle_metadata_call();
-    }
-
-    /// Handes the bus `status` call
-    /// Reutrn tuple of (status, path, duration) where:
-    /// - status: status of the server player
-    /// - path: path to the currently playing song 
-    /// - duration: played duration
-    async fn async_handle_status_call(&mut self) {
-        let status = self.proxy.status().await.unwrap_or_default();
-        let mut l = status.split_terminator("\n");
-        let state = l.next().unwrap_or("Stopp");
-        let path = l.next().unwrap_or("");
-        let volume = l.next().unwrap_or("0.5");
-        (self.batch.status, self.batch.music_path , self.batch.volume) =
-            (State::handle_state(state), State::handle_path(path), State::handle_volume(volume))
+    /// Dervies what ever is deriveable from a dbus call to the server
+    /// and stores everything in the [State] object, returns false if the
+    /// server could not be reached
+    pub async fn async_batch_calls(&mut self) -> bool {
+        match Batch::fetch(&self.proxy, self.sent).await {
+            Ok(batch) => {
+                self.batch = batch;
+                true
+            },
+            Err(_) => false,
+        }
     }
 
-    pub async fn async_finished_playing(&self) -> bool {
-        let timer = self.proxy.timer().await.unwrap_or(String::from("0.0/0.0"));
-        let s = timer.splitn(2,'/').collect::<Vec<&str>>();
-        let len = s.get(0).unwrap().parse::<f64>().unwrap();
-        let played = s.get(1).unwrap().parse::<f64>().unwrap();
-        if len != 0.0 && played == 0.0 {return false} else {return true}
+    pub fn new(proxy: ServerProxy<'a>) -> State<'a>{
+        State {
+            proxy,
+            batch:Batch::default(),
+            shared: None,
+            refresh: None,
+            commands: None,
+            sent: 0,
+            outcomes: None,
+            ran: Vec::new(),
+        }
     }
 
-    /// gets the syncronized played duration
-    async fn async_handle_timer_call(&mut self) {
-        let timer = self.proxy.timer().await.unwrap_or(String::from("0.0/0.0"));
-        let s = timer.splitn(2,'/').collect::<Vec<&str>>();
-        let len = s.get(0).unwrap().parse::<f64>().unwrap();
-        let played = s.get(1).unwrap().parse::<f64>().unwrap();
-        (self.batch.played_duration, self.batch.music_duration) = (Duration::from_secs_f64(played), Duration::from_secs_f64(len))
+    /// Keeps a [Batch] up to date in the background, on the signals of the
+    /// server and every _interval_, for [State::sync] to pick up, and runs
+    /// the commands in the background too, _fetched_ receiving whether
+    /// each fetch worked
+    pub async fn watch(&mut self, interval: Duration, fetched: Sender<bool>) -> zbus::Result<()> {
+        let inner = self.proxy.inner();
+        let proxy = ServerProxy::builder(inner.connection())
+            .destination(inner.destination().to_owned())?
+            .path(inner.path().to_owned())?
+            .build().await?;
+        // the state is known from the start, the next ones come in the background
+        self.async_batch_calls().await;
+        let shared = Arc::new(Mutex::new(self.batch.clone()));
+        let commands_run = Arc::new(AtomicUsize::new(self.sent));
+        // a single pending refresh is enough, the next batch covers them all
+        let (refresh, refreshes) = channel::bounded(1);
+        let (commands, received) = channel::unbounded();
+        let (outcomes, outcomes_received) = channel::unbounded();
+        task::spawn(forward_signals(proxy.clone(), refresh.clone()));
+        task::spawn(run_commands(proxy.clone(), received, commands_run.clone(), refresh.clone(), outcomes));
+        task::spawn(watch_server(proxy, shared.clone(), commands_run, refreshes, fetched, interval));
+        self.refresh = Some(refresh);
+        self.shared = Some(shared);
+        self.commands = Some(commands);
+        self.outcomes = Some(outcomes_received);
+        Ok(())
+    }
+
+    /// Takes the batch of the background task if it is newer than the
+    /// one at hand and no command is left to run, asks the server right
+    /// away if there is no such task, returns true if the batch changed
+    pub fn sync(&mut self) -> bool {
+        let Some(shared) = &self.shared else {
+            return block_on(self.async_batch_calls())
+        };
+        let Ok(shared) = shared.lock() else {
+            return false
+        };
+        if shared.fetched > self.batch.fetched && shared.commands_run >= self.sent {
+            self.batch = shared.clone();
+            return true
+        }
+        false
     }
 
-    /// gets the syncronized played duration
-    async fn  async_handle_playing_music_call(&mut self) {
-        self.batch.playing_music = self.proxy.playing().await.unwrap_or_default();
+    /// Outcomes of the commands run since the last call, in order
+    pub fn outcomes(&mut self) -> Vec<Outcome> {
+        let mut outcomes = std::mem::take(&mut self.ran);
+        if let Some(received) = &self.outcomes {
+            while let Ok(outcome) = received.try_recv() {
+                outcomes.push(outcome);
+            }
+        }
+        outcomes
     }
 
-    /// gets metadata of the metadata of the currently playing music
-    async fn async_handle_metadata_call(&mut self) {
-        self.batch.metadata = self.proxy.metadata().await.unwrap_or_default()
+    /// Asks the background task for a new batch right away
+    pub fn refresh(&self) {
+        if let Some(refresh) = &self.refresh {
+            let _ = refresh.try_send(());
+        }
     }
 
-    /// Dervies what ever is deriveable from a dbus call to the server
-    /// and stores everything in the [State] object
-    pub async fn async_batch_calls(&mut self) {
-        // self.async_handle_metadata_call();
-        self.async_handle_timer_call().await;
-        self.async_handle_playing_music_call().await;
-        self.async_handle_status_call().await;
+    /// Applies what a command just sent to the server is expected to do to
+    /// the batch at hand, until a batch fetched after the command was run
+    /// comes in
+    fn touch(&mut self, expected: impl FnOnce(&mut Batch)) {
+        self.advance();
+        expected(&mut self.batch);
+        self.batch.fetched = Some(Instant::now());
     }
 
-    pub fn new(proxy: ServerProxy<'a>) -> State{
-        State {
-            proxy,
-            batch:Batch::default(),
-        }
+    /// Moves the played duration forward by the time spent playing since
+    /// the batch was fetched
+    fn advance(&mut self) {
+        self.batch.played_duration = self.played_duration();
     }
 
-    /// Played duration of the currently playing music
+    /// Played duration of the currently playing music, counting the time
+    /// spent playing since the batch was fetched
     pub fn played_duration(&self) -> Duration {
-        self.batch.played_duration
+        self.batch.played_duration()
     }
 
     /// Playing music full duration
@@ -215,6 +375,18 @@ impl<'a> State<'a> {
         self.batch.playing_music.to_owned()
     }
 
+    /// Path of the currently playing [Music], empty if nothing plays
+    pub fn playing_path(&self) -> &Path {
+        &self.batch.playing_music.path
+    }
+
+    #[allow(dead_code)]
+    /// Takes _music_ as the playing one until the next fetch, as if the
+    /// server reported it
+    pub fn set_playing_music(&mut self, music: Music) {
+        self.batch.playing_music = music;
+    }
+
     /// Playing status <Playing|Pausing|Stopping>
     pub fn status(&self) -> Status {
         self.batch.status.to_owned()
@@ -236,74 +408,92 @@ impl<'a> State<'a> {
     }
 
     /// plays the music from the path
-    pub fn play(&self, path: &PathBuf) {
-        block_on(self.proxy.play(path)).unwrap();
+    pub fn play(&mut self, path: &PathBuf) {
+        self.send(ServerCommand::Play(path.to_owned()));
+        self.touch(|batch| {
+            batch.status = Status::Playing;
+            batch.played_duration = Duration::ZERO;
+        });
     }
 
     /// Stops the music playre
-    pub fn end(&self) {
-        block_on(self.proxy.end()).unwrap();
+    pub fn end(&mut self) {
+        self.send(ServerCommand::End);
+        self.touch(|batch| {
+            batch.status = Status::Stopping;
+            batch.played_duration = Duration::ZERO;
+        });
     }
 
     /// Seeks by x secons from the current playing time stamp
-    pub fn seek(&self, amount: f64) {
-        block_on(self.proxy.seek(amount)).unwrap();
+    pub fn seek(&mut self, amount: f64) {
+        self.send(ServerCommand::Seek(amount));
+        self.touch(|batch| {
+            let played = batch.played_duration.as_secs_f64() + amount;
+            batch.played_duration = Duration::from_secs_f64(played.max(0.0)).min(batch.music_duration);
+        });
     }
 
     /// Resumes the player 
-    pub fn resume(&self) {
-        block_on(self.proxy.resume()).unwrap();
+    pub fn resume(&mut self) {
+        self.send(ServerCommand::Resume);
+        self.touch(|batch| batch.status = Status::Playing);
     }
 
     /// Pauses the player
-    pub fn pause(&self) {
-        block_on(self.proxy.pause()).unwrap();
+    pub fn pause(&mut self) {
+        self.send(ServerCommand::Pause);
+        self.touch(|batch| batch.status = Status::Pausing);
     }
 
-    /// Changes playing volume, positive value increase 
-    /// volume, and negative decreases
-    pub fn change_volume(&self, amount: f64) {
-        block_on(self.proxy.volume(amount)).unwrap();
+    /// Sets the playing volume to _amount_, out of 100
+    pub fn change_volume(&mut self, amount: f64) {
+        self.send(ServerCommand::Volume(amount));
+        self.touch(|batch| batch.volume = amount / 100.0);
     }
     
     /// Toggle mtue sate
-    pub fn toggle_mute(&self) {
-        block_on(self.proxy.toggle_mute()).unwrap();
-    }
-
-    /// plays the music from the path
-    pub async fn async_play(&self, path: &PathBuf) {
-        self.proxy.play(path).await.unwrap();
-    }
-
-    /// Stops the music playre
-    pub async fn async_end(&self) {
-        self.proxy.end().await.unwrap();
-    }
-
-    /// Seeks by x secons from the current playing time stamp
-    pub async fn async_seek(&self, amount: f64) {
-        self.proxy.seek(amount).await.unwrap();
-    }
-
-    /// Resumes the player 
-    pub async fn async_resume(&self) {
-        self.proxy.resume().await.unwrap();
-    }
-
-    /// Pauses the player
-    pub async fn async_pause(&self) {
-        self.proxy.pause().await.unwrap();
-    }
-
-    /// Changes playing volume, positive value increase 
-    /// volume, and negative decreases
-    pub async fn async_change_volume(&self, amount: f64) {
-        self.proxy.volume(amount).await.unwrap();
+    pub fn toggle_mute(&mut self) {
+        self.send(ServerCommand::ToggleMute);
+        self.touch(|_| {});
+    }
+
+    /// Hands _command_ to the background task, runs it right away if
+    /// there is no such task
+    fn send(&mut self, command: ServerCommand) {
+        let Some(commands) = &self.commands else {
+            let result = block_on(command.run(&self.proxy));
+            if let Err(error) = &result {
+                utils::report(&format!("server: {}", error));
+            }
+            self.ran.push(Outcome { command, result: result.map_err(|error| error.to_string()) });
+            return
+        };
+        if commands.try_send(command).is_ok() {
+            self.sent += 1;
+        }
     }
+}
 
-    /// Toggle mtue sate
-    pub async fn async_toggle_mute(&self) {
-        self.proxy.toggle_mute().await.unwrap();
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_played_duration() {
+        let fetched = Instant::now().checked_sub(Duration::from_secs(3));
+        let batch = Batch {
+            played_duration: Duration::from_secs(10),
+            music_duration: Duration::from_secs(12),
+            status: Status::Pausing,
+            fetched,
+            ..Default::default()
+        };
+        assert_eq!(batch.played_duration(), Duration::from_secs(10));
+        // moves on while playing, up to the end of the music
+        let batch = Batch { status: Status::Playing, ..batch };
+        assert_eq!(batch.played_duration(), Duration::from_secs(12));
+        let batch = Batch { music_duration: Duration::from_secs(60), ..batch };
+        assert!(batch.played_duration() >= Duration::from_secs(13));
     }
 }
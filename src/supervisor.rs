@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use async_std::{channel::{self, Receiver, Sender}, future, stream::StreamExt, task};
+use zbus::fdo::DBusProxy;
+
+use crate::events::AppEvent;
+use crate::ServerProxy;
+
+/// First delay before asking an offline server again, doubled on every failure
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Whether the mplayer server answers
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ServerStatus {
+    #[default]
+    Online,
+    /// the server is asked again after _retry_in_
+    Offline { retry_in: Duration },
+}
+
+/// What the supervisor learns about the server between two checks
+#[derive(Debug)]
+enum Hint {
+    /// the bus name of the server got an owner, or lost it
+    Owner(bool),
+    /// a fetch of the player state worked, or failed
+    Fetched(bool),
+}
+
+/// Delay after _backoff_ failed again
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(MAX_BACKOFF)
+}
+
+/// Follows the server behind _proxy_: sends an [AppEvent::Server] to
+/// _events_ whenever it goes offline or comes back, asks an offline server
+/// again with a growing delay, and right away once its bus name gets an
+/// owner, successful fetches from _fetched_ are sent as [AppEvent::State]
+pub fn spawn_supervisor(proxy: ServerProxy<'static>, fetched: Receiver<bool>, events: Sender<AppEvent>) {
+    let (hints, received) = channel::unbounded();
+    task::spawn(forward_owner_changes(proxy.clone(), hints.clone()));
+    let states = events.clone();
+    task::spawn(async move {
+        while let Ok(worked) = fetched.recv().await {
+            if worked && states.send(AppEvent::State).await.is_err() {
+                return
+            }
+            if hints.send(Hint::Fetched(worked)).await.is_err() {
+                return
+            }
+        }
+    });
+    task::spawn(supervise(proxy, received, events));
+}
+
+/// Sends a [Hint::Owner] to _hints_ whenever the bus name of the server
+/// behind _proxy_ changes hands
+async fn forward_owner_changes(proxy: ServerProxy<'static>, hints: Sender<Hint>) {
+    let inner = proxy.inner();
+    let Ok(bus) = DBusProxy::new(inner.connection()).await else {
+        return
+    };
+    let name = inner.destination().to_string();
+    let Ok(mut changes) = bus.receive_name_owner_changed_with_args(&[(0, name.as_str())]).await else {
+        return
+    };
+    while let Some(change) = changes.next().await {
+        let Ok(args) = change.args() else {
+            continue
+        };
+        if hints.send(Hint::Owner(args.new_owner().is_some())).await.is_err() {
+            return
+        }
+    }
+}
+
+async fn supervise(proxy: ServerProxy<'static>, hints: Receiver<Hint>, events: Sender<AppEvent>) {
+    let mut online = proxy.status().await.is_ok();
+    let mut backoff = MIN_BACKOFF;
+    // when an offline server is asked again, hints coming in meanwhile
+    // don't push it back
+    let mut retry_at = Instant::now() + backoff;
+    if !online {
+        let _ = events.send(AppEvent::Server(ServerStatus::Offline { retry_in: backoff })).await;
+    }
+    loop {
+        let hint = if online {
+            hints.recv().await.ok()
+        } else {
+            match future::timeout(retry_at.saturating_duration_since(Instant::now()), hints.recv()).await {
+                Ok(hint) => hint.ok(),
+                // time to ask again
+                Err(_) => None,
+            }
+        };
+        if hints.is_closed() && hints.is_empty() {
+            return
+        }
+        let check = match hint {
+            // the state being fetched is as good as an answer
+            Some(Hint::Fetched(true)) => {
+                if !online {
+                    online = true;
+                    backoff = MIN_BACKOFF;
+                    let _ = events.send(AppEvent::Server(ServerStatus::Online)).await;
+                }
+                false
+            },
+            // an offline server is only asked again once the delay is over
+            Some(Hint::Owner(false)) | Some(Hint::Fetched(false)) if !online => false,
+            // the server may be gone
+            Some(Hint::Owner(false)) | Some(Hint::Fetched(false)) => true,
+            // a new server is asked right away
+            Some(Hint::Owner(true)) => {
+                backoff = MIN_BACKOFF;
+                !online
+            },
+            None => !online,
+        };
+        if !check {
+            continue
+        }
+        let answered = proxy.status().await.is_ok();
+        match (online, answered) {
+            (false, true) => {
+                online = true;
+                backoff = MIN_BACKOFF;
+                let _ = events.send(AppEvent::Server(ServerStatus::Online)).await;
+            },
+            (true, false) => {
+                online = false;
+                backoff = MIN_BACKOFF;
+                retry_at = Instant::now() + backoff;
+                let _ = events.send(AppEvent::Server(ServerStatus::Offline { retry_in: backoff })).await;
+            },
+            (false, false) => {
+                backoff = next_backoff(backoff);
+                retry_at = Instant::now() + backoff;
+                let _ = events.send(AppEvent::Server(ServerStatus::Offline { retry_in: backoff })).await;
+            },
+            (true, true) => {},
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = MIN_BACKOFF;
+        let mut delays = vec![];
+        for _ in 0..8 {
+            backoff = next_backoff(backoff);
+            delays.push(backoff.as_millis());
+        }
+        assert_eq!(delays, vec![1000, 2000, 4000, 8000, 16000, 30000, 30000, 30000]);
+    }
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::ui::UIStyle;
+
+/// Names of the built-in themes
+pub const THEMES: [&str; 4] = ["default", "gruvbox", "nord", "monochrome"];
+
+/// The `[theme]` config: a built-in theme and the fields overriding it,
+/// one table per region, e.g. `[theme.list]`
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ThemeConfig {
+    /// built-in theme the fields are applied on, `default` if unset
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub overrides: Map<String, Value>,
+}
+
+/// Every region of a theme out of a few colors, _active_ being the
+/// border of the selected region and _accent_ the selection and the
+/// playing parts
+fn palette(active: &str, passive: &str, accent: &str, text: &str, muted: &str, error: &str, background: &str) -> Value {
+    json!({
+        "list": {
+            "hilight_color": accent,
+            "active_region_color": active,
+            "active_search_region_color": muted,
+            "active_after_search_region_color": accent,
+            "search_error_color": error,
+            "match_style": { "fg": accent, "modifiers": ["bold"] },
+            "passive_region_color": passive,
+            "playing_region_color": text,
+        },
+        "actions": {
+            "hilight_color": accent,
+            "active_region_color": active,
+            "passive_region_color": passive,
+        },
+        "seeker": {
+            "active_region_color": active,
+            "passive_region_color": passive,
+            "fg_seeker_color": text,
+            "bg_seeker_color": background,
+        },
+        "volume": {
+            "active_region_color": active,
+            "passive_region_color": passive,
+            "fg_volume_color": text,
+            "bg_volume_color": background,
+        },
+        "queue": {
+            "hilight_color": accent,
+            "active_region_color": active,
+            "passive_region_color": passive,
+        },
+        "playlists": {
+            "hilight_color": accent,
+            "active_region_color": active,
+            "passive_region_color": passive,
+        },
+        "lyrics": {
+            "active_region_color": active,
+            "passive_region_color": passive,
+            "text_color": muted,
+            "current_line_color": accent,
+        },
+        "messages": {
+            "border_color": active,
+            "text_color": text,
+            "error_color": error,
+            "time_color": muted,
+        },
+    })
+}
+
+/// Fields of the built-in theme _name_, applied on the default one
+fn builtin(name: &str) -> Option<Value> {
+    match name {
+        "default" => Some(json!({})),
+        "gruvbox" => Some(palette("#d3869b", "#928374", "#fabd2f", "#ebdbb2", "#665c54", "#fb4934", "#3c3836")),
+        "nord" => Some(palette("#88c0d0", "#4c566a", "#ebcb8b", "#d8dee9", "#616e88", "#bf616a", "#3b4252")),
+        "monochrome" => {
+            let mut theme = palette("white", "dark-gray", "white", "gray", "dark-gray", "white", "black");
+            merge(&mut theme, json!({
+                "list": { "match_style": { "modifiers": ["bold", "underlined"] } },
+                "lyrics": { "current_line_color": "white" },
+            }));
+            Some(theme)
+        },
+        _ => None,
+    }
+}
+
+/// Writes the fields of _overrides_ over the ones of _base_, tables
+/// being merged rather than replaced
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        },
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Builds the [UIStyle] out of the `[theme]` config, unknown themes,
+/// regions and fields, and invalid colors and modifiers are errors
+pub fn load(config: Option<&ThemeConfig>) -> Result<UIStyle, String> {
+    let Some(config) = config else {
+        return Ok(UIStyle::default())
+    };
+    let name = config.name.as_deref().unwrap_or("default");
+    let theme = builtin(name).ok_or_else(|| format!(
+        "[theme]: unknown theme {:?}, expected one of {}", name, THEMES.join(", ")
+    ))?;
+    let mut style = serde_json::to_value(UIStyle::default()).map_err(|e| e.to_string())?;
+    merge(&mut style, theme);
+    merge(&mut style, Value::Object(config.overrides.to_owned()));
+    serde_json::from_value(style).map_err(|e| format!("[theme]: {}", e))
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_builtin_themes() {
+        for name in THEMES {
+            let config = ThemeConfig { name: Some(name.to_string()), ..Default::default() };
+            assert!(load(Some(&config)).is_ok(), "{}", name);
+        }
+        let config = ThemeConfig { name: Some(String::from("solarized")), ..Default::default() };
+        assert!(load(Some(&config)).is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config: ThemeConfig = basic_toml::from_str(r##"
+            name = "nord"
+            [list]
+            selector = "> "
+            match_style = { fg = "#ff0000", modifiers = ["italic"] }
+        "##).unwrap();
+        let style = serde_json::to_value(load(Some(&config)).unwrap()).unwrap();
+        assert_eq!(style["list"]["selector"], "> ");
+        assert_eq!(style["list"]["match_style"]["fg"], "#FF0000");
+        assert_eq!(style["list"]["match_style"]["modifiers"], json!(["italic"]));
+        // the rest comes from the built-in theme
+        assert_eq!(style["list"]["active_region_color"], "#88C0D0");
+
+        for invalid in [
+            "[list]\nselecter = \">\"", "[list]\nhilight_color = \"blurple\"",
+            "[list]\nmatch_style = { modifiers = [\"shiny\"] }", "[sidebar]\nselector = \">\"",
+        ] {
+            let config: ThemeConfig = basic_toml::from_str(invalid).unwrap();
+            assert!(load(Some(&config)).is_err(), "{}", invalid);
+        }
+    }
+}
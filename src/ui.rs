@@ -1,40 +1,49 @@
-use std::{time::Duration, path::PathBuf};
-use async_std::io;
-use crossterm::event::{self, KeyCode, KeyEvent, KeyModifiers};
-use rand::Rng;
-use async_std::task::block_on;
+use std::{collections::HashMap, time::{Duration, Instant}, path::{Path, PathBuf}, ops::Range};
+use crossterm::event::{self, KeyEvent};
+use async_std::channel::Sender;
 use ratatui::{prelude::*, widgets::*, style::Stylize};
-use lofty;
 use lofty::{
+    config::ParseOptions,
     file::{AudioFile, TaggedFileExt},
-    tag::Accessor
+    id3::v2::{Frame as Id3Frame, FrameId},
+    mpeg::MpegFile,
+    tag::{Accessor, ItemKey, Tag}
 };
 use serde::{Deserialize, Serialize};
 
-use crate::states::{State, Status};
-use crate::utils::StringFeatures;
-use crate::{fuzzy_search, Config, ServerProxy, Sorting};
+use crate::albums::Albums;
+use crate::columns::{Column, ColumnField};
+use crate::keybinds::{Command, Context, Input, KeyChord, Keymap, PendingInput};
+use crate::cache::{LibraryChanges, LibraryEvent};
+use crate::layout::{Pane, ScreenLayout};
+use crate::lyrics::LyricsView;
+use crate::shuffle::{self, Shuffler};
+use crate::messages::{Level, Message, Messages};
+use crate::events::AppEvent;
+use crate::history::{Ending, History, Listening, PlayCounts};
+use crate::scrobble::{Scrobble, ScrobbleQueue};
+use crate::session::{self, Session};
+use crate::supervisor::ServerStatus;
+use crate::mpris::{MprisCommand, PlayerSnapshot};
+use crate::playlist::{Playlist, Playlists};
+use crate::query::{Query, QueryError};
+use crate::queue::PlayQueue;
+use crate::states::{ServerCommand, State, Status};
+use crate::utils::{self, StringFeatures};
+use crate::{Config, ServerProxy, Sorting};
 
 #[derive(Default, Debug)]
 pub enum ListMode {
     Search,
     #[default]
     Select,
-    AfterSearch
+    AfterSearch,
+    /// browsing the musics grouped by album
+    Albums,
 }
 
 
-#[derive(Default)]
-/// Represents a two chars combo in a keybind
-pub enum AncitipationMode {
-    #[default]
-    /// no anticipation
-    Normal,
-    /// contains the anticipated next character
-    Char(char),
-}
-
-#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 /// Represents the music repeat option
 pub enum Repeat {
     /// repeat the currently playing music
@@ -49,6 +58,31 @@ pub enum Repeat {
 pub struct UI<'a> {
     /// list of all the musics to play (*dsiplayed* musics)
     pub music_list: Musics,
+    /// musics queued by the user, played before falling back to the music list
+    pub queue: PlayQueue,
+    /// playlists found in the configured playlist directories
+    pub playlists: Playlists,
+    /// every music found in the music directory
+    library: Vec<Music>,
+    /// index of every music of the library by path
+    library_index: HashMap<PathBuf, usize>,
+    /// lyrics of the currently playing music
+    pub lyrics: LyricsView,
+    /// whether to display the lyrics region
+    lyrics_enabled: bool,
+    /// library changes received since the last update
+    library_changes: LibraryChanges,
+    /// whether the server answers
+    server: ServerStatus,
+    /// whether the playing index is to be found again once a batch of the
+    /// server that came back is there
+    resync: bool,
+    /// results of the last actions and errors, shown in the status bar
+    messages: Messages,
+    /// publishes the player state to the MPRIS clients, if enabled
+    mpris: Option<Sender<PlayerSnapshot>>,
+    /// playing music as last published to the MPRIS clients
+    mpris_music: Option<Music>,
     /// bar indecate the playing timer
     pub power_bar: PowerBar,
     /// currently selected action 
@@ -63,12 +97,50 @@ pub struct UI<'a> {
     just_preformed_action: Action,
     /// search buffer, used to search through the musics list
     search_bufr: String,
-    /// helps reading a combination of keys like `gg`
-    pub anticipation_mode: AncitipationMode,
+    /// albums of the loaded music list, shown in the `Albums` mode
+    albums: Albums,
+    /// why the search buffer could not be parsed, shown in the search box
+    search_error: Option<QueryError>,
+    /// maximum number of search results, all of them are shown if [None]
+    search_limit: Option<usize>,
+    /// commands bound to keys
+    keymap: Keymap,
+    /// count and keys typed so far of a command like `12gg`
+    pending: PendingInput,
+    /// last command run along with its count and character, for `.`, and
+    /// the context it ran in, the only one it's repeated in
+    last_command: Option<(Context, Command, Option<usize>, Option<char>)>,
+    /// musics marked with a character, by path to survive sorting and searching
+    marks: HashMap<char, PathBuf>,
+    /// number of musics the list region displayed on the last render
+    list_height: usize,
+    /// columns of the music list
+    columns: Vec<Column>,
+    /// index of the column the music list is sorted by, and whether it is descending
+    column_sort: Option<(usize, bool)>,
+    /// where the panes go on the screen
+    layout: ScreenLayout,
     /// what to repeat <ThisMusic, AllMusics, None>
     repeat: Repeat,
     /// order list <Yes, No>
     order: Sorting,
+    /// shuffles the music list in the shuffled orders
+    shuffler: Shuffler,
+    /// every play, skip and completion of a music
+    history: History,
+    /// the music playing right now, added to the history once it stops
+    listening: Option<Listening>,
+    /// listens waiting to be submitted by some other machine
+    scrobbles: ScrobbleQueue,
+    /// where the session is saved, not saved if unset
+    session_path: Option<PathBuf>,
+    /// last saved session along with when it was, to only save changes
+    saved_session: Option<(Session, Instant)>,
+    /// music asked to play that the server does not report yet, the
+    /// state until then being the one of the previous music
+    requested: Option<PathBuf>,
+    /// times each music was played according to the history
+    play_counts: PlayCounts,
     state: State<'a>,
 }
 
@@ -77,345 +149,54 @@ enum Action {
     Play, Pause, Resume,
 }
 
-#[derive(Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 /// all the displayed region
 pub enum Region {
-    List, Action, Seeker, Volume
-}
-
-impl Default for Region {
-    fn default() -> Self {
-        Region::List
-    }
-}
-
-
-impl ListMode {
-    pub fn handle_search<'a>(ui: &mut UI<'a>, key: &KeyEvent) {
-        if key.kind == event::KeyEventKind::Press && key.modifiers == KeyModifiers::NONE {
-            match key.code {
-                KeyCode::Char(c) => {
-                    block_on(ui.register_querry(c));
-                },
-                KeyCode::Backspace => {
-                    ui.delete_char_querry()
-                },
-                KeyCode::Enter => {
-                    ui.play_selected_music();
-                    ui.reset_querry();
-                    ui.mode = ListMode::Select;
-                },
-                KeyCode::Esc => {
-                    ui.mode = ListMode::AfterSearch;
-                },
-                _ => {},
-            }
-        }
-    }
-
-    pub fn handle_after_search<'a>(ui: &mut UI<'a>, key: &KeyEvent) -> io::Result<bool> {
-        if key.kind == event::KeyEventKind::Press  {
-            match key.modifiers {
-                KeyModifiers::NONE => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'j' => ui.list_down(),
-                                'k' => ui.list_up(),
-                                '/' => ui.change_list_mode(ListMode::Search), 
-                                'q' => ui.change_list_mode(ListMode::Select),
-                                ' ' => ui.play_after_search(),
-                                _ => {}
-                            }
-                        },
-                        KeyCode::Enter => ui.play_after_search(),
-                        KeyCode::Esc => ui.change_list_mode(ListMode::Select),
-                        _=>{}
-                    }
-                },
-                KeyModifiers::ALT => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'j' => ui.list_down(),
-                                'k' => ui.list_up(),
-                                '/' => ui.change_list_mode(ListMode::Search), 
-                                _ => {}
-                            }
-                        },
-                        _ => {}
-                    }
-                },
-                _ => {}
-            }
-        }
-        Ok(false)
-    }
-
-    pub fn handle_select<'a>(ui: &mut UI<'a>, key: &KeyEvent) -> io::Result<bool>{
-        if key.kind == event::KeyEventKind::Press  {
-            match key.modifiers {
-                // No modifiers
-                KeyModifiers::NONE => {
-                    match ui.anticipation_mode {
-                        // No `g` key pressed beforehand
-                        AncitipationMode::Normal => {
-                            match key.code {
-                                KeyCode::Enter => ui.play_selected_music(),
-                                KeyCode::Char(c) => {
-                                    match c {
-                                        'j' => ui.list_down(),
-                                        'k' => ui.list_up(),
-                                        '/' => ui.change_list_mode(ListMode::Search), 
-                                        ' ' => ui.play_selected_music(),
-                                        's' => ui.goto_playing(),
-                                        'g' => ui.anticipate('g'),
-                                        'G' => ui.goto_bottom(),
-                                        'q' => return Ok(true), 
-                                        _ => {}
-                                    }
-                                },
-                                _ => {}
-                            }
-                        },
-                        AncitipationMode::Char(c) => {
-                            if c == 'g' {
-                                match key.code {
-                                    KeyCode::Char(c) => {
-                                        match c {
-                                            'g' => ui.goto_top(),
-                                            _ => {}
-                                        }
-                                    },
-                                    _ => {}
-                                }
-                            }
-                            ui.anticipation_mode = AncitipationMode::Normal;
-                        },
-                    }
-                }
-                KeyModifiers::ALT => {
-                    if let KeyCode::Char(c) = key.code {
-                        match c {
-                            'k' => ui.select_bar_region(),
-                            'j' => ui.select_action_region(),
-                            _ => {}
-                        }
-                    }
-                }
-                KeyModifiers::SHIFT => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'G' => ui.goto_bottom(),
-                                _ => {}
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                KeyModifiers::CONTROL => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'd' => ui.scroll_list_down(),
-                                'u' => ui.scroll_list_up(),
-                                _ => {}
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                _ =>{}
-            }
-        }
-        Ok(false)
-    }
-}
-
-impl Region {
-    pub fn handle_global<'a>(ui: &mut UI<'a>, key: &KeyEvent) {
-        match key.code {
-            KeyCode::Char(c) => {
-                if c == 'm' {
-                    ui.toggle_mute();
-                }else if c =='p' {
-                    ui.toggle_play();
-                }else if c == 'n' {
-                    ui.play_next();
-                } else if c == 'N' {
-                    ui.play_preivous();
-                }
-            }
-            _ => {}
-        }
-    }
-
-    pub fn handle_list<'a>(ui: &mut UI<'a>, key: &KeyEvent) -> std::io::Result<bool>{
-        match ui.mode {
-            ListMode::Search => {
-                ListMode::handle_search(ui, key);
-            },
-            ListMode::AfterSearch => {
-                if ListMode::handle_after_search(ui, key).is_ok_and(|x| x == true ) {
-                    return Ok(true)
-                }
-            }
-            ListMode::Select => {
-                if ListMode::handle_select(ui, key).is_ok_and(|x| x == true ) {
-                    return Ok(true)
-                }
-            }
-        }
-        Ok(false)
-    }
-
-    pub fn handle_action<'a>(ui: &mut UI<'a>, key: &KeyEvent) -> std::io::Result<bool> {
-        if key.kind == event::KeyEventKind::Press {
-            match key.modifiers {
-                KeyModifiers::NONE => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'q' => return Ok(true),
-                                'l' => ui.next_action(),
-                                'h' => ui.previous_action(),
-                                _ => {}
-                            }
-                        }
-                        KeyCode::Enter => {
-                            ui.preform_action();
-                        },
-                        _ => {}
-                    }
-                }
-                KeyModifiers::ALT => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'j' => ui.select_bar_region(),
-                                'k' =>ui.select_list_region(),
-                                _ => {}
-                            }
-                        }
-                        KeyCode::Enter => {
-                            ui.cycle_back();
-                        },
-                        _ => {}
-                    }
-                }
-                _ => {}
-            }
-        }
-        Ok(false)
-    }
-
-    pub fn handle_seeker<'a>(ui: &mut UI<'a>, key: &KeyEvent) -> std::io::Result<bool> {
-        if key.kind == event::KeyEventKind::Press {
-            match key.modifiers {
-                KeyModifiers::NONE => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'l' => ui.next_5s(),
-                                'h' => ui.previous_5s(),
-                                'k' => ui.toggle_play(),
-                                'q' => return Ok(true), 
-                                _ => {}
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                KeyModifiers::ALT => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'j' => ui.select_list_region(),
-                                'k' => ui.select_action_region(),
-                                'y' | 'l' => ui.select_volume_region(),
-                                _ => {}
-                            }
-                        }
-                        _ => {}
-                    }
-
-                }
-                _ => {}
-            }
-        }
-        Ok(false)
-    }
-
-    pub fn handle_volume<'a>(ui: &mut UI<'a>, key: &KeyEvent) -> std::io::Result<bool> {
-        if key.kind == event::KeyEventKind::Press {
-            match key.modifiers {
-                KeyModifiers::NONE => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'k' | 'l' => ui.increase_volume(),
-                                'h' | 'j' => ui.decrease_volume(),
-                                'q' => return Ok(true),
-                                _ => {}
-                            }
-                        }
-                        _ => {}
-                    }
-
-                },
-                KeyModifiers::ALT => {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'j' => ui.select_list_region(),
-                                'k' => ui.select_action_region(),
-                                 'l' | 'h' => ui.select_bar_region(),
-                                _ => {}
-                            }
-                        }
-                        _ => {}
-                    }
-                },
-                KeyModifiers::SHIFT=> {
-                    match key.code {
-                        KeyCode::Char(c) => {
-                            match c {
-                                'j' | 'h' => ui.increase_volume(),
-                                'k' | 'l' => ui.decrease_volume(),
-                                _ => {}
-                            }
-                        }
-                        _ => {}
-                    }
-
-                }
-                _ => {}
-            }
-        }
-        Ok(false)
-    }
+    #[default]
+    List, Action, Seeker, Volume, Queue, Playlists, Lyrics
 }
 
-#[derive(Default)]
+/// Colors and selectors of every region, read from the `[theme]` config
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct UIStyle {
+    #[serde(rename = "list")]
     list_style: ListStyle,
+    #[serde(rename = "actions")]
     action_style: ActionStyle,
+    #[serde(rename = "seeker")]
     seeker_style: SeekerStyle,
+    #[serde(rename = "volume")]
     volume_style: VolumeStyle,
+    #[serde(rename = "queue")]
+    queue_style: QueueStyle,
+    #[serde(rename = "playlists")]
+    playlists_style: PlaylistsStyle,
+    #[serde(rename = "lyrics")]
+    lyrics_style: LyricsStyle,
+    #[serde(rename = "messages")]
+    messages_style: MessagesStyle,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ListStyle {
     hilight_color: Color,
     active_region_color: Color,
     active_search_region_color: Color,
     active_after_search_region_color: Color,
+    search_error_color: Color,
+    /// style of the characters matched by the search
+    #[serde(with = "crate::parser::style_config")]
+    match_style: Style,
     passive_region_color: Color,
     selector: String,
     playing_selector: String,
     playing_region_color: Color,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SeekerStyle {
     active_region_color: Color,
     passive_region_color: Color,
@@ -423,6 +204,8 @@ pub struct SeekerStyle {
     bg_seeker_color: Color,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct VolumeStyle {
     active_region_color: Color,
     passive_region_color: Color,
@@ -430,12 +213,52 @@ pub struct VolumeStyle {
     bg_volume_color: Color,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ActionStyle {
     hilight_color: Color,
     active_region_color: Color,
     passive_region_color: Color,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueueStyle {
+    hilight_color: Color,
+    active_region_color: Color,
+    passive_region_color: Color,
+    selector: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LyricsStyle {
+    active_region_color: Color,
+    passive_region_color: Color,
+    text_color: Color,
+    current_line_color: Color,
+}
+
+/// Style of the status bar and of the message history
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessagesStyle {
+    border_color: Color,
+    text_color: Color,
+    error_color: Color,
+    time_color: Color,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaylistsStyle {
+    hilight_color: Color,
+    active_region_color: Color,
+    passive_region_color: Color,
+    selector: String,
+    loaded_selector: String,
+}
+
 impl Default for ListStyle {
     fn default() -> Self {
         ListStyle {
@@ -444,6 +267,8 @@ impl Default for ListStyle {
             active_region_color: Color::Magenta,
             active_after_search_region_color: Color::Cyan,
             active_search_region_color: Color::DarkGray,
+            search_error_color: Color::Red,
+            match_style: Style::new().fg(Color::Yellow).bold(),
             passive_region_color: Color::default(),
             selector: String::from(">>"),
             playing_selector: String::from("*")
@@ -451,20 +276,6 @@ impl Default for ListStyle {
     }
 }
 
-impl UIStyle {
-    #[allow(dead_code)]
-    pub fn new(
-        list_style: ListStyle,
-        action_style: ActionStyle,
-        seeker_style: SeekerStyle,
-        volume_style: VolumeStyle,
-    ) -> Self {
-        UIStyle {
-            list_style, action_style, seeker_style, volume_style
-        }
-    }
-}
-
 impl Default for SeekerStyle {
     fn default() -> Self {
         SeekerStyle {
@@ -498,12 +309,69 @@ impl Default for ActionStyle {
     }
 }
 
+impl Default for LyricsStyle {
+    fn default() -> Self {
+        LyricsStyle {
+            active_region_color: Color::Magenta,
+            passive_region_color: Color::default(),
+            text_color: Color::DarkGray,
+            current_line_color: Color::Yellow,
+        }
+    }
+}
+
+impl Default for MessagesStyle {
+    fn default() -> Self {
+        MessagesStyle {
+            border_color: Color::Magenta,
+            text_color: Color::default(),
+            error_color: Color::Red,
+            time_color: Color::DarkGray,
+        }
+    }
+}
+
+impl Default for PlaylistsStyle {
+    fn default() -> Self {
+        PlaylistsStyle {
+            hilight_color: Color::default(),
+            active_region_color: Color::Magenta,
+            passive_region_color: Color::default(),
+            selector: String::from(">>"),
+            loaded_selector: String::from("*"),
+        }
+    }
+}
+
+impl Default for QueueStyle {
+    fn default() -> Self {
+        QueueStyle {
+            hilight_color: Color::default(),
+            active_region_color: Color::Magenta,
+            passive_region_color: Color::default(),
+            selector: String::from(">>"),
+        }
+    }
+}
+
 
 impl<'a> UI<'a>{
     pub fn default(proxy: ServerProxy<'a>) -> Self {
         UI {
             power_bar: PowerBar::default(),
             music_list: Musics::default(),
+            queue: PlayQueue::default(),
+            playlists: Playlists::default(),
+            library: Vec::new(),
+            library_index: HashMap::new(),
+            mpris_music: None,
+            lyrics: LyricsView::default(),
+            lyrics_enabled: false,
+            library_changes: LibraryChanges::default(),
+            server: ServerStatus::default(),
+            resync: false,
+            messages: Messages::default(),
+            mpris: None,
             region: Region::default(),
             style : UIStyle::default(),
             action: PowerActions::BackwardSkip,
@@ -511,9 +379,27 @@ impl<'a> UI<'a>{
             state: State::new(proxy),
             mode: ListMode::default() ,
             search_bufr: String::default(),
-            anticipation_mode: AncitipationMode::default(),
+            albums: Albums::default(),
+            search_error: None,
+            search_limit: None,
+            keymap: Keymap::default(),
+            pending: PendingInput::default(),
+            last_command: None,
+            marks: HashMap::new(),
+            list_height: 0,
+            columns: Column::defaults(false),
+            column_sort: None,
+            layout: ScreenLayout::default(),
             repeat: Repeat::default(),
             order: Sorting::default(),
+            shuffler: Shuffler::default(),
+            history: History::default(),
+            listening: None,
+            scrobbles: ScrobbleQueue::default(),
+            session_path: None,
+            saved_session: None,
+            requested: None,
+            play_counts: PlayCounts::new(),
         }
     }
 
@@ -521,11 +407,325 @@ impl<'a> UI<'a>{
         let config = config.clone();
         self.repeat = config.repeat.unwrap_or_default();
         self.order = config.sorting.unwrap_or_default();
+        self.shuffler = Shuffler::new(config.shuffle_seed);
+        self.playlists = Playlists::new(
+            config.playlists.unwrap_or_default().iter().map(PathBuf::from).collect()
+        );
+        self.lyrics_enabled = config.lyrics.unwrap_or(false);
+        self.search_limit = config.search_limit;
+        if let Some(highlight) = &config.highlight {
+            self.style.list_style.match_style = highlight.style();
+        }
     }
 
     pub fn musics(&mut self, musics: Musics) {
         self.power_bar.song_length = self.state.playing_music_duration();
+        self.library = musics.full_que.clone();
+        self.index_library();
+        self.music_list = musics;
+        self.sort_music_list();
+    }
+
+    /// Sets the play history, the play counts being taken from it
+    pub fn history(&mut self, history: History) {
+        self.play_counts = history.play_counts();
+        self.history = history;
+    }
+
+    /// Sets the queue completed listens are added to
+    pub fn scrobbles(&mut self, scrobbles: ScrobbleQueue) {
+        self.scrobbles = scrobbles;
+    }
+
+    /// Sorts the music list in the current order, a shuffle putting the
+    /// playing music first
+    fn sort_music_list(&mut self) {
+        let play_counts = &self.play_counts;
+        let plays = |music: &Music| play_counts.get(&music.path).copied().unwrap_or(0);
+        match self.order {
+            order if order.is_shuffle() => {
+                let playing = self.state.playing_music().path;
+                self.music_list.shuffle(order, &mut self.shuffler, Some(&playing), |music| {
+                    shuffle::weight(music, plays(music))
+                });
+            },
+            Sorting::MostPlayed => {
+                // ties stay in title order
+                self.music_list.sort(Some(Sorting::ByTitleAscending));
+                self.music_list.sort_by(|a, b| plays(b).cmp(&plays(a)));
+            },
+            order => self.music_list.sort(Some(order)),
+        }
+    }
+
+    /// Sets the commands bound to keys
+    pub fn keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    /// Sets where the panes go on the screen
+    pub fn layout(&mut self, layout: ScreenLayout) {
+        self.layout = layout;
+    }
+
+    /// Sets the columns of the music list
+    pub fn columns(&mut self, columns: Vec<Column>) {
+        self.columns = columns;
+        self.column_sort = None;
+    }
+
+    /// Keeps a library change for the next update, where all of them
+    /// are applied at once
+    pub fn library_event(&mut self, event: LibraryEvent) {
+        self.library_changes.push(event);
+    }
+
+    /// Keeps the player state up to date in the background, on the signals
+    /// of the server and every _interval_, instead of asking the server on
+    /// every render, _fetched_ receiving whether each fetch worked
+    pub async fn watch_server(&mut self, interval: Duration, fetched: Sender<bool>) -> zbus::Result<()> {
+        self.state.watch(interval, fetched).await
+    }
+
+    /// Sets the channel the player state is published to for the MPRIS clients
+    pub fn mpris(&mut self, publisher: Sender<PlayerSnapshot>) {
+        self.mpris = Some(publisher);
+    }
+
+    /// Handles an event of the event loop, returns true if the client
+    /// should quit
+    pub fn handle_event(&mut self, event: AppEvent) -> bool {
+        match event {
+            AppEvent::Terminal(event::Event::Key(key)) => return self.handle_key(&key),
+            AppEvent::Library(event) => self.library_event(event),
+            AppEvent::Mpris(command) => self.handle_mpris_command(command),
+            AppEvent::Server(status) => self.server_status(status),
+            // the session is saved on a timer in case the client does not quit cleanly
+            AppEvent::Tick => {
+                if self.saved_session.as_ref().is_none_or(|(_, at)| at.elapsed() >= session::SAVE_INTERVAL) {
+                    self.save_session();
+                }
+            },
+            // rendering again is all there is to do
+            AppEvent::Terminal(_) | AppEvent::State => {},
+        }
+        false
+    }
+
+    /// Follows the server going offline or coming back, the playing music
+    /// might have changed in the meantime
+    pub fn server_status(&mut self, status: ServerStatus) {
+        match (&self.server, &status) {
+            (ServerStatus::Offline { .. }, ServerStatus::Online) => {
+                self.resync = true;
+                self.state.refresh();
+                self.messages.push(Message::info("server back online"));
+            },
+            (ServerStatus::Online, ServerStatus::Offline { .. }) => {
+                self.messages.push(Message::error("server offline"));
+            },
+            _ => {},
+        }
+        self.server = status;
+    }
+
+    /// Runs a command sent by an MPRIS client
+    pub fn handle_mpris_command(&mut self, command: MprisCommand) {
+        let played = self.state.played_duration().as_micros() as i64;
+        match command {
+            MprisCommand::PlayPause => self.toggle_play(),
+            MprisCommand::Play => if self.state.status() != Status::Playing {
+                self.toggle_play()
+            },
+            MprisCommand::Pause => if self.state.status() == Status::Playing {
+                self.pause()
+            },
+            MprisCommand::Stop => self.stop(),
+            MprisCommand::Next => self.play_next(),
+            MprisCommand::Previous => self.play_preivous(),
+            MprisCommand::Seek(offset) => self.seek_to(played.saturating_add(offset)),
+            MprisCommand::SetPosition(position) => self.seek_to(position),
+            MprisCommand::Volume(volume) => self.state.change_volume(volume * 100.0),
+        }
+    }
+
+    /// Seeks to _position_ microseconds of the playing music, the start if
+    /// negative, and plays the next music if past its end
+    fn seek_to(&mut self, position: i64) {
+        let length = self.state.playing_music_duration().as_micros() as i64;
+        if position > length {
+            self.play_next();
+            return
+        }
+        let position = Duration::from_micros(position.max(0) as u64);
+        self.power_bar.current_timer = position;
+        self.state.seek(position.as_secs_f64() - self.state.played_duration().as_secs_f64());
+    }
+
+    /// Publishes the player state to the MPRIS clients
+    fn publish_mpris(&mut self) {
+        let Some(mpris) = &self.mpris else {
+            return
+        };
+        let path = Some(self.state.playing_path()).filter(|path| !path.as_os_str().is_empty());
+        if self.mpris_music.as_ref().map(|music| music.path.as_path()) != path {
+            // the server only knows about a few fields, the rest comes from the library
+            self.mpris_music = path.map(|path| {
+                self.library_music(path).cloned().unwrap_or_else(|| self.state.playing_music())
+            });
+        }
+        let snapshot = PlayerSnapshot {
+            status: self.state.status(),
+            music: self.mpris_music.clone(),
+            position: self.state.played_duration(),
+            volume: self.state.volume(),
+            repeat: self.repeat.to_owned(),
+            shuffle: self.order.is_shuffle(),
+        };
+        let _ = mpris.try_send(snapshot);
+    }
+
+    /// Applies the library changes received since the last update
+    pub fn handle_library_events(&mut self) {
+        let changes = std::mem::take(&mut self.library_changes);
+        if changes.is_empty() {
+            return
+        }
+        changes.apply(&mut self.library);
+        self.index_library();
+        // the playing music may have been retagged
+        self.mpris_music = None;
+        // queued musics that vanished from the disk can't be played anymore
+        changes.update(&mut self.queue.musics);
+        self.queue.selected = self.queue.selected.min(self.queue.len().saturating_sub(1));
+        // playlists are not part of the library, new musics are not added to them
+        if self.playlists.loaded == 0 {
+            changes.apply(&mut self.music_list.full_que);
+        } else {
+            changes.update(&mut self.music_list.full_que);
+        }
+        self.refresh_music_list();
+        if let ListMode::Albums = self.mode {
+            self.albums.refresh(&self.music_list.full_que);
+        }
+    }
+
+    /// Indexes the library by path, after it changed
+    fn index_library(&mut self) {
+        self.library_index = self.library.iter().enumerate()
+            .map(|(index, music)| (music.path.clone(), index))
+            .collect();
+    }
+
+    /// The music of the library at _path_
+    fn library_music(&self, path: &Path) -> Option<&Music> {
+        self.library_index.get(path).and_then(|&index| self.library.get(index))
+    }
+
+    /// Re-derives the displayed music list from the full music list, keeping
+    /// the order, the search filter, the selection and the playing index
+    fn refresh_music_list(&mut self) {
+        let selected = self.music_list.que.get(self.music_list.selected).map(|m| m.path.clone());
+        match self.order {
+            // re-shuffling would move everything around on every change
+            order if order.is_shuffle() => self.music_list.que = self.music_list.full_que.clone(),
+            _ => self.sort_music_list(),
+        }
+        if !matches!(self.mode, ListMode::Select) && !self.search_bufr.is_empty() {
+            self.search_error = self.music_list.search(self.search_bufr.to_owned(), self.search_limit).err();
+        }
+        self.music_list.selected = selected
+            .and_then(|path| self.music_list.que.iter().position(|m| m.path == path))
+            .unwrap_or(self.music_list.selected)
+            .min(self.music_list.que.len().saturating_sub(1));
+        // next and previous go through the displayed list
+        self.restore_state();
+    }
+
+    /// Loads the *selected* entry of the playlist browser into the music list,
+    /// the first entry being the whole library
+    pub fn load_selected_playlist(&mut self) {
+        let musics = match self.playlists.selected_path() {
+            Some(path) => {
+                match Playlist::load(path) {
+                    Ok(playlist) => playlist.musics(),
+                    Err(error) => {
+                        let name = Playlist::name(path);
+                        self.messages.push(Message::error(format!("could not load the playlist {name}: {error}")));
+                        return
+                    },
+                }
+            },
+            None => Musics::new(self.library.clone()),
+        };
+        self.playlists.loaded = self.playlists.selected;
+        self.mode = ListMode::Select;
+        self.search_bufr.clear();
         self.music_list = musics;
+        // playlists keep their own order
+        if self.playlists.loaded == 0 {
+            self.sort_music_list();
+        }
+        self.restore_state();
+    }
+
+    /// Appends the *selected* song in the music list to the *selected* playlist
+    pub fn append_selected_to_playlist(&mut self) {
+        let music = match self.music_list.que.get(self.music_list.selected) {
+            Some(music) => music.clone(),
+            None => return,
+        };
+        if let Some(path) = self.playlists.selected_path() {
+            match Playlist::append(path, &music) {
+                Ok(()) if self.playlists.loaded == self.playlists.selected => self.load_selected_playlist(),
+                Ok(()) => (),
+                Err(error) => {
+                    let name = Playlist::name(path);
+                    self.messages.push(Message::error(format!("could not add to the playlist {name}: {error}")));
+                },
+            }
+        }
+    }
+
+    /// Writes the play queue out as a new playlist in the first playlist directory
+    pub fn write_queue_to_playlist(&mut self) {
+        if self.queue.is_empty() {
+            return
+        }
+        let Some(path) = self.playlists.new_playlist_path() else {
+            self.messages.push(Message::error("no playlist directory to save the queue to"));
+            return
+        };
+        match Playlist::write(&path, &self.queue.musics) {
+            Ok(()) => {
+                self.playlists.refresh();
+                self.messages.push(Message::info(format!("queue saved as the playlist {}", Playlist::name(&path))));
+            },
+            Err(error) => self.messages.push(Message::error(format!("could not save the queue: {error}"))),
+        }
+    }
+
+    /// Moves slection to the `Lyrics` region if it is displayed, to the `Action` region otherwise
+    pub fn select_lyrics_or_action_region(&mut self) {
+        if self.lyrics_enabled {
+            self.region = Region::Lyrics;
+        }else {
+            self.region = Region::Action;
+        }
+    }
+
+    /// Moves slection to the `Lyrics` region if it is displayed, to the `List` region otherwise
+    pub fn select_lyrics_or_list_region(&mut self) {
+        if self.lyrics_enabled {
+            self.region = Region::Lyrics;
+        }else {
+            self.region = Region::List;
+        }
+    }
+
+    /// Moves slection to the `Playlists` region
+    pub fn select_playlists_region(&mut self) {
+        self.region = Region::Playlists;
     }
 
     pub fn previous_action(&mut self) {
@@ -550,14 +750,14 @@ impl<'a> UI<'a>{
         }
     }
 
-    pub async fn pause(&mut self) {
-        self.state.async_pause().await;
+    pub fn pause(&mut self) {
+        self.state.pause();
         self.just_preformed_action = Action::Pause;
     }
 
     /// resumes the currently playing song
-    pub async fn resume(&mut self) {
-        self.state.async_resume().await;
+    pub fn resume(&mut self) {
+        self.state.resume();
         self.just_preformed_action = Action::Resume;
     }
 
@@ -578,26 +778,51 @@ impl<'a> UI<'a>{
                 self.stop();
             },
             PowerActions::Sort => {
+                self.column_sort = None;
                 match self.order {
                     Sorting::ByTitleAscending => {
                         self.order = Sorting::ByTitleDescending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
                     Sorting::ByTitleDescending => {
                         self.order = Sorting::ByDurationAscending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
                     Sorting::ByDurationAscending => {
                         self.order = Sorting::ByDurationDescending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
                     Sorting::ByDurationDescending => {
+                        self.order = Sorting::ByAlbum;
+                        self.sort_music_list();
+                    },
+                    Sorting::ByAlbum => {
+                        self.order = Sorting::MostPlayed;
+                        self.sort_music_list();
+                    },
+                    Sorting::MostPlayed => {
+                        self.order = Sorting::RecentlyAdded;
+                        self.sort_music_list();
+                    },
+                    Sorting::RecentlyAdded => {
                         self.order = Sorting::Shuffle;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
                     Sorting::Shuffle => {
+                        self.order = Sorting::AlbumShuffle;
+                        self.sort_music_list();
+                    },
+                    Sorting::AlbumShuffle => {
+                        self.order = Sorting::ArtistShuffle;
+                        self.sort_music_list();
+                    },
+                    Sorting::ArtistShuffle => {
+                        self.order = Sorting::WeightedShuffle;
+                        self.sort_music_list();
+                    },
+                    Sorting::WeightedShuffle => {
                         self.order = Sorting::ByTitleAscending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
                 }
             }
@@ -621,9 +846,11 @@ impl<'a> UI<'a>{
     /// Plays the provided *Music*
     /// This should be used when the index of the music to play in the full music list is 
     /// known ahead of time 
-    pub async fn o1_play_this_music(&mut self, toplay: &Music, playing_index: usize) {
+    pub fn o1_play_this_music(&mut self, toplay: &Music, playing_index: usize) {
         self.music_list.playing_index = playing_index;
         self.just_preformed_action = Action::Play;
+        self.end_listening(Ending::Skipped);
+        self.requested = Some(toplay.path.clone());
         match self.state.status() {
             Status::Playing => {
                 self.state.end();
@@ -642,10 +869,12 @@ impl<'a> UI<'a>{
     }
 
     /// plays the provided *Music*
-    pub async fn play_this_music(&mut self, toplay: &Music) {
+    pub fn play_this_music(&mut self, toplay: &Music) {
         self.music_list.playing_index = 
-            self.music_list.full_que.iter().position(|x| x == toplay).unwrap_or(0);
+            self.music_list.full_que.iter().position(|x| x.path == toplay.path).unwrap_or(0);
         self.just_preformed_action = Action::Play;
+        self.end_listening(Ending::Skipped);
+        self.requested = Some(toplay.path.clone());
         match self.state.status() {
             Status::Playing => {
                 self.state.end();
@@ -664,8 +893,74 @@ impl<'a> UI<'a>{
 
     /// plays the *selected* song in the music list
     pub fn play_selected_music(&mut self) {
-        let toplay = self.music_list.que.get(self.music_list.selected).unwrap().clone();
-        block_on(self.play_this_music(&toplay));
+        if let Some(toplay) = self.music_list.que.get(self.music_list.selected).cloned() {
+            self.play_this_music(&toplay);
+        }
+    }
+
+    /// Appends the *selected* song in the music list to the end of the play queue
+    pub fn enqueue_selected_music(&mut self) {
+        if let Some(music) = self.music_list.que.get(self.music_list.selected) {
+            self.queue.enqueue(music.clone());
+        }
+    }
+
+    /// Inserts the *selected* song in the music list at the front of the play queue
+    pub fn play_selected_music_next(&mut self) {
+        if let Some(music) = self.music_list.que.get(self.music_list.selected) {
+            self.queue.play_next(music.clone());
+        }
+    }
+
+    /// Switches the music list to the album browser
+    pub fn browse_albums(&mut self) {
+        self.albums.refresh(&self.music_list.full_que);
+        self.mode = ListMode::Albums;
+    }
+
+    /// Plays the *selected* album in track order, the rest of the album
+    /// goes in front of the play queue
+    pub fn play_selected_album(&mut self) {
+        let Some(album) = self.albums.selected_album() else {
+            return
+        };
+        let mut musics = album.musics.clone().into_iter();
+        if let Some(first) = musics.next() {
+            for music in musics.rev() {
+                self.queue.play_next(music);
+            }
+            self.play_this_music(&first);
+        }
+    }
+
+    /// Appends the *selected* album, in track order, to the end of the play queue
+    pub fn enqueue_selected_album(&mut self) {
+        if let Some(album) = self.albums.selected_album() {
+            for music in album.musics.iter() {
+                self.queue.enqueue(music.clone());
+            }
+        }
+    }
+
+    /// Inserts the *selected* album, in track order, at the front of the play queue
+    pub fn play_selected_album_next(&mut self) {
+        if let Some(album) = self.albums.selected_album() {
+            for music in album.musics.iter().rev() {
+                self.queue.play_next(music.clone());
+            }
+        }
+    }
+
+    /// Plays the *selected* song in the play queue and removes it from the queue
+    pub fn play_selected_queued_music(&mut self) {
+        if let Some(toplay) = self.queue.remove_selected() {
+            self.play_this_music(&toplay);
+        }
+    }
+
+    /// Moves slection to the `Queue` region
+    pub fn select_queue_region(&mut self) {
+        self.region = Region::Queue;
     }
 
     /// Moves slection to the `List` region
@@ -683,55 +978,364 @@ impl<'a> UI<'a>{
         self.region = Region::Action
     } 
 
-    /// Renders the region of the music list
-    pub fn render_list(&mut self, frame: &mut Frame) {
+    /// Renders the region of the music list in _area_, the search box
+    /// taking its bottom while searching
+    pub fn render_list(&mut self, frame: &mut Frame, area: Rect) {
+        let (size, search_size) = match self.mode {
+            ListMode::Search | ListMode::AfterSearch => {
+                let [size, search_size] = Layout::vertical([
+                    Constraint::Fill(1),
+                    Constraint::Length(3),
+                ]).areas(area);
+                (size, Some(search_size))
+            },
+            ListMode::Select | ListMode::Albums => (area, None),
+        };
+
+        if let ListMode::Albums = self.mode {
+            self.render_albums(frame, size);
+            return
+        }
+
         let mut rows = vec![];
         let playing = self.state.playing_music();
-        for music in self.music_list.que.iter() {
-            let mut title = music.title.to_owned();
-            let artist = music.artist.to_owned();
-            let time = UI::duration_to_string(music.length.as_secs());
-            if &playing == music {
-                title.insert_str(0, self.style.list_style.playing_selector.as_str());
+        let match_style = self.style.list_style.match_style;
+        for (index, music) in self.music_list.que.iter().enumerate() {
+            let mut cells = self.columns.iter().map(|column| {
+                let text = column.field.text(music, index, &self.play_counts);
+                let spans = match (&self.music_list.query, column.field.query_field()) {
+                    (Some(query), Some(field)) => query.spans(music, field),
+                    _ => vec![],
+                };
+                let spans = UI::highlight(&text, &spans, match_style).spans.into_iter()
+                    .map(|span| Span::styled(span.content.into_owned(), span.style))
+                    .collect::<Vec<Span>>();
+                Line::from(spans).alignment(column.alignment)
+            }).collect::<Vec<Line>>();
+            if playing.path == music.path {
+                // the selector goes before the title, or the first column
+                let position = self.columns.iter().position(|c| c.field == ColumnField::Title).unwrap_or(0);
+                if let Some(cell) = cells.get_mut(position) {
+                    cell.spans.insert(0, Span::raw(self.style.list_style.playing_selector.as_str()));
+                }
                 rows.push(
-                    Row::new(vec![title, artist, time])
+                    Row::new(cells)
                     .style(self.style.list_style.playing_region_color)
                 )
             }else {
                 rows.push(
-                    Row::new(vec![title, artist, time])
+                    Row::new(cells)
                 )
             }
-        }
+        }
+
+        let widths = self.columns.iter().map(|c| c.width).collect::<Vec<Constraint>>();
+        // the sorted column is marked with the direction of its order
+        let header = self.columns.iter().enumerate().map(|(i, c)| {
+            let header = match self.column_sort {
+                Some((sorted, descending)) if sorted == i => {
+                    format!("{} {}", c.header, if descending { "▼" } else { "▲" })
+                },
+                _ => c.header.to_owned(),
+            };
+            Line::from(header).alignment(c.alignment)
+        }).collect::<Vec<Line>>();
+
+        let block = match self.region {
+            Region::List => {
+                match self.mode {
+                    ListMode::Search => {
+                        Block::default().title("Musics")
+                            .borders(Borders::ALL)
+                            .fg(self.style.list_style.active_search_region_color)
+                    },
+                    ListMode::AfterSearch => {
+                        Block::default().title("Musics")
+                            .borders(Borders::ALL)
+                            .fg(self.style.list_style.active_after_search_region_color)
+                    },
+                    ListMode::Select | ListMode::Albums => {
+                        Block::default().title("Musics")
+                            .borders(Borders::ALL)
+                            .fg(self.style.list_style.active_region_color)
+                    },
+                }
+            },
+            _ => {
+                Block::default().title("Musics")
+                    .borders(Borders::ALL)
+                    .fg(self.style.list_style.passive_region_color)
+            }
+        };
+        // count and keys typed so far, e.g. `12g`
+        let block = if self.pending.is_empty() {
+            block
+        } else {
+            block.title_top(Line::from(self.pending.to_string()).right_aligned())
+        };
+        let block = match self.server {
+            ServerStatus::Online => block,
+            ServerStatus::Offline { retry_in } => block.title_bottom(
+                Line::from(format!(" server offline, reconnecting in {}s ", retry_in.as_secs_f64().ceil()))
+                    .style(Style::new().fg(self.style.list_style.search_error_color))
+            ),
+        };
+
+        let table = Table::new(rows, widths)
+            .block(block)
+            .highlight_style(
+                Style::new().add_modifier(Modifier::REVERSED)
+                .fg(self.style.list_style.hilight_color))
+            .highlight_symbol(self.style.list_style.selector.as_str())
+            .header(
+                Row::new(header)
+                .style(Style::new().bold().italic()));
+
+
+        if let Some(search_size) = search_size {
+            let block = match &self.search_error {
+                Some(error) => Block::bordered()
+                    .title(format!("Querry: {}", error))
+                    .fg(self.style.list_style.search_error_color),
+                None => Block::bordered().title("Querry"),
+            };
+            frame.render_widget(
+                Paragraph::new(
+                    self.search_bufr.as_str()
+                ).block(block),
+                search_size
+            );
+        }
+        // without the borders and the header
+        self.list_height = size.height.saturating_sub(3) as usize;
+        frame.render_stateful_widget(table, size, &mut self.music_list.state);
+    }
+
+    /// Renders the lyrics region in _area_
+    pub fn render_lyrics(&mut self, frame: &mut Frame, area: Rect) {
+
+        let style = match self.region {
+            Region::Lyrics => {
+                Style::new().fg(self.style.lyrics_style.active_region_color)
+            }
+            _ => {
+                Style::new().fg(self.style.lyrics_style.passive_region_color)
+            }
+        };
+        let block = Block::default().title("Lyrics").borders(Borders::ALL).style(style);
+
+        let lyrics = match &self.lyrics.lyrics {
+            Some(lyrics) => lyrics,
+            None => {
+                Paragraph::new("No lyrics")
+                    .alignment(Alignment::Center)
+                    .block(block)
+                    .render(area, frame.buffer_mut());
+                return
+            }
+        };
+
+        let current = lyrics.current_line(self.state.played_duration());
+        let visible = area.height.saturating_sub(2) as usize;
+        // keeps the currently sung line in the middle of the region
+        if self.lyrics.follow {
+            if let Some(current) = current {
+                self.lyrics.scroll = current.saturating_sub(visible / 2);
+            }
+        }
+
+        let lines = lyrics.lines.iter().enumerate().map(|(index, line)| {
+            if Some(index) == current {
+                Line::from(line.text.as_str()).style(
+                    Style::new()
+                    .fg(self.style.lyrics_style.current_line_color)
+                    .add_modifier(Modifier::BOLD))
+            }else {
+                Line::from(line.text.as_str()).style(
+                    Style::new().fg(self.style.lyrics_style.text_color))
+            }
+        }).collect::<Vec<Line>>();
+
+        Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .scroll((self.lyrics.scroll as u16, 0))
+            .block(block)
+            .render(area, frame.buffer_mut());
+    }
+
+    /// A message as a line, `HH:MM:SS text`
+    fn message_line(&self, message: &Message) -> Line<'static> {
+        let style = &self.style.messages_style;
+        let color = match message.level {
+            Level::Info => style.text_color,
+            Level::Error => style.error_color,
+        };
+        Line::from(vec![
+            Span::styled(message.timestamp(), Style::new().fg(style.time_color)),
+            Span::raw(" "),
+            Span::styled(message.text.clone(), Style::new().fg(color)),
+        ])
+    }
+
+    /// Renders the status bar, with the last message
+    pub fn render_status(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(message) = self.messages.last() else {
+            return
+        };
+        Paragraph::new(self.message_line(message)).render(area, frame.buffer_mut());
+    }
+
+    /// Renders the message history over the other regions
+    pub fn render_messages(&mut self, frame: &mut Frame) {
+        let [_, area, _] = Layout::vertical([
+            Constraint::Percentage(20), Constraint::Percentage(60), Constraint::Percentage(20),
+        ]).areas(frame.area());
+        let [_, area, _] = Layout::horizontal([
+            Constraint::Percentage(10), Constraint::Percentage(80), Constraint::Percentage(10),
+        ]).areas(area);
+        let block = Block::default()
+            .title(format!("Messages ({})", self.messages.len()))
+            .borders(Borders::ALL)
+            .style(Style::new().fg(self.style.messages_style.border_color));
+        let lines = self.messages.newest_first()
+            .map(|message| self.message_line(message))
+            .collect::<Vec<Line>>();
+        Clear.render(area, frame.buffer_mut());
+        if lines.is_empty() {
+            Paragraph::new("No messages")
+                .alignment(Alignment::Center)
+                .block(block)
+                .render(area, frame.buffer_mut());
+            return
+        }
+        Paragraph::new(lines)
+            .scroll((self.messages.scroll as u16, 0))
+            .block(block)
+            .render(area, frame.buffer_mut());
+    }
+
+    /// Renders the play history over the other regions, the newest play first
+    pub fn render_history(&mut self, frame: &mut Frame) {
+        let [_, area, _] = Layout::vertical([
+            Constraint::Percentage(20), Constraint::Percentage(60), Constraint::Percentage(20),
+        ]).areas(frame.area());
+        let [_, area, _] = Layout::horizontal([
+            Constraint::Percentage(10), Constraint::Percentage(80), Constraint::Percentage(10),
+        ]).areas(area);
+        let style = &self.style.messages_style;
+        let block = Block::default()
+            .title(format!("History ({})", self.history.len()))
+            .borders(Borders::ALL)
+            .style(Style::new().fg(style.border_color));
+        Clear.render(area, frame.buffer_mut());
+        if self.history.len() == 0 {
+            Paragraph::new("Nothing played yet")
+                .alignment(Alignment::Center)
+                .block(block)
+                .render(area, frame.buffer_mut());
+            return
+        }
+        let items = self.history.newest_first().map(|play| {
+            let time = play.local_time()
+                .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            let name = match self.library_music(&play.path) {
+                Some(music) => format!("{} - {}", music.title, music.artist),
+                None => play.path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            };
+            let ending = match play.ending {
+                Ending::Completed => "completed",
+                Ending::Skipped => "skipped",
+                Ending::Stopped => "stopped",
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(time, Style::new().fg(style.time_color)),
+                Span::raw(" "),
+                Span::styled(name, Style::new().fg(style.text_color)),
+                Span::styled(
+                    format!(" {} {ending}", UI::duration_to_string(play.played as u64)),
+                    Style::new().fg(style.time_color),
+                ),
+            ]))
+        }).collect::<Vec<ListItem>>();
+        let mut state = ListState::default().with_selected(Some(self.history.selected));
+        StatefulWidget::render(
+            List::new(items)
+                .block(block)
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            area, frame.buffer_mut(), &mut state,
+        );
+    }
+
+    /// Renders the region of the playlist browser
+    pub fn render_playlists(&mut self, frame: &mut Frame, area: Rect) {
+        let mut names = vec![String::from("Library")];
+        names.extend(self.playlists.paths.iter().map(|p| Playlist::name(p)));
+        let items = names.into_iter().enumerate().map(|(index, mut name)| {
+            if index == self.playlists.loaded {
+                name.insert_str(0, self.style.playlists_style.loaded_selector.as_str());
+            }
+            ListItem::new(name)
+        });
+
+        let block = match self.region {
+            Region::Playlists => {
+                Block::default().title("Playlists")
+                    .borders(Borders::ALL)
+                    .fg(self.style.playlists_style.active_region_color)
+            },
+            _ => {
+                Block::default().title("Playlists")
+                    .borders(Borders::ALL)
+                    .fg(self.style.playlists_style.passive_region_color)
+            }
+        };
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(
+                Style::new().add_modifier(Modifier::REVERSED)
+                .fg(self.style.playlists_style.hilight_color))
+            .highlight_symbol(self.style.playlists_style.selector.as_str());
+
+        self.playlists.state.select(Some(self.playlists.selected));
+        frame.render_stateful_widget(list, area, &mut self.playlists.state);
+    }
+
+    /// Renders the album browser in place of the music list
+    pub fn render_albums(&mut self, frame: &mut Frame, area: Rect) {
+        let playing = self.state.playing_music().path;
+        let rows = self.albums.albums.iter().map(|album| {
+            let row = Row::new(vec![
+                album.title.to_owned(),
+                album.artist.to_owned(),
+                album.year.map(|y| y.to_string()).unwrap_or_default(),
+                album.musics.len().to_string(),
+                UI::duration_to_string(album.length().as_secs()),
+            ]);
+            if album.musics.iter().any(|m| m.path == playing) {
+                row.style(self.style.list_style.playing_region_color)
+            } else {
+                row
+            }
+        });
 
         let widths = [
             Constraint::Fill(4),
             Constraint::Fill(2),
-            Constraint::Fill(1),
+            Constraint::Length(4),
+            Constraint::Length(6),
+            Constraint::Length(8),
         ];
 
         let block = match self.region {
             Region::List => {
-                match self.mode {
-                    ListMode::Search => {
-                        Block::default().title("Musics")
-                            .borders(Borders::ALL)
-                            .fg(self.style.list_style.active_search_region_color)
-                    },
-                    ListMode::AfterSearch => {
-                        Block::default().title("Musics")
-                            .borders(Borders::ALL)
-                            .fg(self.style.list_style.active_after_search_region_color)
-                    },
-                    ListMode::Select => {
-                        Block::default().title("Musics")
-                            .borders(Borders::ALL)
-                            .fg(self.style.list_style.active_region_color)
-                    },
-                }
+                Block::default().title(format!("Albums ({})", self.albums.len()))
+                    .borders(Borders::ALL)
+                    .fg(self.style.list_style.active_region_color)
             },
             _ => {
-                Block::default().title("Musics")
+                Block::default().title(format!("Albums ({})", self.albums.len()))
                     .borders(Borders::ALL)
                     .fg(self.style.list_style.passive_region_color)
             }
@@ -744,32 +1348,53 @@ impl<'a> UI<'a>{
                 .fg(self.style.list_style.hilight_color))
             .highlight_symbol(self.style.list_style.selector.as_str())
             .header(
-                Row::new(vec!["Title", "artist", "duration"])
+                Row::new(vec!["Album", "Album artist", "Year", "Tracks", "Duration"])
                 .style(Style::new().bold().italic()));
 
-        let mut size = frame.area();
-        let mut search_size = frame.area();
-        size.height = size.height  - 3 - 4;
+        self.albums.state.select(Some(self.albums.selected));
+        frame.render_stateful_widget(table, area, &mut self.albums.state);
+    }
+
+    /// Renders the region of the play queue
+    pub fn render_queue(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = self.queue.musics.iter().map(|music| {
+            Row::new(vec![
+                music.title.to_owned(),
+                UI::duration_to_string(music.length.as_secs()),
+            ])
+        });
 
-        match self.mode {
-            ListMode::Search | ListMode::AfterSearch => {
-                search_size.y = size.height - 3;
-                search_size.height = 3;
-                size.height = size.height - 3;
-                frame.render_widget(
-                    Paragraph::new(
-                        self.search_bufr.as_str()
-                    ).block(Block::bordered().title("Querry")),
-                    search_size
-                );
+        let widths = [
+            Constraint::Fill(3),
+            Constraint::Length(5),
+        ];
+
+        let block = match self.region {
+            Region::Queue => {
+                Block::default().title(format!("Queue ({})", self.queue.len()))
+                    .borders(Borders::ALL)
+                    .fg(self.style.queue_style.active_region_color)
             },
-            ListMode::Select => {},
-        }
-        frame.render_stateful_widget(table, size, &mut self.music_list.state);
+            _ => {
+                Block::default().title(format!("Queue ({})", self.queue.len()))
+                    .borders(Borders::ALL)
+                    .fg(self.style.queue_style.passive_region_color)
+            }
+        };
+
+        let table = Table::new(rows, widths)
+            .block(block)
+            .highlight_style(
+                Style::new().add_modifier(Modifier::REVERSED)
+                .fg(self.style.queue_style.hilight_color))
+            .highlight_symbol(self.style.queue_style.selector.as_str());
+
+        self.queue.state.select(Some(self.queue.selected));
+        frame.render_stateful_widget(table, area, &mut self.queue.state);
     }
 
     fn get_action_index(&self) -> usize {
-        return match self.action {
+        match self.action {
             PowerActions::BackwardSkip => 0,
             PowerActions::TogglePlay => 1,
             PowerActions::ForwardSkip => 2,
@@ -779,6 +1404,32 @@ impl<'a> UI<'a>{
         }
     }
 
+    /// Splits _text_ into spans, the chars in _ranges_ being styled with _style_
+    pub fn highlight<'b>(text: &'b str, ranges: &[Range<usize>], style: Style) -> Line<'b> {
+        if ranges.is_empty() {
+            return Line::from(text)
+        }
+        let mut spans = vec![];
+        // byte offset of every char, plus the end of the text
+        let offsets = text.char_indices().map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect::<Vec<usize>>();
+        let byte = |char_index: usize| offsets[char_index.min(offsets.len() - 1)];
+        let mut last = 0;
+        for range in ranges {
+            let (start, end) = (byte(range.start), byte(range.end));
+            if start > last {
+                spans.push(Span::raw(&text[last..start]));
+            }
+            spans.push(Span::styled(&text[start..end], style));
+            last = end;
+        }
+        if last < text.len() {
+            spans.push(Span::raw(&text[last..]));
+        }
+        Line::from(spans)
+    }
+
     /// Converst timer in the u64 form to a string of form xx:yy
     pub fn duration_to_string(time: u64) -> String {
         let seconds = time % 60;
@@ -796,7 +1447,7 @@ impl<'a> UI<'a>{
             format!("0{}", minities)
         };
 
-        return format!("{}:{}", sminutes, sseconds)
+        format!("{}:{}", sminutes, sseconds)
     }
 
     /// Returns the current playing timer as a string "xx:yy"
@@ -812,10 +1463,10 @@ impl<'a> UI<'a>{
         let current = self.state.played_duration().as_secs();
         let max = self.state.playing_music().length.as_secs();
         if max != 0 {
-            return current as f64 / max as f64
+            current as f64 / max as f64
         }else {
-            return 0.0
-        };
+            0.0
+        }
     }
 
     /// Seeks playing time forward by 5 seconds
@@ -844,12 +1495,8 @@ impl<'a> UI<'a>{
         }
     }
 
-    /// Renders the displayed time seeker
-    pub fn render_seeker(&mut self, frame: &mut Frame) {
-        let mut area = frame.area();
-        area.y = area.height - 3;
-        area.height = 3;
-        area.width = (area.width / 5)*4 as u16;
+    /// Renders the displayed time seeker in _area_
+    pub fn render_seeker(&mut self, frame: &mut Frame, area: Rect) {
 
         let selected_music = self.state.playing_music();
         let style = match self.region {
@@ -876,14 +1523,8 @@ impl<'a> UI<'a>{
             .render(area, frame.buffer_mut());
     }
 
-    /// Renders the displayed volume slider
-    pub fn render_volume(&mut self, frame: &mut Frame) {
-        let mut area = frame.area();
-        area.y = area.height - 3; // v
-        area.x = (area.width / 5) * 4 + 1 as u16; // >
-
-        area.width = area.width / 5 + 2 as u16;
-        area.height = 3;
+    /// Renders the displayed volume slider in _area_
+    pub fn render_volume(&mut self, frame: &mut Frame, area: Rect) {
 
         let style = match self.region {
             Region::Volume=> {
@@ -908,12 +1549,8 @@ impl<'a> UI<'a>{
             .render(area, frame.buffer_mut());
     }
 
-    /// Renders the displayed actions
-    pub fn render_actions(&mut self, frame: &mut Frame) {
-        let mut area = frame.area();
-        // - seeker hight - action height
-        area.y = area.height - 3 - 4;
-        area.height = 3;
+    /// Renders the displayed actions in _area_
+    pub fn render_actions(&mut self, frame: &mut Frame, area: Rect) {
 
         let style = match self.region {
             Region::Action => {
@@ -950,16 +1587,31 @@ impl<'a> UI<'a>{
             Sorting::ByDurationDescending => {
                 actions.push("DurationDescending")
             },
+            Sorting::ByAlbum => {
+                actions.push("Album")
+            },
+            Sorting::MostPlayed => {
+                actions.push("MostPlayed")
+            },
+            Sorting::RecentlyAdded => {
+                actions.push("RecentlyAdded")
+            },
             Sorting::Shuffle => {
                 actions.push("Shuffle")
             },
+            Sorting::AlbumShuffle => {
+                actions.push("AlbumShuffle")
+            },
+            Sorting::ArtistShuffle => {
+                actions.push("ArtistShuffle")
+            },
+            Sorting::WeightedShuffle => {
+                actions.push("WeightedShuffle")
+            },
         }
         let status = self.state.status();
-        match status {
-            Status::Playing => {
-                actions[1] = "⏸";
-            },
-            _ => {}
+        if status == Status::Playing {
+            actions[1] = "⏸";
         }
 
         Tabs::new(actions)
@@ -977,18 +1629,188 @@ impl<'a> UI<'a>{
 
     /// Updates the music playing state
     pub fn update_state(&mut self) {
-        block_on(self.state.async_batch_calls());
+        if self.state.sync() && self.resync {
+            self.resync = false;
+            self.restore_state();
+        }
+        for outcome in self.state.outcomes() {
+            let failed = !matches!(&outcome.result, Ok(status) if status.is_ok());
+            if let (ServerCommand::Play(path), true) = (&outcome.command, failed) {
+                // the previous music is still the one playing
+                if self.requested.as_ref() == Some(path) {
+                    self.requested = None;
+                }
+            }
+            self.messages.push(Message::outcome(&outcome));
+        }
+        self.handle_library_events();
+        if self.lyrics_enabled {
+            self.lyrics.load(&self.state.playing_music().path);
+        }
         self.handle_music_selection();
-        self.handle_repeat();
+        // the last known state of an offline server does not move on
+        if self.server == ServerStatus::Online {
+            self.track_listening();
+            self.handle_repeat();
+        }
+        self.publish_mpris();
     }
 
     /// Renders the displayed UI
     pub fn render(&mut self, frame: &mut Frame) {
         self.update_state();
-        self.render_seeker(frame);
-        self.render_list(frame);
-        self.render_actions(frame);
-        self.render_volume(frame);
+        let areas = self.layout.areas(frame.area(), |pane| match pane {
+            Pane::Queue => !self.queue.is_empty() || matches!(self.region, Region::Queue),
+            Pane::Playlists => !self.playlists.dirs.is_empty() || matches!(self.region, Region::Playlists),
+            Pane::Lyrics => self.lyrics_enabled,
+            Pane::List | Pane::Actions | Pane::Seeker | Pane::Volume | Pane::Status => true,
+        });
+        if areas.is_empty() {
+            Paragraph::new("Terminal too small")
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true })
+                .render(frame.area(), frame.buffer_mut());
+            return
+        }
+        for (pane, area) in areas {
+            match pane {
+                Pane::List => self.render_list(frame, area),
+                Pane::Queue => self.render_queue(frame, area),
+                Pane::Playlists => self.render_playlists(frame, area),
+                Pane::Lyrics => self.render_lyrics(frame, area),
+                Pane::Actions => self.render_actions(frame, area),
+                Pane::Seeker => self.render_seeker(frame, area),
+                Pane::Volume => self.render_volume(frame, area),
+                Pane::Status => self.render_status(frame, area),
+            }
+        }
+        if self.messages.open {
+            self.render_messages(frame);
+        }
+        if self.history.open {
+            self.render_history(frame);
+        }
+    }
+
+    /// Follows the playing music, adding it to the history once another
+    /// one plays or the player stops
+    fn track_listening(&mut self) {
+        let playing = self.state.playing_music().path;
+        if let Some(requested) = &self.requested {
+            if *requested != playing {
+                return
+            }
+            self.requested = None;
+        }
+        let status = self.state.status();
+        let played = self.state.played_duration();
+        if let Some(listening) = &mut self.listening {
+            if listening.path == playing && status != Status::Stopping {
+                listening.played = listening.played.max(played);
+                return
+            }
+            let ending = if status == Status::Stopping { Ending::Stopped } else { Ending::Skipped };
+            self.end_listening(ending);
+        }
+        if status != Status::Stopping && !playing.as_os_str().is_empty() {
+            self.listening = Some(Listening::new(playing, self.state.playing_music_duration(), played));
+        }
+    }
+
+    /// Adds the playing music to the history, _ending_ being how it ended
+    /// unless it played to its end
+    fn end_listening(&mut self, ending: Ending) {
+        let Some(mut listening) = self.listening.take() else {
+            return
+        };
+        if listening.path == self.state.playing_music().path {
+            listening.played = listening.played.max(self.state.played_duration());
+        }
+        let play = listening.end(ending);
+        let music = self.library_music(&play.path).cloned()
+            .or_else(|| Some(self.state.playing_music()).filter(|m| m.path == play.path));
+        if let Some(scrobble) = music.and_then(|music| Scrobble::new(&play, &music)) {
+            if let Err(error) = self.scrobbles.push(&scrobble) {
+                utils::report(&format!("could not queue the scrobble: {error}"));
+            }
+        }
+        if play.counts() {
+            *self.play_counts.entry(play.path.clone()).or_default() += 1;
+        }
+        if let Err(error) = self.history.record(play) {
+            utils::report(&format!("could not write the history: {error}"));
+        }
+    }
+
+    /// Adds the playing music to the history as stopped and saves the
+    /// session, before quitting
+    pub fn close(&mut self) {
+        self.end_listening(Ending::Stopped);
+        self.save_session();
+    }
+
+    /// Sets where the session is saved
+    pub fn session_path(&mut self, path: PathBuf) {
+        self.session_path = Some(path);
+    }
+
+    /// Snapshot of the UI, see [Session]
+    pub fn session(&self) -> Session {
+        let searching = matches!(self.mode, ListMode::Search | ListMode::AfterSearch);
+        Session {
+            order: self.order,
+            repeat: self.repeat.clone(),
+            region: self.region.clone(),
+            selected: self.music_list.que.get(self.music_list.selected).map(|m| m.path.clone()),
+            offset: self.music_list.state.offset(),
+            query: (searching && !self.search_bufr.is_empty()).then(|| self.search_bufr.clone()),
+            queue: self.queue.musics.iter().map(|m| m.path.clone()).collect(),
+            queue_selected: self.queue.selected,
+        }
+    }
+
+    /// Saves the session if it changed since it was last saved
+    pub fn save_session(&mut self) {
+        let Some(path) = &self.session_path else {
+            return
+        };
+        let session = self.session();
+        if self.saved_session.as_ref().is_some_and(|(saved, _)| *saved == session) {
+            self.saved_session = Some((session, Instant::now()));
+            return
+        }
+        if let Err(error) = session.save(path) {
+            utils::report(&format!("could not save the session: {error}"));
+        }
+        self.saved_session = Some((session, Instant::now()));
+    }
+
+    /// Puts the UI back the way _session_ left it, musics that are gone
+    /// being left out of the queue
+    pub fn restore_session(&mut self, session: Session) {
+        self.order = session.order;
+        self.repeat = session.repeat;
+        self.region = match session.region {
+            Region::Lyrics if !self.lyrics_enabled => Region::List,
+            region => region,
+        };
+        self.sort_music_list();
+        self.queue.musics = session.queue.into_iter()
+            .filter_map(|path| match self.library_music(&path) {
+                Some(music) => Some(music.clone()),
+                None => Music::simple_new(path),
+            })
+            .collect();
+        self.queue.selected = session.queue_selected.min(self.queue.len().saturating_sub(1));
+        if let Some(query) = session.query {
+            self.search_bufr = query;
+            self.search_error = self.music_list.search(self.search_bufr.to_owned(), self.search_limit).err();
+            self.mode = ListMode::AfterSearch;
+        }
+        self.music_list.selected = session.selected
+            .and_then(|path| self.music_list.que.iter().position(|m| m.path == path))
+            .unwrap_or(0);
+        *self.music_list.state.offset_mut() = session.offset.min(self.music_list.selected);
     }
 
     /// Handles repeating music
@@ -1007,7 +1829,15 @@ impl<'a> UI<'a>{
                         self.play_next();
                     }
                 },
-                Repeat::Dont => {},
+                Repeat::Dont => {
+                    // the queue still plays even if the list does not repeat
+                    if !self.queue.is_empty() &&
+                        self.state.played_duration().checked_add(Duration::from_millis(200)).unwrap().as_secs() >=
+                        self.state.playing_music_duration().as_secs()
+                    {
+                        self.play_next();
+                    }
+                },
             }
         }
 
@@ -1023,10 +1853,13 @@ impl<'a> UI<'a>{
     pub fn list_up(&mut self) {
         let quesize = self.music_list.que.len();
         let selected_index = self.music_list.selected;
+        if quesize == 0 {
+            return
+        }
         if selected_index == 0 {
             self.music_list.selected = quesize - 1;
         }else {
-            self.music_list.selected = self.music_list.selected - 1;
+            self.music_list.selected -= 1;
         }
     }
 
@@ -1035,28 +1868,29 @@ impl<'a> UI<'a>{
         let quesize = self.music_list.que.len();
         let selected_index = self.music_list.selected;
 
-        if selected_index == quesize - 1 {
+        if selected_index + 1 >= quesize {
             self.music_list.selected = 0;
         }else {
-            self.music_list.selected = self.music_list.selected + 1;
+            self.music_list.selected += 1;
         }
     }
 
     /// Appends the char to the existing search querry and search it
-    pub async fn register_querry(&mut self, c: char) {
+    pub fn register_querry(&mut self, c: char) {
         self.search_bufr.push(c);
-        self.music_list.search(self.search_bufr.to_owned());
+        self.search_error = self.music_list.search(self.search_bufr.to_owned(), self.search_limit).err();
     }
 
     /// Resets the search querry to an empty string
     pub fn reset_querry(&mut self) {
+        self.search_error = None;
         self.music_list.reset_search();
     }
 
     /// Delets a character in the search querry and search the result
     pub fn delete_char_querry(&mut self) {
         self.search_bufr.pop();
-        self.music_list.search(self.search_bufr.to_owned());
+        self.search_error = self.music_list.search(self.search_bufr.to_owned(), self.search_limit).err();
     }
 
     /// Selects the first element in the music list
@@ -1071,11 +1905,80 @@ impl<'a> UI<'a>{
 
     /// Selects the last element in the music list
     pub fn goto_bottom(&mut self) {
-        self.music_list.selected = self.music_list.que.len() - 1;
+        self.music_list.selected = self.music_list.que.len().saturating_sub(1);
+    }
+
+    /// Selects the element at _index_ in the music list, or the last one
+    pub fn goto_index(&mut self, index: usize) {
+        self.music_list.selected = index.min(self.music_list.que.len().saturating_sub(1));
+    }
+
+    /// Range of the music list shown on the screen
+    fn visible_range(&self) -> Range<usize> {
+        let offset = self.music_list.state.offset();
+        let end = (offset + self.list_height).min(self.music_list.que.len());
+        offset..end.max(offset)
+    }
+
+    /// Selects the element _lines_ below the first visible one
+    pub fn goto_screen_top(&mut self, lines: usize) {
+        let visible = self.visible_range();
+        self.music_list.selected = (visible.start + lines).min(visible.end.saturating_sub(1));
+    }
+
+    /// Selects the element in the middle of the visible ones
+    pub fn goto_screen_middle(&mut self) {
+        let visible = self.visible_range();
+        self.music_list.selected = visible.start + visible.len().saturating_sub(1) / 2;
+    }
+
+    /// Selects the element _lines_ above the last visible one
+    pub fn goto_screen_bottom(&mut self, lines: usize) {
+        let visible = self.visible_range();
+        self.music_list.selected = visible.end.saturating_sub(1 + lines).max(visible.start);
+    }
+
+    /// Scrolls the music list to have the selection in the middle
+    pub fn center_list(&mut self) {
+        *self.music_list.state.offset_mut() = self.music_list.selected.saturating_sub(self.list_height / 2);
+    }
+
+    /// Sorts the music list by the column at _index_, reversing the order
+    /// if it is sorted by it already
+    pub fn sort_by_column(&mut self, index: usize) {
+        // the index follows the order of the list
+        let Some(field) = self.columns.get(index).map(|c| c.field).filter(|f| *f != ColumnField::Index) else {
+            return
+        };
+        let descending = matches!(self.column_sort, Some((i, false)) if i == index);
+        let plays = &self.play_counts;
+        self.music_list.sort_by(|a, b| {
+            let order = field.order(a, b, plays);
+            if descending { order.reverse() } else { order }
+        });
+        self.column_sort = Some((index, descending));
+        self.restore_state();
+    }
+
+    /// Marks the selected music with _c_
+    pub fn set_mark(&mut self, c: char) {
+        if let Some(music) = self.music_list.que.get(self.music_list.selected) {
+            self.marks.insert(c, music.path.to_owned());
+        }
+    }
+
+    /// Selects the music marked with _c_ if it is in the music list
+    pub fn goto_mark(&mut self, c: char) {
+        let Some(path) = self.marks.get(&c) else {
+            return
+        };
+        if let Some(index) = self.music_list.que.iter().position(|m| &m.path == path) {
+            self.music_list.selected = index;
+        }
     }
 
     /// Increases volume by 5
-    pub fn increase_volume(&self) {
+    pub fn increase_volume(&mut self) {
         let volume = self.state.volume() * 100.0;
         if volume < 101.0 {
             self.state.change_volume(volume + 1.0);
@@ -1083,7 +1986,7 @@ impl<'a> UI<'a>{
     }
 
     /// Decreases volume by 5
-    pub fn decrease_volume(&self) {
+    pub fn decrease_volume(&mut self) {
         let volume = self.state.volume() * 100.0;
         if  volume > 0.0 {
             self.state.change_volume(volume - 1.0);
@@ -1095,10 +1998,20 @@ impl<'a> UI<'a>{
         self.region = Region::Volume
     }
 
-    /// Plays the previous music in the list
+    /// Plays the previous music in the list, going back from the first
+    /// music of a list shuffled again leads to the last music played before
     fn play_preivous(&mut self) {
-        let previous = self.music_list.previous_song().to_owned();
-        block_on(self.play_this_music(&previous));
+        if self.order.is_shuffle() && self.music_list.playing_index == 0 {
+            let wrapped_from = self.shuffler.wrapped_from.take()
+                .and_then(|path| self.library_music(&path).cloned());
+            if let Some(previous) = wrapped_from {
+                self.play_this_music(&previous);
+                return
+            }
+        }
+        if let Some(previous) = self.music_list.previous_song().cloned() {
+            self.play_this_music(&previous);
+        }
     }
 
     #[allow(dead_code)]
@@ -1106,14 +2019,15 @@ impl<'a> UI<'a>{
     /// This should be used when the playing index of the full music list 
     /// is known ahead of time
     fn o1_play_preivous(&mut self, playing_index: usize) {
-        let previous = self.music_list.previous_song().to_owned();
-        block_on(self.o1_play_this_music(&previous, playing_index));
+        if let Some(previous) = self.music_list.previous_song().cloned() {
+            self.o1_play_this_music(&previous, playing_index);
+        }
     }
 
     /// Plays the next song in the music list
     fn restart_playing_music(&mut self) {
         let playing = self.state.playing_music();
-        block_on(self.play_this_music(&playing));
+        self.play_this_music(&playing);
     }
 
     #[allow(dead_code)]
@@ -1121,14 +2035,60 @@ impl<'a> UI<'a>{
     /// This should be used when the playing index of the full music list 
     /// is known ahead of time
     fn o1_play_next(&mut self, playing_index: usize) {
-        let next = self.music_list.next_song().to_owned();
-        block_on(self.o1_play_this_music(&next, playing_index));
+        if let Some(next) = self.music_list.next_song().cloned() {
+            self.o1_play_this_music(&next, playing_index);
+        }
     }
 
-    /// Plays the next song in the music list
+    /// Plays the music of the play selected in the history, if it is
+    /// still in the library
+    fn play_from_history(&mut self) {
+        let Some(path) = self.history.selected_play().map(|play| play.path.clone()) else {
+            return
+        };
+        match self.library_music(&path).cloned() {
+            Some(music) => self.play_this_music(&music),
+            None => self.messages.push(Message::error(format!("{} is not in the library", path.display()))),
+        }
+    }
+
+    /// Plays the next song in the play queue, or in the music list
+    /// if the queue is empty
     fn play_next(&mut self) {
-        let next = self.music_list.next_song().to_owned();
-        block_on(self.play_this_music(&next));
+        let next = match self.queue.pop() {
+            Some(queued) => queued,
+            None => match self.next_in_list() {
+                Some(next) => next,
+                None => return,
+            },
+        };
+        self.play_this_music(&next);
+    }
+
+    /// The music after the playing one in the music list, a shuffled list
+    /// being shuffled again rather than started over once it all played
+    fn next_in_list(&mut self) -> Option<Music> {
+        let wraps = self.music_list.playing_index + 1 >= self.music_list.que.len();
+        if self.order.is_shuffle() && wraps && self.music_list.query.is_none() {
+            let playing = self.state.playing_music().path;
+            let play_counts = &self.play_counts;
+            self.shuffler.reshuffle(self.order, &mut self.music_list.full_que, &playing, |music| {
+                shuffle::weight(music, play_counts.get(&music.path).copied().unwrap_or(0))
+            });
+            self.music_list.que = self.music_list.full_que.clone();
+            return self.music_list.que.first().cloned()
+        }
+        self.music_list.next_song().cloned()
+    }
+
+    /// Shuffles the music list again, the playing music first, in the
+    /// `Shuffle` order if it is not shuffled already
+    pub fn reshuffle(&mut self) {
+        self.column_sort = None;
+        if !self.order.is_shuffle() {
+            self.order = Sorting::Shuffle;
+        }
+        self.sort_music_list();
     }
 
     /// Toggls the playing music
@@ -1142,17 +2102,17 @@ impl<'a> UI<'a>{
         match self.state.status() {
             // if we are playing we pause
             Status::Playing => {
-                block_on(self.pause());
+                self.pause();
             },
             // if we are pausing we resume
             Status::Pausing => {
-                block_on(self.resume());
+                self.resume();
             }
             // if we stopped we play
             Status::Stopping => {
                 match self.state.playing_music().is_valid() {
                     Some(_) => {
-                        block_on(self.play_this_music(&self.state.playing_music()));
+                        self.play_this_music(&self.state.playing_music());
                     }
                     None => {
                         self.play_selected_music();
@@ -1169,32 +2129,58 @@ impl<'a> UI<'a>{
 
     /// Cycles through actions in this orders
     /// - sort action:
-    ///     ByTitleAscending -> ByTitleDescending -> ByDurationAscending -> ByDurationDescending -> Shuffle -> ByTitleAscending
+    ///   ByTitleAscending -> ByTitleDescending -> ByDurationAscending -> ByDurationDescending -> ByAlbum -> MostPlayed
+    ///   -> RecentlyAdded -> Shuffle -> AlbumShuffle -> ArtistShuffle -> WeightedShuffle -> ByTitleAscending
     /// - repeat action:
-    ///     ThisMusic -> AllMusics -> Dont -> ThisMusic
+    ///   ThisMusic -> AllMusics -> Dont -> ThisMusic
     pub fn cycle_back(&mut self) {
         match self.action {
             PowerActions::Sort => {
+                self.column_sort = None;
                 match self.order {
                     Sorting::ByTitleAscending => {
-                        self.order = Sorting::Shuffle;
-                        self.music_list.sort(Some(self.order));
+                        self.order = Sorting::WeightedShuffle;
+                        self.sort_music_list();
                     },
                     Sorting::ByTitleDescending => {
                         self.order = Sorting::ByTitleAscending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
                     Sorting::ByDurationAscending => {
                         self.order = Sorting::ByTitleDescending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
                     Sorting::ByDurationDescending => {
                         self.order = Sorting::ByDurationAscending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
                     },
-                    Sorting::Shuffle => {
+                    Sorting::ByAlbum => {
                         self.order = Sorting::ByDurationDescending;
-                        self.music_list.sort(Some(self.order));
+                        self.sort_music_list();
+                    },
+                    Sorting::MostPlayed => {
+                        self.order = Sorting::ByAlbum;
+                        self.sort_music_list();
+                    },
+                    Sorting::RecentlyAdded => {
+                        self.order = Sorting::MostPlayed;
+                        self.sort_music_list();
+                    },
+                    Sorting::Shuffle => {
+                        self.order = Sorting::RecentlyAdded;
+                        self.sort_music_list();
+                    },
+                    Sorting::AlbumShuffle => {
+                        self.order = Sorting::Shuffle;
+                        self.sort_music_list();
+                    },
+                    Sorting::ArtistShuffle => {
+                        self.order = Sorting::AlbumShuffle;
+                        self.sort_music_list();
+                    },
+                    Sorting::WeightedShuffle => {
+                        self.order = Sorting::ArtistShuffle;
+                        self.sort_music_list();
                     },
                 }
             }
@@ -1221,9 +2207,9 @@ impl<'a> UI<'a>{
         let quesize = self.music_list.que.len();
         let selected_index = self.music_list.selected;
         if selected_index == 0 {
-            self.music_list.selected = quesize - 1;
+            self.music_list.selected = quesize.saturating_sub(1);
         }else {
-            self.music_list.selected = self.music_list.selected - 7;
+            self.music_list.selected = selected_index.saturating_sub(7);
         }
     }
 
@@ -1232,26 +2218,25 @@ impl<'a> UI<'a>{
         let quesize = self.music_list.que.len();
         let selected_index = self.music_list.selected;
 
-        if selected_index == quesize - 1 {
+        if selected_index + 1 >= quesize {
             self.music_list.selected = 0;
         }else {
-            self.music_list.selected = self.music_list.selected + 7;
+            self.music_list.selected = (selected_index + 7).min(quesize - 1);
         }
     }
 
-    pub fn toggle_mute(&self) {
+    pub fn toggle_mute(&mut self) {
         self.state.toggle_mute();
     }
 
-    fn stop(&self) {
+    fn stop(&mut self) {
+        self.end_listening(Ending::Stopped);
+        self.requested = None;
         self.state.end();
     }
 
     fn change_list_mode(&mut self, mode: ListMode) {
-        match mode {
-            ListMode::Select => self.reset_querry(),
-            _ => {}
-        }
+        if let ListMode::Select = mode { self.reset_querry() }
         self.mode = mode;
     }
 
@@ -1261,84 +2246,325 @@ impl<'a> UI<'a>{
         self.mode = ListMode::Select;
     }
 
-    fn quit(&self) -> Result<bool, ()>{
-        Ok(true)
+    /// Where the keys are looked up in, given the region and list mode
+    fn context(&self) -> Context {
+        if self.messages.open {
+            return Context::Messages
+        }
+        if self.history.open {
+            return Context::History
+        }
+        match self.region {
+            Region::List => match self.mode {
+                ListMode::Select => Context::List,
+                ListMode::Search => Context::Search,
+                ListMode::AfterSearch => Context::AfterSearch,
+                ListMode::Albums => Context::Albums,
+            },
+            Region::Action => Context::Actions,
+            Region::Seeker => Context::Seeker,
+            Region::Volume => Context::Volume,
+            Region::Queue => Context::Queue,
+            Region::Playlists => Context::Playlists,
+            Region::Lyrics => Context::Lyrics,
+        }
+    }
+
+    /// Handles a key press, returns true if the client should quit
+    pub fn handle_key(&mut self, key: &KeyEvent) -> bool {
+        if key.kind != event::KeyEventKind::Press {
+            return false
+        }
+        let context = self.context();
+        match self.pending.feed(&self.keymap, context, KeyChord::from(key)) {
+            Input::Run { command, count, char } => self.run_command(command, count, char),
+            Input::Pending => false,
+            Input::Unbound(keys) => {
+                if let Context::Search = context {
+                    // an unfinished sequence was typed in the search
+                    for c in keys.iter().filter_map(KeyChord::char) {
+                        self.register_querry(c);
+                    }
+                    false
+                } else if keys.len() > 1 {
+                    // the last key may start something else
+                    self.handle_key(key)
+                } else {
+                    false
+                }
+            },
+        }
     }
 
-    fn anticipate(&mut self, arg: char) {
-        self.anticipation_mode = AncitipationMode::Char(arg); 
+    /// Runs _command_ _count_ times if it [Command::repeats], _char_ being
+    /// the character typed after it, returns true if the client should quit
+    pub fn run_command(&mut self, command: Command, count: Option<usize>, char: Option<char>) -> bool {
+        match command {
+            Command::Repeat => {
+                // a count given to `.` replaces the one of the last command
+                return match self.last_command {
+                    Some((context, last, last_count, char)) if context == self.context() => {
+                        self.run_command(last, count.or(last_count), char)
+                    },
+                    _ => false,
+                }
+            },
+            Command::Quit => {},
+            _ => self.last_command = Some((self.context(), command, count, char)),
+        }
+        let times = if command.repeats() { count.unwrap_or(1) } else { 1 };
+        for _ in 0..times {
+            if self.run(command, count, char) {
+                return true
+            }
+        }
+        false
+    }
+
+    /// Runs _command_ once, _count_ is used by the commands taking it as a
+    /// position, e.g. `12G`
+    fn run(&mut self, command: Command, count: Option<usize>, char: Option<char>) -> bool {
+        let albums = matches!(self.mode, ListMode::Albums);
+        match command {
+            Command::Quit => return true,
+            Command::Repeat => {},
+            Command::Messages | Command::MessagesClose => self.messages.toggle(),
+            Command::History | Command::HistoryClose => self.history.toggle(),
+            Command::TogglePause => self.toggle_play(),
+            Command::ToggleMute => self.toggle_mute(),
+            Command::PlayNext => self.play_next(),
+            Command::PlayPrevious => self.play_preivous(),
+            Command::RegionList => self.select_list_region(),
+            Command::RegionQueue => self.select_queue_region(),
+            Command::RegionPlaylists => self.select_playlists_region(),
+            Command::RegionSeeker => self.select_bar_region(),
+            Command::RegionVolume => self.select_volume_region(),
+            Command::RegionActions => self.select_action_region(),
+            Command::RegionLyricsOrActions => self.select_lyrics_or_action_region(),
+            Command::RegionLyricsOrList => self.select_lyrics_or_list_region(),
+            Command::ListDown if albums => self.albums.select_down(),
+            Command::ListDown => self.list_down(),
+            Command::ListUp if albums => self.albums.select_up(),
+            Command::ListUp => self.list_up(),
+            Command::ListTop | Command::ListBottom if count.is_some() => {
+                self.goto_index(count.unwrap_or(1) - 1)
+            },
+            Command::ListTop => self.goto_top(),
+            Command::ListBottom => self.goto_bottom(),
+            Command::ListPlaying => self.goto_playing(),
+            Command::ListScreenTop => self.goto_screen_top(count.unwrap_or(1) - 1),
+            Command::ListScreenMiddle => self.goto_screen_middle(),
+            Command::ListScreenBottom => self.goto_screen_bottom(count.unwrap_or(1) - 1),
+            Command::ListCenter => self.center_list(),
+            Command::ListSetMark => if let Some(c) = char { self.set_mark(c) },
+            Command::ListGotoMark => if let Some(c) = char { self.goto_mark(c) },
+            Command::ListSortColumn => {
+                if let Some(index) = char.and_then(|c| c.to_digit(10)) {
+                    self.sort_by_column((index as usize).saturating_sub(1))
+                }
+            },
+            Command::ListHalfPageDown => self.scroll_list_down(),
+            Command::ListHalfPageUp => self.scroll_list_up(),
+            Command::ListPlay => match self.mode {
+                ListMode::Albums => self.play_selected_album(),
+                ListMode::AfterSearch => self.play_after_search(),
+                _ => self.play_selected_music(),
+            },
+            Command::ListEnqueue if albums => self.enqueue_selected_album(),
+            Command::ListEnqueue => self.enqueue_selected_music(),
+            Command::ListPlayNext if albums => self.play_selected_album_next(),
+            Command::ListPlayNext => self.play_selected_music_next(),
+            Command::ListAddToPlaylist => self.append_selected_to_playlist(),
+            Command::ListAlbums => self.browse_albums(),
+            Command::ListReshuffle => self.reshuffle(),
+            Command::ListBack => self.change_list_mode(ListMode::Select),
+            Command::SearchStart => self.change_list_mode(ListMode::Search),
+            Command::SearchAccept => self.play_after_search(),
+            Command::SearchLeave => self.change_list_mode(ListMode::AfterSearch),
+            Command::SearchDeleteChar => self.delete_char_querry(),
+            Command::QueueDown => self.queue.select_down(),
+            Command::QueueUp => self.queue.select_up(),
+            Command::QueuePlay => self.play_selected_queued_music(),
+            Command::QueueRemove => { self.queue.remove_selected(); },
+            Command::QueueClear => self.queue.clear(),
+            Command::QueueMoveDown => self.queue.move_down(),
+            Command::QueueMoveUp => self.queue.move_up(),
+            Command::QueueSave => self.write_queue_to_playlist(),
+            Command::PlaylistsDown => self.playlists.select_down(),
+            Command::PlaylistsUp => self.playlists.select_up(),
+            Command::PlaylistsLoad => self.load_selected_playlist(),
+            Command::PlaylistsRefresh => self.playlists.refresh(),
+            Command::LyricsDown => self.lyrics.scroll_down(),
+            Command::LyricsUp => self.lyrics.scroll_up(),
+            Command::LyricsFollow => self.lyrics.follow(),
+            Command::ActionsNext => self.next_action(),
+            Command::ActionsPrevious => self.previous_action(),
+            Command::ActionsPerform => self.preform_action(),
+            Command::ActionsCycleBack => self.cycle_back(),
+            Command::SeekForward => self.next_5s(),
+            Command::SeekBackward => self.previous_5s(),
+            Command::VolumeUp => self.increase_volume(),
+            Command::VolumeDown => self.decrease_volume(),
+            Command::MessagesDown => self.messages.scroll_down(),
+            Command::MessagesUp => self.messages.scroll_up(),
+            Command::HistoryDown => self.history.select_down(),
+            Command::HistoryUp => self.history.select_up(),
+            Command::HistoryPlay => self.play_from_history(),
+        }
+        false
     }
 }
 
 #[derive(PartialEq, Eq, Debug, Ord, PartialOrd, Clone)]
-#[derive(serde::Serialize, serde::Deserialize, zbus::zvariant::Type)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Music {
     pub title: String,
     pub length: Duration,
     pub path: PathBuf,
     pub artist: String,
     pub genre: String,
+    pub album: String,
+    /// artist of the whole album, the artist of the music if not tagged
+    pub album_artist: String,
+    pub composer: String,
+    /// track number inside its disc
+    pub track: Option<u32>,
+    /// disc number inside its album
+    pub disc: Option<u32>,
+    pub year: Option<u32>,
+    /// in kbps
+    pub bitrate: Option<u32>,
+    /// out of 5, from the `RATING` or popularimeter tag
+    pub rating: Option<u8>,
+    /// creation time of the file since the unix epoch, its modification
+    /// time where unknown
+    pub added: Option<Duration>,
 }
 
 impl Music {
+    /// Music titled _title_ at `/music/<title>.mp3`, a minute long and
+    /// with unknown tags, for the tests to build on
+    #[allow(dead_code)]
+    pub fn test(title: &str) -> Self {
+        Music::new(
+            title.to_string(), PathBuf::from(format!("/music/{}.mp3", title)),
+            Duration::from_secs(60), String::from("Unknown"), String::from("Unknown")
+        )
+    }
+
     pub fn new(
         title: String, path: PathBuf,
         length: Duration, artist: String, genre: String
         ) -> Self {
         Self {
-            title, path, length, artist, genre
+            album_artist: artist.clone(),
+            title, path, length, artist, genre,
+            album: String::from("Unknown"),
+            composer: String::from("Unknown"),
+            track: None,
+            disc: None,
+            year: None,
+            bitrate: None,
+            rating: None,
+            added: None,
         }
     }
 
+    #[allow(dead_code)]
     pub fn default (path: PathBuf) -> Self {
+        Self::new(
+            Music::derive_title_from_path(&path), path, Duration::ZERO,
+            String::from("Unknown"), String::from("Unknown")
+        )
+    }
+
+    /// Builds a [Music] from the tag of its file, missing text fields are `Unknown`
+    fn from_tag(path: PathBuf, length: Duration, tag: Option<&Tag>) -> Self {
+        let Some(tag) = tag else {
+            return Self::new(
+                String::from("Unknown"), path, length,
+                String::from("Unknown"), String::from("Unknown")
+            )
+        };
+        let text = |value: Option<std::borrow::Cow<'_, str>>| {
+            let mut value = value.unwrap_or_default().to_string();
+            value.insert_if_empty("Unknown");
+            value
+        };
+        let artist = text(tag.artist());
+        let mut album_artist = tag.get_string(&ItemKey::AlbumArtist).unwrap_or_default().to_string();
+        album_artist.insert_if_empty(&artist);
         Self {
-            title: Music::derive_title_from_path(&path),
-            path,
-            length: Duration::ZERO,
-            artist: String::from("Unknown"),
-            genre: String::from("Unknown"),
-        }
-    }
-
-    pub fn unchecked_new(path: PathBuf) -> Self {
-        let res = lofty::probe::Probe::open(&path);
-        // can read file
-        match res {
-            // can read properties
-            Ok(probe) => {
-                if let Ok(mut x) = probe.read() {
-                    let properties = x.properties();
-                    let length = properties.duration();
-                    // can read metadata
-                    if let Some(tag) = x.primary_tag_mut() {
-                        let mut title = tag.title().unwrap_or_default().to_string();
-                        let mut artist = tag.artist().unwrap_or_default().to_string();
-                        let mut genre = tag.genre().unwrap_or_default().to_string();
-                        title.insert_if_empty("Unknown");
-                        artist.insert_if_empty("Unknown");
-                        genre.insert_if_empty("Unknown");
-                        return Self {
-                            title, length, path, artist, genre
-                        }
-                        // can't read metadata
-                    }else {
-                        return Self {
-                            path, length,
-                            title: String::from("Unknown"),
-                            artist: String::from("Unknown"),
-                            genre: String::from("Unknown"),
-                        }
-                    }
-                    // can't read propertes
-                }else {
-                    return Self::default(path)
-                }
-            },
-            // invalid file
-            _ => {
-                return Self::default(path)
-            }
+            title: text(tag.title()),
+            genre: text(tag.genre()),
+            album: text(tag.album()),
+            composer: tag.get_string(&ItemKey::Composer).unwrap_or("Unknown").to_string(),
+            track: tag.track(),
+            disc: tag.disk(),
+            year: tag.year(),
+            bitrate: None,
+            rating: Music::rating(tag),
+            added: None,
+            length, path, artist, album_artist,
+        }
+    }
+
+    /// Rating out of 5 of the `RATING` tag, written out of 5 or 100
+    /// depending on the tagger
+    fn rating(tag: &Tag) -> Option<u8> {
+        let value = tag.get_string(&ItemKey::Popularimeter)?.trim().parse::<f64>().ok()?;
+        let stars = match value {
+            v if !(0.0..=100.0).contains(&v) => return None,
+            v if v <= 5.0 => v,
+            v => v / 20.0,
+        };
+        Some(stars.round() as u8)
+    }
+
+    /// Rating out of 5 of the ID3v2 `POPM` frame, which lofty doesn't map
+    /// to a generic tag item
+    fn popularimeter_rating(path: &Path) -> Option<u8> {
+        let is_mpeg = path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("mp3"));
+        if !is_mpeg {
+            return None
+        }
+        let mut file = std::fs::File::open(path).ok()?;
+        let mpeg = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+        let frame = mpeg.id3v2()?.get(&FrameId::Valid("POPM".into()))?;
+        let Id3Frame::Popularimeter(popm) = frame else {
+            return None
         };
+        // the 1-255 scale most players write, 0 being unrated
+        match popm.rating {
+            0 => None,
+            1..=31 => Some(1),
+            32..=95 => Some(2),
+            96..=159 => Some(3),
+            160..=223 => Some(4),
+            _ => Some(5),
+        }
+    }
+
+    /// Album and album artist, telling albums of the same name apart
+    pub fn album_key(&self) -> (String, String) {
+        (self.album.clone(), self.album_artist.clone())
+    }
+
+    /// Orders musics by album, then disc and track number
+    pub fn album_order(&self, other: &Music) -> std::cmp::Ordering {
+        self.album.cmp(&other.album)
+            .then_with(|| self.album_artist.cmp(&other.album_artist))
+            .then_with(|| self.track_order(other))
+    }
+
+    /// Orders musics of the same album by disc and track number,
+    /// untagged ones coming last
+    pub fn track_order(&self, other: &Music) -> std::cmp::Ordering {
+        self.disc.unwrap_or(1).cmp(&other.disc.unwrap_or(1))
+            .then_with(|| self.track.unwrap_or(u32::MAX).cmp(&other.track.unwrap_or(u32::MAX)))
+            .then_with(|| self.title.cmp(&other.title))
     }
 
     pub fn simple_new(path: PathBuf) -> Option<Self> {
@@ -1349,30 +2575,18 @@ impl Music {
             match res {
                 // can read properties
                 Ok(probe) => {
-                    if let Ok(mut x) = probe.read() {
-                        let properties = x.properties();
-                        let length = properties.duration();
-                        // can read metadata
-                        if let Some(tag) = x.primary_tag_mut() {
-                            let mut title = tag.title().unwrap_or_default().to_string();
-                            let mut artist = tag.artist().unwrap_or_default().to_string();
-                            let mut genre = tag.genre().unwrap_or_default().to_string();
-                            title.insert_if_empty("Unknown");
-                            artist.insert_if_empty("Unknown");
-                            genre.insert_if_empty("Unknown");
-
-                            return Some(Self {
-                                title, length, path, artist, genre
-                            })
-                        // can't read metadata/tags
-                        }else {
-                            return Some(Self {
-                                path, length,
-                                title: String::from("Unknown"),
-                                artist: String::from("Unknown"),
-                                genre: String::from("Unknown"),
-                            })
-                        }
+                    if let Ok(x) = probe.read() {
+                        let length = x.properties().duration();
+                        let added = std::fs::metadata(&path).ok()
+                            .and_then(|m| m.created().or_else(|_| m.modified()).ok())
+                            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok());
+                        let music = Self::from_tag(path, length, x.primary_tag());
+                        return Some(Self {
+                            bitrate: x.properties().audio_bitrate(),
+                            rating: music.rating.or_else(|| Self::popularimeter_rating(&music.path)),
+                            added,
+                            ..music
+                        })
                     // can't read propertes
                     }else {
                         return None
@@ -1380,25 +2594,24 @@ impl Music {
                 },
                 // invalid file
                 Err(e) => {
-                    println!("File {:?} is not valid, if you think it's valid, try renaming it", path);
-                    println!("Error: {}", e);
+                    utils::report(&format!("File {:?} is not valid, if you think it's valid, try renaming it: {}", path, e));
                     return None
                 }
             };
         }
-        println!("File {:?} is not valid", path);
-        return None
+        utils::report(&format!("File {:?} is not valid", path));
+        None
     }
 
     fn is_valid(&self) -> Option<&str> {
         if self.path.exists() {
-            return Some(&self.path.to_str()?)
+            return self.path.to_str()
         }
         None
     }
 
     fn derive_title_from_path(path: &PathBuf) -> String {
-        match audiotags::Tag::new().read_from_path(path.to_owned()) {
+        match audiotags::Tag::new().read_from_path(path) {
             Ok(p) => p.title().unwrap_or(path.file_name().unwrap().to_str().unwrap_or("")).to_string(),
             Err(_) => String::from("Default"),
         }
@@ -1412,7 +2625,16 @@ impl Default for Music {
             length: Duration::ZERO,
             path: PathBuf::default(),
             artist: String::new(),
-            genre: String::new()
+            genre: String::new(),
+            album: String::new(),
+            album_artist: String::new(),
+            composer: String::new(),
+            track: None,
+            disc: None,
+            year: None,
+            bitrate: None,
+            rating: None,
+            added: None,
         }
     }
 }
@@ -1428,6 +2650,8 @@ pub struct Musics {
     pub full_que: Vec<Music>,
     /// index of the currently playing song in the full music list
     playing_index: usize,
+    /// query the displayed music list was filtered with
+    pub query: Option<Query>,
 }
 
 impl Musics {
@@ -1438,131 +2662,131 @@ impl Musics {
             selected: 0,
             state: TableState::default().with_selected(0),
             playing_index: 0,
+            query: None,
         }
     }
 
-    /// Returns a [`Music`] reference to the next song in playing quee
-    fn next_song(&self) -> &Music {
+    /// Returns a [`Music`] reference to the next song in playing quee,
+    /// [None] if the list is empty
+    fn next_song(&self) -> Option<&Music> {
         if self.playing_index + 1 < self.que.len() {
-            return &self.que.get(self.playing_index + 1).unwrap()
+            self.que.get(self.playing_index + 1)
         }else {
-            return self.que.get(0).unwrap()
+            self.que.first()
         }
     }
 
-    /// Seturns a [`Music`] reference to the previous song in the palying quee
-    fn previous_song(&self) -> &Music {
+    /// Seturns a [`Music`] reference to the previous song in the palying quee,
+    /// [None] if the list is empty
+    fn previous_song(&self) -> Option<&Music> {
         match self.playing_index.checked_sub(1) {
-            Some(number) => return &self.que.get(number).unwrap(),
-            None => return self.que.get(self.que.len() - 1).unwrap(),
+            Some(number) => self.que.get(number),
+            None => self.que.last(),
         }
     }
 
-    /// Sorts the music list acoording to the Sorting enum
+    /// Sorts the music list acoording to the Sorting enum, shuffle orders
+    /// being left to [Musics::shuffle]
     pub fn sort(&mut self, sorting: Option<Sorting>) {
-        match sorting {
-            Some(o) => {
-                self.full_que.sort_by(|x, y| {
-                    match o {
-                        Sorting::ByTitleAscending => {
-                            if x.title > y.title {
-                                return std::cmp::Ordering::Greater
-                            }else if x.title < y.title {
-                                return std::cmp::Ordering::Less
-                            }
-                            return std::cmp::Ordering::Equal
-                        },
-                        Sorting::ByTitleDescending => {
-                            if x.title < y.title {
-                                return std::cmp::Ordering::Greater
-                            }
-                            else if x.title > y.title {
-                                return std::cmp::Ordering::Less
-                            }
-                            std::cmp::Ordering::Equal
+        if let Some(o) = sorting.filter(|o| !o.is_shuffle()) {
+            self.full_que.sort_by(|x, y| {
+                match o {
+                    Sorting::ByTitleAscending => {
+                        if x.title > y.title {
+                            return std::cmp::Ordering::Greater
+                        }else if x.title < y.title {
+                            return std::cmp::Ordering::Less
+                        }
+                        std::cmp::Ordering::Equal
+                    },
+                    Sorting::ByTitleDescending => {
+                        if x.title < y.title {
+                            return std::cmp::Ordering::Greater
                         }
-                        Sorting::ByDurationAscending => {
-                            if x.length > y.length {
-                                return std::cmp::Ordering::Greater
-                            }
-                            else if x.length < y.length {
-                                return std::cmp::Ordering::Less
-                            }
-                            std::cmp::Ordering::Equal
+                        else if x.title > y.title {
+                            return std::cmp::Ordering::Less
                         }
-                        Sorting::ByDurationDescending => {
-                            if x.length < y.length {
-                                return std::cmp::Ordering::Greater
-                            }
-                            else if x.length > y.length {
-                                return std::cmp::Ordering::Less
-                            }
-                            std::cmp::Ordering::Equal
+                        std::cmp::Ordering::Equal
+                    }
+                    Sorting::ByDurationAscending => {
+                        if x.length > y.length {
+                            return std::cmp::Ordering::Greater
                         }
-                        Sorting::Shuffle => {
-                            let num = rand::thread_rng().gen_range(0..3);
-                            if num == 0 {
-                                return std::cmp::Ordering::Greater
-                            }else if num == 1 {
-                                return std::cmp::Ordering::Less
-                            }
-                            std::cmp::Ordering::Equal
+                        else if x.length < y.length {
+                            return std::cmp::Ordering::Less
                         }
+                        std::cmp::Ordering::Equal
                     }
-                });
-            },
-            None => {},
+                    Sorting::ByDurationDescending => {
+                        if x.length < y.length {
+                            return std::cmp::Ordering::Greater
+                        }
+                        else if x.length > y.length {
+                            return std::cmp::Ordering::Less
+                        }
+                        std::cmp::Ordering::Equal
+                    }
+                    Sorting::ByAlbum => x.album_order(y),
+                    Sorting::RecentlyAdded => y.added.cmp(&x.added),
+                    // needs the play counts, see UI::sort_music_list
+                    Sorting::MostPlayed => std::cmp::Ordering::Equal,
+                    // filtered out above
+                    Sorting::Shuffle | Sorting::AlbumShuffle | Sorting::ArtistShuffle
+                        | Sorting::WeightedShuffle => std::cmp::Ordering::Equal,
+                }
+            });
         }
         self.que = self.full_que.clone();
     }
 
-    /// search the music list wit respect to the search buffer and 
-    pub fn search(&mut self, search_bufr: String) {
-        let n = 20;
-        let paire = search_bufr.splitn(2, ":").collect::<Vec<&str>>();
-        match paire.get(1) {
-        // thus the xxxx: yyyy pattern matches
-            Some(s) => {
-                let criteria = paire.get(0).unwrap();
-                if criteria == &"duration" {
-                    let mut toreturn: Vec<Music> = vec![];
-                    let results = fuzzy_search::fuzzy_search_music_duration_best_n(&s, &self.full_que, n);
-                    for (music, _) in results {
-                        toreturn.push(music);
-                    }
-                    self.que = toreturn;
-                } else if criteria == &"artist" {
-                    let mut toreturn: Vec<Music> = vec![];
-                    let results = fuzzy_search::fuzzy_search_music_artist_best_n(&s, &self.full_que, n);
-                    for (music, _) in results {
-                        toreturn.push(music);
-                    }
-                    self.que = toreturn;
-                }
-            },
-            // normal search querry
-            None => {
-                if search_bufr.is_empty() {
-                    self.que = self.full_que.clone();
-                }else {
-                    let mut toreturn: Vec<Music> = vec![];
-                    let results = fuzzy_search::fuzzy_search_music_titles_best_n(&search_bufr, &self.full_que, n);
-                    for (music, _) in results {
-                        toreturn.push(music);
-                    }
-                    self.que = toreturn;
-                }
-
-            },
+    /// Shuffles the music list in _order_ with _shuffler_, the music at
+    /// _playing_ first, see [Shuffler::shuffle_musics]
+    pub fn shuffle(
+        &mut self, order: Sorting, shuffler: &mut Shuffler, playing: Option<&std::path::Path>,
+        weight: impl Fn(&Music) -> f64,
+    ) {
+        shuffler.shuffle_musics(order, &mut self.full_que, playing, weight);
+        self.que = self.full_que.clone();
+        self.playing_index = playing
+            .and_then(|path| self.full_que.iter().position(|m| m.path == path))
+            .unwrap_or(0);
+    }
+
+    /// Indices in the full music list of the musics matching _query_,
+    /// the best matches first
+    pub fn search_indices(&self, query: &Query) -> Vec<usize> {
+        let mut scored = self.full_que.iter().enumerate()
+            .filter_map(|(i, m)| query.score(m).map(|score| (i, score)))
+            .collect::<Vec<(usize, i64)>>();
+        // stable, so equal scores keep the order of the list
+        scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(i, _)| i).collect()
+    }
+
+    /// Filters the music list with the query in the search buffer, keeping
+    /// at most _limit_ musics, the list is left untouched if the query
+    /// can't be parsed
+    pub fn search(&mut self, search_bufr: String, limit: Option<usize>) -> Result<(), QueryError> {
+        let query = Query::parse(&search_bufr)?;
+        let mut indices = self.search_indices(&query);
+        if let Some(limit) = limit {
+            indices.truncate(limit);
         }
+        self.que = indices.into_iter().map(|i| self.full_que[i].clone()).collect();
+        self.selected = self.selected.min(self.que.len().saturating_sub(1));
+        self.query = Some(query);
+        Ok(())
+    }
+
+    /// Sorts the full and the displayed music lists with _compare_, keeping
+    /// the search
+    pub fn sort_by(&mut self, compare: impl Fn(&Music, &Music) -> std::cmp::Ordering) {
+        self.full_que.sort_by(&compare);
+        self.que.sort_by(&compare);
     }
 
     pub fn reset_search(&mut self) {
-        let mut toreturn: Vec<Music> = vec![];
-        let results = fuzzy_search::fuzzy_search_musics_by_title("", &self.que);
-        for (music, _) in results {
-            toreturn.push(music);
-        }
+        self.query = None;
         self.que = self.full_que.clone();
     }
 
@@ -1606,7 +2830,74 @@ pub mod test {
     pub fn test_search() {
         let config = Config::parse_config("./config.toml");
         let mut musics = config.config.clone().unwrap().extract_music();
-        musics.search("Hilo".to_string());
+        musics.search("Hilo".to_string(), Some(20)).unwrap();
+        assert!(musics.que.len() <= 20);
+        assert!(musics.search("unknown:Hilo".to_string(), None).is_err());
+    }
+
+    #[test]
+    pub fn test_highlight() {
+        let style = Style::new().bold();
+        let line = UI::highlight("Café del Mar", &[2..4, 9..12], style);
+        assert_eq!(line.spans, vec![
+            Span::raw("Ca"), Span::styled("fé", style), Span::raw(" del "), Span::styled("Mar", style),
+        ]);
+        assert_eq!(UI::highlight("abc", &[], style).spans, vec![Span::raw("abc")]);
+    }
+
+    /// Proxy over a connection to a peer of its own, for a [UI] to be
+    /// built without a bus, the peer answering nothing as long as it's kept
+    #[allow(dead_code)]
+    fn peer_proxy() -> (ServerProxy<'static>, zbus::Connection) {
+        let (client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+        async_std::task::block_on(async {
+            let peer = zbus::connection::Builder::unix_stream(server)
+                .server(zbus::Guid::generate()).unwrap()
+                .p2p()
+                .build();
+            let peer = async_std::task::spawn(peer);
+            let connection = zbus::connection::Builder::unix_stream(client).p2p().build().await.unwrap();
+            let proxy = ServerProxy::builder(&connection)
+                .cache_properties(zbus::proxy::CacheProperties::No)
+                .build().await.unwrap();
+            (proxy, peer.await.unwrap())
+        })
+    }
+
+    #[test]
+    pub fn test_library_change_while_searching() {
+        let (proxy, _peer) = peer_proxy();
+        let mut ui = UI::default(proxy);
+        ui.musics(Musics::new(vec![Music::test("alpha"), Music::test("bravo"), Music::test("charlie"), Music::test("delta")]));
+        ui.state.set_playing_music(Music::test("charlie"));
+        ui.change_list_mode(ListMode::Search);
+        for c in "title:a -title:bravo".chars() {
+            ui.register_querry(c);
+        }
+        ui.change_list_mode(ListMode::AfterSearch);
+        ui.library_event(LibraryEvent::Insert(Box::new(Music::test("echo"))));
+        ui.handle_library_events();
+        // the index is the one of the playing music in the filtered list
+        let playing = &ui.music_list.que[ui.music_list.playing_index];
+        assert_eq!(playing.title, "charlie");
+        assert_eq!(ui.music_list.next_song().map(|m| m.title.as_str()), Some("delta"));
+    }
+
+    #[test]
+    pub fn test_repeat_in_context() {
+        let (proxy, _peer) = peer_proxy();
+        let mut ui = UI::default(proxy);
+        ui.musics(Musics::new(vec![Music::test("a"), Music::test("b"), Music::test("c"), Music::test("d")]));
+        ui.region = Region::List;
+        ui.run_command(Command::ListDown, None, None);
+        assert_eq!(ui.music_list.selected, 1);
+        // moving down the list means nothing in the queue
+        ui.region = Region::Queue;
+        ui.run_command(Command::Repeat, None, None);
+        ui.region = Region::List;
+        assert_eq!(ui.music_list.selected, 1);
+        ui.run_command(Command::Repeat, Some(2), None);
+        assert_eq!(ui.music_list.selected, 3);
     }
 
     #[test]
@@ -1616,4 +2907,11 @@ pub mod test {
         musics.sort(config.config.clone().unwrap().sorting);
         assert!(musics.que == musics.full_que);
     }
+
+    #[test]
+    pub fn test_popularimeter_rating() {
+        let music = Music::simple_new(PathBuf::from("./tests/fixtures/rated.mp3")).unwrap();
+        assert_eq!(music.title, "Rated");
+        assert_eq!(music.rating, Some(4));
+    }
 }
@@ -1,5 +1,6 @@
 use std::fs::OpenOptions;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 pub trait StringFeatures {
     /// insert [content] if the requested [String] is empty
@@ -13,12 +14,41 @@ pub trait StringFeatures {
 /// - failed to create or write to the file
 pub fn log(data: &str, filename: &str) -> std::io::Result<std::fs::File>{
     let mut f = OpenOptions::new()
-        .write(true)
         .append(true)
         .create(true)
         .open(filename)?;
     writeln!(f, "{}", data)?;
-    return std::io::Result::Ok(f)
+    std::io::Result::Ok(f)
+}
+
+/// Appends _data_ to the log of the client, in
+/// `$XDG_STATE_HOME/mplayer-client/client.log`, failures are ignored
+/// since the terminal can't be written to while the UI is running
+pub fn report(data: &str) {
+    if let Some(dir) = xdg_dir("XDG_STATE_HOME", ".local/state") {
+        let _ = std::fs::create_dir_all(&dir);
+        let _ = log(data, dir.join("client.log").to_str().unwrap_or_default());
+    }
+}
+
+/// Appends _value_ to the file at _path_ as a line of JSON, creating the
+/// file and its directory if needed
+pub fn append_json(path: &Path, value: &impl serde::Serialize) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(value)?)
+}
+
+/// Directory of this client inside the XDG base directory _var_
+/// (e.g. `XDG_CACHE_HOME`), defaulting to `$HOME/<fallback>` if unset
+pub fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match std::env::var(var) {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").ok()?).join(fallback),
+    };
+    Some(base.join("mplayer-client"))
 }
 
 impl StringFeatures for String {
@@ -53,8 +83,26 @@ pub enum StatusOption {
     CoudntReadMusicData,
 }
 
+impl std::fmt::Display for StatusOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            StatusOption::Ok => "ok",
+            StatusOption::OutOfRange => "out of range",
+            StatusOption::CoudntPreformAction => "couldn't perform the action",
+            StatusOption::CoudntGetSHandler => "couldn't get the stream handler",
+            StatusOption::CoudntSeek => "couldn't seek",
+            StatusOption::CoudntPauseManager | StatusOption::CoudntPauseHandler => "couldn't pause",
+            StatusOption::CoudntResumeManager | StatusOption::CoudntResumeHandler => "couldn't resume",
+            StatusOption::WrongPath => "wrong path",
+            StatusOption::CoudntReadMusicData => "couldn't read the music data",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[allow(dead_code)]
 impl RunStatus {
-    fn new(msg: String, status: StatusOption) -> Self {
+    pub fn new(msg: String, status: StatusOption) -> Self {
         Self {
             error_messge: msg, status_type: status
         }
@@ -64,16 +112,23 @@ impl RunStatus {
         Self::new(String::from(""), StatusOption::Ok)
     }
 
-    fn is_ok(&self) -> bool {
-        match self.status_type {
-            StatusOption::Ok => true,
-            _ => false
+    pub fn is_ok(&self) -> bool {
+        matches!(self.status_type, StatusOption::Ok)
+    }
+
+    /// What went wrong, as told by the server, or what the status means if
+    /// it told nothing
+    pub fn message(&self) -> String {
+        if self.error_messge.trim().is_empty() {
+            self.status_type.to_string()
+        } else {
+            self.error_messge.trim().to_string()
         }
     }
 
     fn handler_errror() -> Self {
-        return RunStatus::new(
-            format!("coudn't get stream handler!"),
+        RunStatus::new(
+            "coudn't get stream handler!".to_string(),
             StatusOption::CoudntGetSHandler
         )
     }
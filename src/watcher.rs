@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use async_std::channel::Sender;
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+use crate::{cache::LibraryEvent, library::LibraryFilter, ui::Music, utils, Config};
+
+/// how long a path has to stay quiet before it gets probed, so files that
+/// are still being written (downloads, copies) are only read once
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// What happened to a watched path, only the last change matters
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    /// the path was created, written to or moved in
+    Updated,
+    /// the path was deleted or moved out
+    Removed,
+}
+
+/// Changes waiting for their path to become quiet
+#[derive(Debug, Default)]
+struct Pending {
+    changes: HashMap<PathBuf, (Change, Instant)>,
+}
+
+impl Pending {
+    /// Records the changes carried by a notify [notify::Event]
+    fn push(&mut self, event: notify::Event, now: Instant) {
+        let change = match event.kind {
+            EventKind::Remove(_) => Change::Removed,
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Change::Removed,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                // paths are [from, to]
+                let mut paths = event.paths.into_iter();
+                if let Some(from) = paths.next() {
+                    self.changes.insert(from, (Change::Removed, now));
+                }
+                for to in paths {
+                    self.changes.insert(to, (Change::Updated, now));
+                }
+                return
+            },
+            EventKind::Create(_) | EventKind::Modify(_) => Change::Updated,
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => Change::Updated,
+            _ => return,
+        };
+        for path in event.paths {
+            self.changes.insert(path, (change, now));
+        }
+    }
+
+    /// Takes out the changes that have been quiet for long enough
+    fn take_quiet(&mut self, now: Instant) -> Vec<(PathBuf, Change)> {
+        let quiet = self.changes.iter()
+            .filter(|(_, (_, at))| now.duration_since(*at) >= QUIET_PERIOD)
+            .map(|(path, (change, _))| (path.clone(), *change))
+            .collect::<Vec<(PathBuf, Change)>>();
+        for (path, _) in quiet.iter() {
+            self.changes.remove(path);
+        }
+        quiet
+    }
+}
+
+/// Turns a quiet change into [LibraryEvent]s
+fn library_events(filter: &LibraryFilter, path: PathBuf, change: Change) -> Vec<LibraryEvent> {
+    match change {
+        // a removed directory takes every music under it along
+        Change::Removed => vec![LibraryEvent::Remove(path)],
+        Change::Updated if path.is_dir() => filter.files_in(&path).into_iter()
+            .filter_map(Music::simple_new)
+            .map(|music| LibraryEvent::Insert(Box::new(music)))
+            .collect(),
+        Change::Updated if !filter.accepts(&path) => vec![],
+        // files that can not be probed (yet) are left alone, a later write
+        // event probes them again
+        Change::Updated => Music::simple_new(path)
+            .map(|music| LibraryEvent::Insert(Box::new(music)))
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Watches the music directories and streams the changes to the library
+/// through _sender_, the returned watcher stops watching once dropped
+pub fn spawn_watcher(config: Config, sender: Sender<LibraryEvent>) -> notify::Result<RecommendedWatcher> {
+    let (events_sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events_sender)?;
+    let filter = LibraryFilter::new(&config);
+    for dir in filter.dirs() {
+        // a missing root (e.g. an unplugged disk) should not stop the others from being watched
+        if let Err(e) = watcher.watch(&dir, RecursiveMode::Recursive) {
+            utils::report(&format!("Can't watch {:?}: {}", dir, e));
+        }
+    }
+    std::thread::spawn(move || {
+        let mut pending = Pending::default();
+        loop {
+            match events.recv_timeout(QUIET_PERIOD / 2) {
+                Ok(Ok(event)) => pending.push(event, Instant::now()),
+                Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            for (path, change) in pending.take_quiet(Instant::now()) {
+                if !filter.contains(&path) {
+                    continue
+                }
+                for event in library_events(&filter, path, change) {
+                    if sender.send_blocking(event).is_err() {
+                        return
+                    }
+                }
+            }
+        }
+    });
+    Ok(watcher)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use notify::event::{CreateKind, RemoveKind};
+
+    #[test]
+    fn test_pending_debounce() {
+        let start = Instant::now();
+        let mut pending = Pending::default();
+        let path = PathBuf::from("/music/a.mp3");
+        pending.push(notify::Event::new(EventKind::Create(CreateKind::File)).add_path(path.clone()), start);
+        pending.push(notify::Event::new(EventKind::Remove(RemoveKind::File)).add_path(path.clone()), start + QUIET_PERIOD / 2);
+        assert!(pending.take_quiet(start + QUIET_PERIOD).is_empty());
+        assert_eq!(pending.take_quiet(start + QUIET_PERIOD * 2), vec![(path, Change::Removed)]);
+        assert!(pending.changes.is_empty());
+    }
+
+    #[test]
+    fn test_pending_rename() {
+        let now = Instant::now();
+        let mut pending = Pending::default();
+        let event = notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/music/old"))
+            .add_path(PathBuf::from("/music/new"));
+        pending.push(event, now);
+        let mut changes = pending.take_quiet(now + QUIET_PERIOD);
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(changes, vec![
+            (PathBuf::from("/music/new"), Change::Updated),
+            (PathBuf::from("/music/old"), Change::Removed),
+        ]);
+    }
+}